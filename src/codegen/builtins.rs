@@ -3,6 +3,9 @@ use inkwell::types::BasicTypeEnum;
 use inkwell::values::BasicValueEnum;
 
 use crate::codegen::Codegen;
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::Span;
+use crate::typeck::Type;
 
 impl<'a, 'ctx> Codegen<'a, 'ctx> {
     pub fn generate_main_fn(&self) {
@@ -15,15 +18,14 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
     // define and add the printf function to the module
     pub fn add_printf(&mut self) {
-        let f64_type = self.context.f64_type();
+        let i32_type = self.context.i32_type();
         let str_type = self
             .context
             .i8_type()
             .ptr_type(inkwell::AddressSpace::Generic);
         let printf_args_type = vec![BasicTypeEnum::PointerType(str_type)];
 
-        // printf needs to return a double to be used in compile_call
-        let printf_type = f64_type.fn_type(printf_args_type.as_slice(), true);
+        let printf_type = i32_type.fn_type(printf_args_type.as_slice(), true);
 
         let printf_fn = self
             .module
@@ -34,24 +36,78 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
 
     pub fn generate_printf_format_string(
         &self,
-        compiled_args: Vec<BasicValueEnum<'ctx>>,
-    ) -> BasicValueEnum<'ctx> {
+        arg_types: &[Type],
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
         let mut format_string = String::from("");
 
-        for arg in compiled_args {
-            let format_arg = match arg {
-                BasicValueEnum::FloatValue(_) => "%f ",
-                BasicValueEnum::PointerValue(_) => "%s ",
-                _ => panic!("Invalid arg type for printf"),
+        for ty in arg_types {
+            let format_arg = match printf_conversion(ty) {
+                Some(conversion) => conversion,
+                None => return Err(unprintable_argument(span)),
             };
 
             format_string.push_str(format_arg);
+            format_string.push(' ');
         }
 
-        BasicValueEnum::PointerValue(
+        Ok(BasicValueEnum::PointerValue(
             self.builder
                 .build_global_string_ptr(format_string.as_str(), "format_string")
                 .as_pointer_value(),
-        )
+        ))
+    }
+
+    /// Converts a value of type `ty` into the form printf expects for
+    /// it. Integers smaller than 32 bits and `f32`s are promoted the
+    /// way C promotes variadic arguments.
+    pub fn printf_arg(&self, arg: BasicValueEnum<'ctx>, ty: &Type) -> BasicValueEnum<'ctx> {
+        match arg {
+            BasicValueEnum::IntValue(val) if *ty == Type::Bool => {
+                let true_str = self.builder.build_global_string_ptr("true", "true_str");
+                let false_str = self.builder.build_global_string_ptr("false", "false_str");
+                self.builder.build_select(
+                    val,
+                    true_str.as_pointer_value(),
+                    false_str.as_pointer_value(),
+                    "bool_str",
+                )
+            }
+            BasicValueEnum::IntValue(val) if ty.bit_width() < Some(32) => {
+                let i32_type = self.context.i32_type();
+                if ty.is_signed() {
+                    self.builder.build_int_s_extend(val, i32_type, "promote")
+                } else {
+                    self.builder.build_int_z_extend(val, i32_type, "promote")
+                }
+                .into()
+            }
+            BasicValueEnum::FloatValue(val) if *ty == Type::F32 => self
+                .builder
+                .build_float_ext(val, self.context.f64_type(), "promote")
+                .into(),
+            _ => arg,
+        }
+    }
+}
+
+/// Error for a call to `print` at `span` with an argument that can't
+/// be printed
+pub fn unprintable_argument(span: Span) -> Diagnostic {
+    Diagnostic::error("E0207", "invalid argument type for `print`")
+        .with_primary(span, "only numbers, booleans and strings can be printed")
+}
+
+/// printf conversion that prints a value of type `ty`, if it can be
+/// printed. Booleans are printed as `true` or `false`.
+pub fn printf_conversion(ty: &Type) -> Option<&'static str> {
+    match ty {
+        Type::I8 | Type::I16 | Type::I32 => Some("%d"),
+        Type::U8 | Type::U16 | Type::U32 => Some("%u"),
+        Type::I64 => Some("%lld"),
+        Type::U64 => Some("%llu"),
+        Type::F32 | Type::F64 => Some("%f"),
+        Type::Bool | Type::Str => Some("%s"),
+        Type::Unit | Type::Fn(..) | Type::Var(_) => None,
     }
 }
@@ -1,77 +1,228 @@
 use std::collections::HashMap;
 
-use crate::tispc_lexer::{Ident, IdentKind, Value};
+use crate::codegen::{DebugInfo, GcFrame, GenericFn};
+use crate::diagnostics::{division_by_zero, Diagnostic, Diagnostics};
+use crate::tispc_lexer::{Ident, IdentKind, Span, Value};
 use crate::tispc_parser::Expr;
+use crate::typeck::{Scopes, Substitution, Type, TypeTable};
 use inkwell::context::Context;
-use inkwell::values::{FloatValue, FunctionValue};
-use inkwell::FloatPredicate;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{FunctionValue, IntValue};
 use inkwell::{builder::Builder, values::BasicValueEnum};
 use inkwell::{module::Module, values::PointerValue};
+use inkwell::{FloatPredicate, IntPredicate};
 
 pub struct Codegen<'a, 'ctx> {
     pub context: &'ctx Context,
     pub module: &'a Module<'ctx>,
     pub builder: &'a Builder<'ctx>,
     pub builtins: &'a mut HashMap<&'a str, FunctionValue<'ctx>>,
-    pub variables: &'a mut HashMap<&'a str, PointerValue<'ctx>>,
+    pub functions: &'a mut HashMap<&'a str, FunctionValue<'ctx>>,
+    pub variables: &'a mut Scopes<'a, PointerValue<'ctx>>,
+    // storage of top-level `let`s that live in globals rather than on
+    // the stack, by the span of the `let`, e.g. in the REPL
+    pub globals: &'a HashMap<Span, PointerValue<'ctx>>,
+    pub types: &'a TypeTable,
+    // name and text of the program, to render the errors that stop it
+    // at runtime the way the compiler renders diagnostics
+    pub filename: &'a str,
+    pub source: &'a str,
+    // definitions of generic functions, compiled once for every set
+    // of types they are called with
+    pub generics: &'a mut HashMap<&'a str, GenericFn<'a>>,
+    // types of the generic function instance being compiled
+    pub substitution: &'a mut Substitution,
+    // DWARF description of the program, if it is compiled with `-g`
+    pub debug_info: Option<DebugInfo<'a, 'ctx>>,
+    // stack slots of the function being generated that the garbage
+    // collector has to look in
+    pub gc_frame: Option<GcFrame<'ctx>>,
 }
 
 impl<'a, 'ctx> Codegen<'a, 'ctx> {
-    pub fn generate_llvm_ir(&mut self, expression_tree: Vec<Expr<'a>>) {
+    /// generate_llvm_ir
+    ///
+    /// Lowers every top-level expression into `main`. An expression
+    /// that fails to compile is reported in `diagnostics` and the
+    /// remaining expressions are still compiled, so that one run
+    /// reports as many errors as possible.
+    pub fn generate_llvm_ir(
+        &mut self,
+        expression_tree: Vec<Expr<'a>>,
+        diagnostics: &mut Diagnostics,
+    ) {
+        let main = self.module.get_function("main").unwrap();
+        let caller_frame = self.begin_gc_frame(main);
+
+        self.compile_program(expression_tree, diagnostics);
+
+        // report the collector's work, then return 0 at the end
+        self.build_gc_exit();
+        self.build_gc_return(&self.context.i32_type().const_int(0, false));
+        self.end_gc_frame(caller_frame);
+
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.builder.finalize();
+        }
+    }
+
+    /// Compiles top-level expressions at the builder's position and
+    /// returns the value of the last one, if it compiled
+    pub fn compile_program(
+        &mut self,
+        expression_tree: Vec<Expr<'a>>,
+        diagnostics: &mut Diagnostics,
+    ) -> Option<BasicValueEnum<'ctx>> {
+        // declare top-level functions up front so they can be
+        // called before their definition
+        for expression in expression_tree.iter() {
+            if let Expr::Defn {
+                name,
+                name_span,
+                params,
+                body,
+                span,
+                ..
+            } = expression
+            {
+                if self.type_of(*span).has_vars() {
+                    self.generics.insert(
+                        name,
+                        GenericFn {
+                            params: params.clone(),
+                            body: body.clone(),
+                            span: *span,
+                        },
+                    );
+                } else if let Err(diagnostic) = self.declare_function(name, *name_span, *span) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+
+        let mut last_value = None;
         for expression in expression_tree {
             match self.compile_expr(expression) {
-                Err(x) => panic!("{}", x),
-                _ => (),
+                Ok(value) => last_value = Some(value),
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    last_value = None;
+                }
             }
         }
 
-        // add return 0 at the end
-        self.builder
-            .build_return(Some(&self.context.i32_type().const_int(0, false)));
+        last_value
+    }
+
+    pub fn compile_expr(
+        &mut self,
+        expression: Expr<'a>,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        // instructions belong to the innermost expression they are
+        // generated for, so the rest of the enclosing expression goes
+        // back to its location
+        let outer_location = self.set_debug_location(expression.span());
+        // new objects are only reachable from the stack once stored in
+        // a variable, so the results of calls are kept in slots of
+        // their own until then
+        let is_temporary = matches!(expression, Expr::Call(..) | Expr::Lambda { .. });
+        let result = self.lower_expr(expression);
+        if let (true, Ok(value)) = (is_temporary, &result) {
+            self.root_temporary(*value);
+        }
+        if let Some(span) = outer_location {
+            self.set_debug_location(span);
+        }
+
+        result
     }
 
-    fn compile_expr(&mut self, expression: Expr<'a>) -> Result<FloatValue<'ctx>, &'a str> {
+    fn lower_expr(&mut self, expression: Expr<'a>) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
         match expression {
-            Expr::Call(_, _) => self.compile_call(expression),
+            Expr::Call(..) => self.compile_call(expression),
 
-            Expr::Constant(Value::Number(val)) => Ok(self.context.f64_type().const_float(val)),
+            Expr::Constant(Value::Integer(val), span) => self.compile_integer(val, span),
 
-            Expr::Builtin(Ident {
-                kind: IdentKind::Variable,
-                value: Some(Value::String(val)),
-            }) => match self.variables.get(val) {
-                Some(var) => Ok(self.builder.build_load(*var, val).into_float_value()),
-                None => Err("Could not find variable"),
-            },
+            Expr::Constant(Value::Float(val), span) => {
+                Ok(self.compile_float(val, &self.type_of(span)))
+            }
 
-            Expr::While { condition, body } => {
-                // TODO: convert condition to llvm format
-                let unboxed_condition = *condition;
-
-                let (predicate, params) = match unboxed_condition {
-                    Expr::Call(boxed_expr, params) => {
-                        let expr = *boxed_expr;
-                        match expr {
-                            Expr::Builtin(Ident {
-                                kind: IdentKind::Greater,
-                                value: _,
-                            }) => (FloatPredicate::OGT, params),
-                            Expr::Builtin(Ident {
-                                kind: IdentKind::Smaller,
-                                value: _,
-                            }) => (FloatPredicate::OLT, params),
-                            _ => panic!("Invalid condition expression"),
-                        }
-                    }
-                    _ => panic!("Invalid condition format"),
-                };
+            Expr::Cast { value, span, .. } => self.compile_cast(*value, span),
 
-                // NOTE: assume there is only one function (main)
-                let current_fn = self.module.get_function("main");
+            Expr::Constant(Value::Boolean(val), _) => {
+                Ok(self.context.bool_type().const_int(val as u64, false).into())
+            }
+
+            Expr::Constant(Value::Str(text), _) => Ok(self.build_string_literal(&text).into()),
+
+            Expr::Builtin(
+                Ident {
+                    kind: IdentKind::Variable,
+                    value: Some(Value::String(val)),
+                },
+                span,
+            ) => match self.variables.get(val) {
+                Some(var) => Ok(self.builder.build_load(*var, val)),
+                None => Err(
+                    Diagnostic::error("E0201", format!("cannot find variable `{}`", val))
+                        .with_primary(span, "not found in this scope"),
+                ),
+            },
+
+            Expr::Let {
+                name, value, span, ..
+            } => self.compile_let(name, value, span),
+
+            Expr::Set {
+                name,
+                name_span,
+                value,
+                ..
+            } => self.compile_set(name, name_span, *value),
+
+            Expr::Do { body, .. } => self.compile_block(body),
+
+            Expr::Print { args, span } => self.compile_print(args, span),
+
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => self.compile_if(*condition, *then_branch, else_branch.map(|e| *e), span),
+
+            Expr::Cond { clauses, span } => self.compile_cond(clauses, span),
+
+            Expr::When {
+                condition,
+                body,
+                negated,
+                ..
+            } => self.compile_when(*condition, body, negated),
+
+            Expr::Defn {
+                name,
+                name_span,
+                params,
+                body,
+                span,
+                ..
+            } => self.compile_defn(name, name_span, params, body, span),
+
+            Expr::Lambda {
+                params, body, span, ..
+            } => self.compile_lambda(params, body, span),
+
+            Expr::While {
+                condition, body, ..
+            } => {
+                let current_fn = self
+                    .builder
+                    .get_insert_block()
+                    .and_then(|block| block.get_parent());
 
                 // Compare Basic Block
-                // loads the indexing variable
-                // performs the comparision and jumps to Loop Basic Block
+                // evaluates the condition and jumps to Loop Basic Block
                 // if true, else goes to After Basic Block
                 let comp_bb = self
                     .context
@@ -79,12 +230,9 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                 self.builder.build_unconditional_branch(comp_bb);
                 self.builder.position_at_end(comp_bb);
 
-                let lhs = self.compile_expr(params[0].clone())?;
-                let rhs = self.compile_expr(params[1].clone())?;
-
-                let cond = self
-                    .builder
-                    .build_float_compare(predicate, lhs, rhs, "while_cond");
+                let cond = self.compile_expr(*condition)?.into_int_value();
+                // the condition may have branched, e.g. `and` or `if`
+                let comp_end = self.builder.get_insert_block().unwrap();
 
                 // Loop Basic Block
                 // adds statements to execute in the body
@@ -95,12 +243,7 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                 self.builder.position_at_end(loop_bb);
 
                 // add body statements
-                for expr in body {
-                    match self.compile_expr(expr) {
-                        Err(x) => return Err(x),
-                        _ => (),
-                    }
-                }
+                self.compile_block(body)?;
 
                 // After Basic Block
                 // basic block for code to run after loop
@@ -111,107 +254,180 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
                 self.builder.build_unconditional_branch(comp_bb);
 
                 // go to end of Compare Basic Block and add condition
-                self.builder.position_at_end(comp_bb);
+                self.builder.position_at_end(comp_end);
                 self.builder
                     .build_conditional_branch(cond, loop_bb, after_bb);
 
                 // go to end of After Basic Block (end of loop)
                 self.builder.position_at_end(after_bb);
 
-                Ok(self.context.f64_type().const_float(0.0))
+                Ok(self.unit_value())
             }
-            _ => Err("Invalid expression. can\'t compile"),
+            _ => Err(Diagnostic::error("E0200", "cannot compile expression")
+                .with_primary(expression.span(), "not supported by the code generator")),
         }
     }
 
-    fn compile_call(&mut self, expr: Expr<'a>) -> Result<FloatValue<'ctx>, &'a str> {
-        let expression = expr.clone();
+    fn compile_let(
+        &mut self,
+        name: &'a str,
+        value: Option<Box<Expr<'a>>>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let var_type = self.type_of(span);
+
+        // `(let name)` starts the variable off at 0
+        let value = match value {
+            Some(value) => self.compile_expr(*value)?,
+            None => self.zero_value(&var_type),
+        };
 
-        match expression {
-            Expr::Call(boxed_func_name, _params) => match *boxed_func_name {
-                Expr::Builtin(_) => self.compile_builtin(expr.clone()),
-                _ => Err("Invalid call expression"),
-            },
-            _ => Err("Internal error: Invalid use of compile_call function"),
+        // every `let` gets its own storage, so it can't clobber the
+        // variable it shadows
+        let val_ptr = match self.globals.get(&span) {
+            Some(global) => *global,
+            None => {
+                let llvm_type = self.llvm_type(&var_type);
+                let val_ptr = self.build_entry_alloca(llvm_type, name);
+                self.add_gc_root(val_ptr, llvm_type);
+                self.declare_debug_variable(name, &var_type, span, val_ptr);
+                val_ptr
+            }
+        };
+        self.builder.build_store(val_ptr, value);
+        self.variables.declare(name, val_ptr);
+
+        Ok(value)
+    }
+
+    fn compile_set(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        value: Expr<'a>,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let value = self.compile_expr(value)?;
+
+        match self.variables.get(name) {
+            Some(val_ptr) => {
+                self.builder.build_store(*val_ptr, value);
+                Ok(value)
+            }
+            None => Err(
+                Diagnostic::error("E0201", format!("cannot find variable `{}`", name))
+                    .with_primary(name_span, "not found in this scope"),
+            ),
         }
     }
 
-    fn compile_builtin(&mut self, expr: Expr<'a>) -> Result<FloatValue<'ctx>, &'a str> {
-        let (func_name_ident, args) = match expr {
-            Expr::Call(func_name_box, params) => match *func_name_box {
-                Expr::Builtin(func_name_ident) => (func_name_ident, params),
-                _ => panic!("Invalid builtin function {:?}", *func_name_box),
-            },
-            _ => panic!("Invalid function call {:?}", expr),
-        };
+    /// Allocates stack space in the entry block of the current
+    /// function, so that a variable declared in a loop doesn't take
+    /// more space on every iteration
+    pub fn build_entry_alloca(&self, ty: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let entry = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .and_then(|function| function.get_first_basic_block())
+            .unwrap();
+
+        let builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry),
+        }
 
-        match func_name_ident {
-            Ident {
-                kind: IdentKind::Print,
-                value: _,
-            } => {
-                let printf = self.builtins.get("printf").unwrap().clone();
+        builder.build_alloca(ty, name)
+    }
 
-                // let mut compiled_args = self.generate_args("print", args.clone());
-                // let format_string = self.generate_printf_format_string(compiled_args.clone());
-                // compiled_args.insert(0, format_string);
+    fn compile_print(
+        &mut self,
+        args: Vec<Expr<'a>>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let mut argsv: Vec<BasicValueEnum<'ctx>> = Vec::new();
+        let mut arg_types: Vec<Type> = Vec::new();
+        for arg in args {
+            arg_types.push(self.type_of(arg.span()));
+            argsv.push(self.compile_expr(arg)?);
+        }
 
-                let mut compiled_args: Vec<FloatValue<'ctx>> = Vec::new();
-                for arg in args {
-                    compiled_args.push(self.compile_expr(arg)?);
-                }
+        if self.targets_wasm() {
+            self.build_host_print(argsv, &arg_types, span)?;
+            return Ok(self.unit_value());
+        }
 
-                let mut argsv: Vec<BasicValueEnum<'ctx>> = compiled_args
-                    .iter()
-                    .by_ref()
-                    .map(|&val| val.into())
-                    .collect();
+        let printf = *self.builtins.get("printf").unwrap();
 
-                let format_string = self.generate_printf_format_string(argsv.clone());
-                argsv.insert(0, format_string);
+        let format_string = self.generate_printf_format_string(&arg_types, span)?;
+        let mut argsv: Vec<BasicValueEnum<'ctx>> = argsv
+            .into_iter()
+            .zip(arg_types.iter())
+            .map(|(arg, ty)| self.printf_arg(arg, ty))
+            .collect();
+        argsv.insert(0, format_string);
 
-                match self
-                    .builder
-                    .build_call(printf, argsv.as_slice(), "printf")
-                    .try_as_basic_value()
-                    .left()
-                {
-                    Some(value) => Ok(value.into_float_value()),
-                    None => Err("Invalid call to print"),
-                }
+        self.builder.build_call(printf, argsv.as_slice(), "printf");
+
+        Ok(self.unit_value())
+    }
+
+    /// Compiles a sequence of expressions in a scope of their own and
+    /// returns the value of the last one, or unit if there are none
+    pub fn compile_block(
+        &mut self,
+        body: Vec<Expr<'a>>,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        self.variables.push();
+
+        let mut result = Ok(self.unit_value());
+        for expr in body {
+            result = self.compile_expr(expr);
+            if result.is_err() {
+                break;
             }
-            Ident {
-                kind: IdentKind::Let,
-                value: _,
-            } => {
-                // panic if let doesn't have exactly 2 params (name and value)
-                if args.len() != 2 {
-                    return Err("Invalid syntax for let");
-                }
+        }
 
-                // get name and value of variable
-                let name = match args[0].clone() {
-                    Expr::Builtin(Ident {
-                        kind: IdentKind::Variable,
-                        value: Some(Value::String(val)),
-                    }) => val,
-                    _ => panic!("Invalid variable name"),
-                };
+        self.variables.pop();
+        result
+    }
 
-                // let value_vec = vec![args[1].clone()];
-                // let value = self.generate_args("let", value_vec)[0].into_float_value();
-                let value = self.compile_expr(args[1].clone());
+    fn compile_call(&mut self, expr: Expr<'a>) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let expression = expr.clone();
 
-                let val_ptr_result = self.variables.get(name);
-                let val_ptr = match val_ptr_result {
-                    None => self.builder.build_alloca(self.context.f64_type(), name),
-                    _ => *val_ptr_result.unwrap(),
-                };
-                self.variables.insert(name, val_ptr);
-                self.builder.build_store(val_ptr, value.unwrap());
+        match expression {
+            Expr::Call(boxed_func_name, params, span) => match *boxed_func_name {
+                // resolved as in `TypeChecker::infer_call`
+                Expr::Builtin(
+                    Ident {
+                        kind: IdentKind::Variable,
+                        value: Some(Value::String(name)),
+                    },
+                    name_span,
+                ) if self.variables.get(name).is_none() => {
+                    self.compile_user_call(name, name_span, params, span)
+                }
+                Expr::Builtin(Ident { kind, .. }, _) if kind != IdentKind::Variable => {
+                    self.compile_builtin(expr.clone())
+                }
+                callee => self.compile_closure_call(callee, params),
+            },
+            _ => unreachable!("compile_call called on a non-call expression"),
+        }
+    }
 
-                value
-            }
+    fn compile_builtin(&mut self, expr: Expr<'a>) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let (func_name_ident, func_name_span, args, span) = match expr {
+            Expr::Call(func_name_box, params, span) => match *func_name_box {
+                Expr::Builtin(func_name_ident, func_name_span) => {
+                    (func_name_ident, func_name_span, params, span)
+                }
+                _ => unreachable!("compile_builtin called on a non-builtin call"),
+            },
+            _ => unreachable!("compile_builtin called on a non-call expression"),
+        };
+
+        match func_name_ident {
             Ident {
                 kind: IdentKind::Plus,
                 value: None,
@@ -227,72 +443,290 @@ impl<'a, 'ctx> Codegen<'a, 'ctx> {
             | Ident {
                 kind: IdentKind::Div,
                 value: None,
+            }
+            | Ident {
+                kind: IdentKind::Rem,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::BitAnd,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::BitOr,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::BitXor,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::Shl,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::Shr,
+                value: None,
             } => {
-                let mut argsv = args.clone();
-
-                let mut result = self.compile_expr(argsv.pop().unwrap())?;
-
-                match func_name_ident {
-                    Ident {
-                        kind: IdentKind::Plus,
-                        value: None,
-                    } => {
-                        for arg in argsv {
-                            result = self.builder.build_float_add(
-                                result,
-                                self.compile_expr(arg)?,
-                                "add",
-                            );
-                        }
+                // fold left from the first operand, so `(- n 1)` is n - 1
+                let signed = self.type_of(span).is_signed();
+                let mut argsv = args.into_iter();
+
+                let mut result = match argsv.next() {
+                    Some(arg) => self.compile_expr(arg)?,
+                    None => {
+                        return Err(Diagnostic::error("E0205", "missing operands")
+                            .with_primary(func_name_span, "expects at least one operand"))
                     }
-                    Ident {
-                        kind: IdentKind::Minus,
-                        value: None,
-                    } => {
-                        for arg in argsv {
-                            result = self.builder.build_float_sub(
-                                result,
-                                self.compile_expr(arg)?,
-                                "add",
-                            );
-                        }
-                    }
-                    Ident {
-                        kind: IdentKind::Mult,
-                        value: None,
-                    } => {
-                        for arg in argsv {
-                            result = self.builder.build_float_mul(
-                                result,
-                                self.compile_expr(arg)?,
-                                "add",
-                            );
-                        }
-                    }
-                    Ident {
-                        kind: IdentKind::Div,
-                        value: None,
-                    } => {
-                        for arg in argsv {
-                            result = self.builder.build_float_div(
-                                result,
-                                self.compile_expr(arg)?,
-                                "add",
-                            );
-                        }
-                    }
-                    _ => return Err("Invalid operation"),
+                };
+
+                for arg in argsv {
+                    let arg_span = arg.span();
+                    let operand = self.compile_expr(arg)?;
+                    result = self.build_arithmetic(
+                        &func_name_ident.kind,
+                        result,
+                        operand,
+                        arg_span,
+                        signed,
+                    );
                 }
 
                 Ok(result)
             }
-            _ => Err("function not defined."),
+            Ident {
+                kind: IdentKind::Greater,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::Smaller,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::GreaterEqual,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::SmallerEqual,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::Equal,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::NotEqual,
+                value: None,
+            } => {
+                // the type checker makes sure there are two operands
+                let operand_type = self.type_of(args[0].span());
+                let signed = !operand_type.is_integer() || operand_type.is_signed();
+                let mut argsv = args.into_iter();
+                let lhs = self.compile_expr(argsv.next().unwrap())?;
+                let rhs = self.compile_expr(argsv.next().unwrap())?;
+
+                Ok(self.build_comparison(&func_name_ident.kind, lhs, rhs, signed))
+            }
+            Ident {
+                kind: IdentKind::And,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::Or,
+                value: None,
+            } => self.compile_logical(&func_name_ident.kind, args),
+            Ident {
+                kind: IdentKind::Not,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::BitNot,
+                value: None,
+            } => {
+                let operand = self
+                    .compile_expr(args.into_iter().next().unwrap())?
+                    .into_int_value();
+
+                // on booleans this is logical not, and on other integers
+                // it flips every bit
+                Ok(self.builder.build_not(operand, "not").into())
+            }
+            Ident {
+                kind: IdentKind::StrConcat,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::StrLen,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::Substr,
+                value: None,
+            }
+            | Ident {
+                kind: IdentKind::StrEqual,
+                value: None,
+            } => self.compile_string_builtin(&func_name_ident.kind, args),
+            _ => Err(Diagnostic::error("E0203", "invalid call expression")
+                .with_primary(func_name_span, "cannot be called")),
+        }
+    }
+
+    /// Applies an arithmetic or bitwise builtin to two operands of the
+    /// same type. `signed` picks the signed or unsigned forms of
+    /// division, remainder and right shift for integers, and `rhs_span`
+    /// is where a zero divisor is reported.
+    fn build_arithmetic(
+        &self,
+        operator: &IdentKind,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        rhs_span: Span,
+        signed: bool,
+    ) -> BasicValueEnum<'ctx> {
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(lhs), BasicValueEnum::IntValue(rhs)) => match operator {
+                IdentKind::Plus => self.builder.build_int_add(lhs, rhs, "add"),
+                IdentKind::Minus => self.builder.build_int_sub(lhs, rhs, "sub"),
+                IdentKind::Mult => self.builder.build_int_mul(lhs, rhs, "mul"),
+                IdentKind::Div | IdentKind::Rem => {
+                    self.build_int_division(operator, lhs, rhs, rhs_span, signed)
+                }
+                IdentKind::BitAnd => self.builder.build_and(lhs, rhs, "and"),
+                IdentKind::BitOr => self.builder.build_or(lhs, rhs, "or"),
+                IdentKind::BitXor => self.builder.build_xor(lhs, rhs, "xor"),
+                IdentKind::Shl => {
+                    let rhs = self.build_shift_amount(rhs);
+                    self.builder.build_left_shift(lhs, rhs, "shl")
+                }
+                IdentKind::Shr => {
+                    let rhs = self.build_shift_amount(rhs);
+                    self.builder.build_right_shift(lhs, rhs, signed, "shr")
+                }
+                _ => unreachable!("{:?} is not an arithmetic builtin", operator),
+            }
+            .into(),
+            (BasicValueEnum::FloatValue(lhs), BasicValueEnum::FloatValue(rhs)) => match operator {
+                IdentKind::Plus => self.builder.build_float_add(lhs, rhs, "add"),
+                IdentKind::Minus => self.builder.build_float_sub(lhs, rhs, "sub"),
+                IdentKind::Mult => self.builder.build_float_mul(lhs, rhs, "mul"),
+                IdentKind::Div => self.builder.build_float_div(lhs, rhs, "div"),
+                _ => unreachable!("{:?} is not an arithmetic builtin", operator),
+            }
+            .into(),
+            _ => unreachable!("operands of different types passed the type checker"),
+        }
+    }
+
+    /// Divides integers, or takes the remainder of their division. LLVM
+    /// leaves dividing by zero undefined, so the program stops with the
+    /// error the interpreter reports instead. It also traps on the
+    /// signed `MIN / -1`, so dividing by -1 wraps around like the other
+    /// operations: the quotient is `0 - lhs`, and the remainder 0.
+    fn build_int_division(
+        &self,
+        operator: &IdentKind,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        rhs_span: Span,
+        signed: bool,
+    ) -> IntValue<'ctx> {
+        let int_type = rhs.get_type();
+        let zero = int_type.const_zero();
+        let is_zero = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rhs, zero, "is_zero");
+        self.build_runtime_check(is_zero, division_by_zero(rhs_span));
+
+        if !signed {
+            return match operator {
+                IdentKind::Div => self.builder.build_int_unsigned_div(lhs, rhs, "div"),
+                _ => self.builder.build_int_unsigned_rem(lhs, rhs, "rem"),
+            };
+        }
+
+        // dividing by 1 instead gives `lhs`, and a remainder of 0
+        let is_minus_one = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            rhs,
+            int_type.const_all_ones(),
+            "is_minus_one",
+        );
+        let divisor = self
+            .builder
+            .build_select(is_minus_one, int_type.const_int(1, false), rhs, "divisor")
+            .into_int_value();
+        match operator {
+            IdentKind::Div => {
+                let quotient = self.builder.build_int_signed_div(lhs, divisor, "div");
+                let negated = self.builder.build_int_sub(zero, lhs, "neg");
+                self.builder
+                    .build_select(is_minus_one, negated, quotient, "div")
+                    .into_int_value()
+            }
+            _ => self.builder.build_int_signed_rem(lhs, divisor, "rem"),
+        }
+    }
+
+    /// Wraps a shift amount around at the width of its type. LLVM gives
+    /// poison for shifts by the width or more, so `(<< 1 64)` shifts by
+    /// 0 instead, and the interpreter does the same.
+    fn build_shift_amount(&self, amount: IntValue<'ctx>) -> IntValue<'ctx> {
+        let int_type = amount.get_type();
+        let mask = int_type.const_int(int_type.get_bit_width() as u64 - 1, false);
+        self.builder.build_and(amount, mask, "shift_amount")
+    }
+
+    /// Compares two operands of the same type. Integers are compared
+    /// as signed unless `signed` is false, and floats as ordered except
+    /// for `!=`, which is true if either operand is NaN.
+    fn build_comparison(
+        &self,
+        operator: &IdentKind,
+        lhs: BasicValueEnum<'ctx>,
+        rhs: BasicValueEnum<'ctx>,
+        signed: bool,
+    ) -> BasicValueEnum<'ctx> {
+        let (float_predicate, int_predicate) = match (operator, signed) {
+            (IdentKind::Greater, true) => (FloatPredicate::OGT, IntPredicate::SGT),
+            (IdentKind::Greater, false) => (FloatPredicate::OGT, IntPredicate::UGT),
+            (IdentKind::Smaller, true) => (FloatPredicate::OLT, IntPredicate::SLT),
+            (IdentKind::Smaller, false) => (FloatPredicate::OLT, IntPredicate::ULT),
+            (IdentKind::GreaterEqual, true) => (FloatPredicate::OGE, IntPredicate::SGE),
+            (IdentKind::GreaterEqual, false) => (FloatPredicate::OGE, IntPredicate::UGE),
+            (IdentKind::SmallerEqual, true) => (FloatPredicate::OLE, IntPredicate::SLE),
+            (IdentKind::SmallerEqual, false) => (FloatPredicate::OLE, IntPredicate::ULE),
+            (IdentKind::Equal, _) => (FloatPredicate::OEQ, IntPredicate::EQ),
+            (IdentKind::NotEqual, _) => (FloatPredicate::UNE, IntPredicate::NE),
+            _ => unreachable!("{:?} is not a comparison builtin", operator),
+        };
+
+        match (lhs, rhs) {
+            (BasicValueEnum::IntValue(lhs), BasicValueEnum::IntValue(rhs)) => self
+                .builder
+                .build_int_compare(int_predicate, lhs, rhs, "cmp")
+                .into(),
+            (BasicValueEnum::FloatValue(lhs), BasicValueEnum::FloatValue(rhs)) => self
+                .builder
+                .build_float_compare(float_predicate, lhs, rhs, "cmp")
+                .into(),
+            _ => unreachable!("operands of different types passed the type checker"),
         }
     }
 
     pub fn init(&mut self, source_filename: &str) {
         self.module.set_source_file_name(source_filename);
         self.generate_main_fn();
-        self.add_printf();
+
+        // there is no C library on the web, so printing goes through
+        // the host and the string functions are part of the module
+        if self.targets_wasm() {
+            self.add_host_imports();
+            self.add_wasm_runtime();
+        } else {
+            self.add_printf();
+            self.add_string_functions();
+        }
+        self.add_gc_runtime();
     }
 }
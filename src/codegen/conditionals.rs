@@ -0,0 +1,233 @@
+use inkwell::basic_block::BasicBlock;
+use inkwell::values::{BasicValue, BasicValueEnum};
+
+use crate::codegen::Codegen;
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::{IdentKind, Span};
+use crate::tispc_parser::{CondClause, Expr};
+use crate::typeck::Type;
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// compile_if
+    ///
+    /// Branches to a `then` or an `else` Basic Block on the value of
+    /// the condition. Both jump to an `after_if` Basic Block, where a
+    /// phi node picks the value of the branch that ran.
+    pub fn compile_if(
+        &mut self,
+        condition: Expr<'a>,
+        then_branch: Expr<'a>,
+        else_branch: Option<Expr<'a>>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let current_fn = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .unwrap();
+
+        let cond = self.compile_expr(condition)?.into_int_value();
+
+        let then_bb = self.context.append_basic_block(current_fn, "then");
+        let else_bb = self.context.append_basic_block(current_fn, "else");
+        self.builder
+            .build_conditional_branch(cond, then_bb, else_bb);
+
+        // the branches may end in a different block than they started
+        // in, e.g. if they contain a loop, so the block to jump to
+        // `after_if` from is read back after compiling them
+        self.builder.position_at_end(then_bb);
+        let then_value = self.compile_block(vec![then_branch])?;
+        let then_end = self.builder.get_insert_block().unwrap();
+
+        self.builder.position_at_end(else_bb);
+        let else_value = match else_branch {
+            Some(else_branch) => self.compile_block(vec![else_branch])?,
+            None => self.unit_value(),
+        };
+        let else_end = self.builder.get_insert_block().unwrap();
+
+        Ok(self.build_merge(
+            span,
+            "after_if",
+            &[(then_value, then_end), (else_value, else_end)],
+        ))
+    }
+
+    /// compile_cond
+    ///
+    /// Tests the clauses in order: each condition branches to the
+    /// clause body if true, and to the test of the next clause
+    /// otherwise. The `else` clause, if any, runs when no condition
+    /// was true.
+    pub fn compile_cond(
+        &mut self,
+        clauses: Vec<CondClause<'a>>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let current_fn = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .unwrap();
+
+        let mut has_else = false;
+        let mut incoming: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)> = Vec::new();
+
+        for clause in clauses {
+            match clause.condition {
+                Some(condition) => {
+                    let cond = self.compile_expr(condition)?.into_int_value();
+
+                    let body_bb = self.context.append_basic_block(current_fn, "cond_body");
+                    let next_bb = self.context.append_basic_block(current_fn, "cond_next");
+                    self.builder
+                        .build_conditional_branch(cond, body_bb, next_bb);
+
+                    self.builder.position_at_end(body_bb);
+                    let value = self.compile_block(clause.body)?;
+                    incoming.push((value, self.builder.get_insert_block().unwrap()));
+
+                    self.builder.position_at_end(next_bb);
+                }
+                None => {
+                    has_else = true;
+                    let value = self.compile_block(clause.body)?;
+                    incoming.push((value, self.builder.get_insert_block().unwrap()));
+                }
+            }
+        }
+
+        // no clause ran
+        if !has_else {
+            incoming.push((self.unit_value(), self.builder.get_insert_block().unwrap()));
+        }
+
+        Ok(self.build_merge(span, "after_cond", &incoming))
+    }
+
+    /// Lowers `(when condition body...)`, and `(unless condition body...)`
+    /// when `negated`, which skip the body if the condition is false
+    /// (or true) and yield no value
+    pub fn compile_when(
+        &mut self,
+        condition: Expr<'a>,
+        body: Vec<Expr<'a>>,
+        negated: bool,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let current_fn = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .unwrap();
+
+        let cond = self.compile_expr(condition)?.into_int_value();
+
+        let body_bb = self.context.append_basic_block(current_fn, "when");
+        let after_bb = self.context.append_basic_block(current_fn, "after_when");
+        if negated {
+            self.builder
+                .build_conditional_branch(cond, after_bb, body_bb);
+        } else {
+            self.builder
+                .build_conditional_branch(cond, body_bb, after_bb);
+        }
+
+        self.builder.position_at_end(body_bb);
+        self.compile_block(body)?;
+        self.builder.build_unconditional_branch(after_bb);
+
+        self.builder.position_at_end(after_bb);
+        Ok(self.unit_value())
+    }
+
+    /// Lowers `and` and `or`, which only evaluate operands until one
+    /// decides the result: `and` stops at the first false operand and
+    /// `or` at the first true one. The result is the value of the last
+    /// operand evaluated.
+    pub fn compile_logical(
+        &mut self,
+        operator: &IdentKind,
+        args: Vec<Expr<'a>>,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let current_fn = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .unwrap();
+
+        let name = match operator {
+            IdentKind::And => "and",
+            _ => "or",
+        };
+        let after_bb = self
+            .context
+            .append_basic_block(current_fn, &format!("after_{}", name));
+
+        let mut incoming: Vec<(BasicValueEnum<'ctx>, BasicBlock<'ctx>)> = Vec::new();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let value = self.compile_expr(arg)?.into_int_value();
+            incoming.push((value.into(), self.builder.get_insert_block().unwrap()));
+
+            if args.peek().is_none() {
+                self.builder.build_unconditional_branch(after_bb);
+                break;
+            }
+
+            let next_bb = self.context.append_basic_block(current_fn, name);
+            match operator {
+                IdentKind::And => self
+                    .builder
+                    .build_conditional_branch(value, next_bb, after_bb),
+                _ => self
+                    .builder
+                    .build_conditional_branch(value, after_bb, next_bb),
+            };
+            self.builder.position_at_end(next_bb);
+        }
+
+        self.builder.position_at_end(after_bb);
+        let phi = self.builder.build_phi(self.context.bool_type(), name);
+        let incoming: Vec<(&dyn BasicValue<'ctx>, BasicBlock<'ctx>)> = incoming
+            .iter()
+            .map(|(value, block)| (value as &dyn BasicValue<'ctx>, *block))
+            .collect();
+        phi.add_incoming(incoming.as_slice());
+
+        Ok(phi.as_basic_value())
+    }
+
+    /// Jumps from the end of every branch in `incoming` to a new Basic
+    /// Block named `name`, and returns the value of the branch that ran
+    fn build_merge(
+        &mut self,
+        span: Span,
+        name: &str,
+        incoming: &[(BasicValueEnum<'ctx>, BasicBlock<'ctx>)],
+    ) -> BasicValueEnum<'ctx> {
+        let current_fn = incoming[0].1.get_parent().unwrap();
+        let after_bb = self.context.append_basic_block(current_fn, name);
+
+        for (_, block) in incoming {
+            self.builder.position_at_end(*block);
+            self.builder.build_unconditional_branch(after_bb);
+        }
+        self.builder.position_at_end(after_bb);
+
+        match self.type_of(span) {
+            // branches without a value don't need a phi node
+            Type::Unit => self.unit_value(),
+            ty => {
+                let phi = self.builder.build_phi(self.llvm_type(&ty), name);
+                let incoming: Vec<(&dyn BasicValue<'ctx>, BasicBlock<'ctx>)> = incoming
+                    .iter()
+                    .map(|(value, block)| (value as &dyn BasicValue<'ctx>, *block))
+                    .collect();
+                phi.add_incoming(incoming.as_slice());
+                phi.as_basic_value()
+            }
+        }
+    }
+}
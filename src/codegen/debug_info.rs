@@ -0,0 +1,348 @@
+use std::env;
+
+use inkwell::debug_info::{
+    debug_metadata_version, AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants,
+    DILocalVariable, DIScope, DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::FlagBehavior;
+use inkwell::values::{FunctionValue, PointerValue};
+use inkwell::AddressSpace;
+
+use crate::codegen::Codegen;
+use crate::tispc_lexer::{LineIndex, Span};
+use crate::tispc_parser::Param;
+use crate::typeck::Type;
+
+// DWARF encodings of basic types
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+const DW_ATE_UNSIGNED: u32 = 0x08;
+
+/// State of the DWARF debug info of a module being generated
+pub struct DebugInfo<'a, 'ctx> {
+    pub builder: DebugInfoBuilder<'ctx>,
+    pub compile_unit: DICompileUnit<'ctx>,
+    pub file: DIFile<'ctx>,
+    pub line_index: LineIndex<'a>,
+    // subprogram of the function being generated
+    pub scope: DIScope<'ctx>,
+    // the expression whose instructions are being generated
+    pub location: Option<Span>,
+    pub is_optimized: bool,
+    // size of pointers on the target, in bits
+    pub pointer_bits: u64,
+}
+
+/// Where the debug info was before generating the body of a function,
+/// to go back to afterwards
+pub struct CallerDebugInfo<'ctx> {
+    scope: DIScope<'ctx>,
+    location: Option<Span>,
+}
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// add_debug_info
+    ///
+    /// Makes the module describe the program in `source` with DWARF
+    /// debug info, so that debuggers can map its machine code back to
+    /// lines of the program and show its variables. Must be called
+    /// after `init`, while the builder is still in `main`.
+    pub fn add_debug_info(
+        &mut self,
+        filename: &str,
+        source: &'a str,
+        is_optimized: bool,
+        pointer_bits: u64,
+    ) {
+        // without this flag LLVM throws the debug info away
+        self.module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            self.context
+                .i32_type()
+                .const_int(debug_metadata_version() as u64, false),
+        );
+
+        // debuggers look the file up relative to the directory the
+        // compiler ran in
+        let directory = env::current_dir().unwrap_or_default();
+        let (builder, compile_unit) = self.module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            filename,
+            &directory.to_string_lossy(),
+            "tispc",
+            is_optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+        );
+        let file = compile_unit.get_file();
+
+        self.debug_info = Some(DebugInfo {
+            builder,
+            compile_unit,
+            file,
+            line_index: LineIndex::new(source),
+            scope: compile_unit.as_debug_info_scope(),
+            location: None,
+            is_optimized,
+            pointer_bits,
+        });
+
+        // the top-level expressions make up `main`
+        let main = self.module.get_function("main").unwrap();
+        let main_type = Type::Fn(vec![Type::I32], Box::new(Type::I32));
+        self.add_subprogram(main, &main_type, Span::new(0, 0));
+    }
+
+    /// Describes `function`, defined at `span` with the checked type
+    /// `ty`, and makes it the scope of the instructions generated from
+    /// now on. Returns where the debug info was before, for
+    /// `leave_subprogram`.
+    pub fn add_subprogram(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        ty: &Type,
+        span: Span,
+    ) -> Option<CallerDebugInfo<'ctx>> {
+        let debug_info = self.debug_info.as_ref()?;
+        let (params, ret) = match ty {
+            Type::Fn(params, ret) => (params, ret),
+            _ => unreachable!("{} is not a function type", ty),
+        };
+
+        let param_types: Vec<DIType<'ctx>> =
+            params.iter().map(|param| self.di_type(param)).collect();
+        let subroutine_type = debug_info.builder.create_subroutine_type(
+            debug_info.file,
+            Some(self.di_type(ret)),
+            &param_types,
+            DIFlags::ZERO,
+        );
+
+        let debug_info = self.debug_info.as_mut().unwrap();
+        let line = debug_info.line_index.line_col(span.start).line as u32;
+        let subprogram = debug_info.builder.create_function(
+            debug_info.compile_unit.as_debug_info_scope(),
+            &function.get_name().to_string_lossy(),
+            None,
+            debug_info.file,
+            line,
+            subroutine_type,
+            true,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            debug_info.is_optimized,
+        );
+        function.set_subprogram(subprogram);
+
+        let caller = CallerDebugInfo {
+            scope: debug_info.scope,
+            location: debug_info.location,
+        };
+        debug_info.scope = subprogram.as_debug_info_scope();
+        self.set_debug_location(span);
+
+        Some(caller)
+    }
+
+    /// Goes back to generating the function whose generation was
+    /// interrupted by `add_subprogram`
+    pub fn leave_subprogram(&mut self, caller: Option<CallerDebugInfo<'ctx>>) {
+        let caller = match caller {
+            Some(caller) => caller,
+            None => return,
+        };
+
+        if let Some(debug_info) = self.debug_info.as_mut() {
+            debug_info.scope = caller.scope;
+        }
+        if let Some(location) = caller.location {
+            self.set_debug_location(location);
+        }
+    }
+
+    /// Attributes the instructions generated from now on to the source
+    /// code at `span`, and returns the location they were attributed
+    /// to before
+    pub fn set_debug_location(&mut self, span: Span) -> Option<Span> {
+        let debug_info = self.debug_info.as_mut()?;
+
+        let start = debug_info.line_index.line_col(span.start);
+        let location = debug_info.builder.create_debug_location(
+            self.context,
+            start.line as u32,
+            start.col as u32,
+            debug_info.scope,
+            None,
+        );
+        self.builder
+            .set_current_debug_location(self.context, location);
+
+        debug_info.location.replace(span)
+    }
+
+    /// Describes the parameter at `index` of the function being
+    /// generated, whose value has been copied to `storage`
+    pub fn declare_debug_param(
+        &self,
+        param: &Param<'a>,
+        index: usize,
+        ty: &Type,
+        storage: PointerValue<'ctx>,
+    ) {
+        let debug_info = match &self.debug_info {
+            Some(debug_info) => debug_info,
+            None => return,
+        };
+
+        let ty = self.di_type(ty);
+        let line = debug_info.line_index.line_col(param.span.start).line as u32;
+        // DWARF numbers arguments from 1
+        let variable = debug_info.builder.create_parameter_variable(
+            debug_info.scope,
+            param.name,
+            index as u32 + 1,
+            debug_info.file,
+            line,
+            ty,
+            true,
+            DIFlags::ZERO,
+        );
+        self.insert_debug_declare(storage, variable, param.span);
+    }
+
+    /// Describes a variable declared with `let` at `span`, which lives
+    /// in `storage`
+    pub fn declare_debug_variable(
+        &self,
+        name: &str,
+        ty: &Type,
+        span: Span,
+        storage: PointerValue<'ctx>,
+    ) {
+        let debug_info = match &self.debug_info {
+            Some(debug_info) => debug_info,
+            None => return,
+        };
+
+        let ty = self.di_type(ty);
+        let line = debug_info.line_index.line_col(span.start).line as u32;
+        let variable = debug_info.builder.create_auto_variable(
+            debug_info.scope,
+            name,
+            debug_info.file,
+            line,
+            ty,
+            true,
+            DIFlags::ZERO,
+            0,
+        );
+        self.insert_debug_declare(storage, variable, span);
+    }
+
+    fn insert_debug_declare(
+        &self,
+        storage: PointerValue<'ctx>,
+        variable: DILocalVariable<'ctx>,
+        span: Span,
+    ) {
+        let debug_info = self.debug_info.as_ref().unwrap();
+        let start = debug_info.line_index.line_col(span.start);
+        let location = debug_info.builder.create_debug_location(
+            self.context,
+            start.line as u32,
+            start.col as u32,
+            debug_info.scope,
+            None,
+        );
+
+        let block = self.builder.get_insert_block().unwrap();
+        debug_info
+            .builder
+            .insert_declare_at_end(storage, Some(variable), None, location, block);
+    }
+
+    /// DWARF description of a checked type
+    fn di_type(&self, ty: &Type) -> DIType<'ctx> {
+        let debug_info = self.debug_info.as_ref().unwrap();
+        let basic_type = |name: &str, size_in_bits: u64, encoding: u32| {
+            debug_info
+                .builder
+                .create_basic_type(name, size_in_bits, encoding, DIFlags::ZERO)
+                .expect("basic types have a name and a size")
+                .as_type()
+        };
+
+        let name = ty.to_string();
+        match ty {
+            Type::I8 | Type::I16 | Type::I32 | Type::I64 => {
+                basic_type(&name, ty.bit_width().unwrap() as u64, DW_ATE_SIGNED)
+            }
+            Type::U8 | Type::U16 | Type::U32 | Type::U64 => {
+                basic_type(&name, ty.bit_width().unwrap() as u64, DW_ATE_UNSIGNED)
+            }
+            Type::F32 => basic_type(&name, 32, DW_ATE_FLOAT),
+            Type::F64 => basic_type(&name, 64, DW_ATE_FLOAT),
+            Type::Bool => basic_type(&name, 8, DW_ATE_BOOLEAN),
+            // strings are C strings
+            Type::Str => {
+                let char_type = basic_type("char", 8, DW_ATE_SIGNED_CHAR);
+                debug_info
+                    .builder
+                    .create_pointer_type(
+                        &name,
+                        char_type,
+                        debug_info.pointer_bits,
+                        0,
+                        AddressSpace::Generic,
+                    )
+                    .as_type()
+            }
+            Type::Unit => debug_info
+                .builder
+                .create_struct_type(
+                    debug_info.compile_unit.as_debug_info_scope(),
+                    &name,
+                    debug_info.file,
+                    0,
+                    0,
+                    0,
+                    DIFlags::ZERO,
+                    None,
+                    &[],
+                    0,
+                    None,
+                    &name,
+                )
+                .as_type(),
+            // closures are described as an opaque pair of pointers
+            Type::Fn(..) => debug_info
+                .builder
+                .create_struct_type(
+                    debug_info.compile_unit.as_debug_info_scope(),
+                    &name,
+                    debug_info.file,
+                    0,
+                    2 * debug_info.pointer_bits,
+                    0,
+                    DIFlags::ZERO,
+                    None,
+                    &[],
+                    0,
+                    None,
+                    &name,
+                )
+                .as_type(),
+            Type::Var(_) => unreachable!("generic functions are compiled per instance"),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use inkwell::module::Linkage;
+use inkwell::types::FunctionType;
+use inkwell::values::{FunctionValue, IntValue};
+
+use crate::codegen::Codegen;
+use crate::diagnostics::{render_diagnostics, Diagnostic, Diagnostics};
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// build_runtime_check
+    ///
+    /// Stops the program with `diagnostic` if `failed` is true when it
+    /// runs. The diagnostic is rendered now, the way the compiler and
+    /// the interpreter print it, and the program prints it to stderr
+    /// and exits with code 1. WebAssembly programs can't exit, so
+    /// they print it through the host and trap instead. The builder is
+    /// left where the program goes on if the check passes.
+    pub fn build_runtime_check(&self, failed: IntValue<'ctx>, diagnostic: Diagnostic) {
+        let current_fn = self
+            .builder
+            .get_insert_block()
+            .and_then(|block| block.get_parent())
+            .unwrap();
+        let error_bb = self.context.append_basic_block(current_fn, "runtime_error");
+        let ok_bb = self.context.append_basic_block(current_fn, "checked");
+        self.builder
+            .build_conditional_branch(failed, error_bb, ok_bb);
+
+        self.builder.position_at_end(error_bb);
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(diagnostic);
+        let message = render_diagnostics(&diagnostics, self.filename, self.source);
+        let message = self
+            .builder
+            .build_global_string_ptr(&message, "runtime_error")
+            .as_pointer_value();
+
+        if self.targets_wasm() {
+            let print_str = *self.builtins.get("print_str").unwrap();
+            self.builder.build_call(print_str, &[message.into()], "");
+            let trap =
+                self.declare_external("llvm.trap", self.context.void_type().fn_type(&[], false));
+            self.builder.build_call(trap, &[], "");
+        } else {
+            let i32_type = self.context.i32_type();
+            let dprintf = *self.builtins.get("dprintf").unwrap();
+            let stderr = i32_type.const_int(2, false);
+            let format = self
+                .builder
+                .build_global_string_ptr("%s", "runtime_error_format")
+                .as_pointer_value();
+            self.builder.build_call(
+                dprintf,
+                &[stderr.into(), format.into(), message.into()],
+                "dprintf",
+            );
+
+            // `exit` flushes what the program printed to stdout
+            let exit = self.declare_external(
+                "exit",
+                self.context.void_type().fn_type(&[i32_type.into()], false),
+            );
+            self.builder
+                .build_call(exit, &[i32_type.const_int(1, false).into()], "");
+        }
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(ok_bb);
+    }
+
+    /// The external function `name` of the module, declared the first
+    /// time it's asked for
+    fn declare_external(&self, name: &str, fn_type: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+        self.module.get_function(name).unwrap_or_else(|| {
+            self.module
+                .add_function(name, fn_type, Some(Linkage::External))
+        })
+    }
+}
@@ -0,0 +1,259 @@
+use std::mem;
+
+use inkwell::values::{BasicValueEnum, FunctionValue};
+
+use crate::codegen::{Codegen, Environment};
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::Span;
+use crate::tispc_parser::{Expr, Param};
+use crate::typeck::{Substitution, Type};
+
+/// Definition of a generic function, kept until the function is
+/// called and the types to compile it for are known
+#[derive(Debug, Clone)]
+pub struct GenericFn<'a> {
+    pub params: Vec<Param<'a>>,
+    pub body: Vec<Expr<'a>>,
+    pub span: Span,
+}
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// Adds the prototype of a user-defined function to the module,
+    /// or returns the existing one if it was already declared
+    pub fn declare_function(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        defn_span: Span,
+    ) -> Result<FunctionValue<'ctx>, Diagnostic> {
+        if let Some(function) = self.functions.get(name) {
+            return Ok(*function);
+        }
+
+        // don't let user functions replace main or printf
+        if self.module.get_function(name).is_some() {
+            return Err(
+                Diagnostic::error("E0209", format!("cannot define function `{}`", name))
+                    .with_primary(name_span, "this name is reserved by the compiler"),
+            );
+        }
+
+        let fn_type = self.llvm_fn_type(&self.type_of(defn_span));
+
+        let function = self.module.add_function(name, fn_type, None);
+        self.functions.insert(name, function);
+
+        Ok(function)
+    }
+
+    /// compile_defn
+    ///
+    /// Generates the body of a user-defined function. Every function
+    /// gets its own variable scope holding only its parameters, and
+    /// returns the value of the last expression in its body.
+    ///
+    /// Generic functions are only recorded here; each instance is
+    /// compiled when it is first called.
+    pub fn compile_defn(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        params: Vec<Param<'a>>,
+        body: Vec<Expr<'a>>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let defined_twice = || {
+            Diagnostic::error("E0210", format!("function `{}` is defined twice", name))
+                .with_primary(name_span, "redefined here")
+        };
+
+        if self.type_of(span).has_vars() {
+            let redefined = match self.generics.get(name) {
+                Some(generic) => generic.span != span,
+                None => self.functions.contains_key(name),
+            };
+            if redefined {
+                return Err(defined_twice());
+            }
+
+            self.generics.insert(name, GenericFn { params, body, span });
+            return Ok(self.unit_value());
+        }
+
+        let function = self.declare_function(name, name_span, span)?;
+
+        if function.count_basic_blocks() > 0 || self.generics.contains_key(name) {
+            return Err(defined_twice());
+        }
+
+        self.define_function(function, None, params, body, &self.type_of(span), span)?;
+        Ok(self.unit_value())
+    }
+
+    /// Compiles the instance of generic function `name` called by the
+    /// call at `call_span`, unless it has been compiled already.
+    /// Instances are named after the types they are compiled for,
+    /// e.g. `id<i64>`.
+    fn instantiate_generic(
+        &mut self,
+        name: &'a str,
+        call_span: Span,
+    ) -> Result<FunctionValue<'ctx>, Diagnostic> {
+        let generic = self.generics[name].clone();
+        let generic_type = self
+            .types
+            .get(generic.span)
+            .cloned()
+            .expect("codegen ran on a function that was not type checked");
+        let instance = self.instance_of(call_span);
+
+        let mut substitution = Substitution::new();
+        generic_type.bind_vars(&instance, &mut substitution);
+
+        let type_args: Vec<String> = generic_type
+            .vars()
+            .iter()
+            .map(|var| substitution[var].to_string())
+            .collect();
+        let instance_name = format!("{}<{}>", name, type_args.join(", "));
+
+        if let Some(function) = self.module.get_function(&instance_name) {
+            return Ok(function);
+        }
+
+        let function = self
+            .module
+            .add_function(&instance_name, self.llvm_fn_type(&instance), None);
+
+        let caller_substitution = mem::replace(&mut *self.substitution, substitution);
+        let result = self.define_function(
+            function,
+            None,
+            generic.params,
+            generic.body,
+            &instance,
+            generic.span,
+        );
+        *self.substitution = caller_substitution;
+
+        result?;
+        Ok(function)
+    }
+
+    /// Generates the body of `function`, of type `ty` and defined at
+    /// `span`. The body is compiled in a fresh scope and with a frame
+    /// on the shadow stack of its own, and the builder returns to the
+    /// caller's block afterwards, even if the body fails to compile.
+    /// The function of a `fn` also gets `env`.
+    pub fn define_function(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        env: Option<&Environment<'a, 'ctx>>,
+        params: Vec<Param<'a>>,
+        body: Vec<Expr<'a>>,
+        ty: &Type,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        let caller_block = self.builder.get_insert_block();
+        let caller_variables = mem::take(&mut *self.variables);
+        let caller_debug_info = self.add_subprogram(function, ty, span);
+
+        self.context.append_basic_block(function, "entry");
+        let caller_frame = self.begin_gc_frame(function);
+
+        let result = self.compile_function_body(function, env, params, body, ty);
+
+        self.end_gc_frame(caller_frame);
+        *self.variables = caller_variables;
+        self.leave_subprogram(caller_debug_info);
+        if let Some(block) = caller_block {
+            self.builder.position_at_end(block);
+        }
+
+        result
+    }
+
+    fn compile_function_body(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        env: Option<&Environment<'a, 'ctx>>,
+        params: Vec<Param<'a>>,
+        body: Vec<Expr<'a>>,
+        ty: &Type,
+    ) -> Result<(), Diagnostic> {
+        let param_types = match ty {
+            Type::Fn(param_types, _) => param_types,
+            _ => unreachable!("{} is not a function type", ty),
+        };
+
+        let mut values = function.get_param_iter();
+        if let Some(env) = env {
+            let env_ptr = values.next().unwrap().into_pointer_value();
+            self.load_environment(env, env_ptr);
+        }
+
+        // copy parameters into allocas so they behave like `let` variables
+        let first_param = function.count_params() as usize - params.len();
+        for (index, (param, value)) in params.iter().zip(values).enumerate() {
+            let ptr = self.build_entry_alloca(value.get_type(), param.name);
+            self.add_gc_root(ptr, value.get_type());
+            self.builder.build_store(ptr, value);
+            self.declare_debug_param(param, first_param + index, &param_types[index], ptr);
+            self.variables.declare(param.name, ptr);
+        }
+
+        let return_value = self.compile_block(body)?;
+        self.build_gc_return(&return_value);
+        Ok(())
+    }
+
+    /// Calls a function defined with `defn`
+    pub fn compile_user_call(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        args: Vec<Expr<'a>>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let function = match self.functions.get(name) {
+            Some(function) => *function,
+            None if self.generics.contains_key(name) => self.instantiate_generic(name, span)?,
+            None => {
+                return Err(Diagnostic::error(
+                    "E0206",
+                    format!("function `{}` is not defined", name),
+                )
+                .with_primary(name_span, "not found in this scope"))
+            }
+        };
+
+        let param_count = function.count_params() as usize;
+        if args.len() != param_count {
+            return Err(Diagnostic::error(
+                "E0208",
+                format!(
+                    "function `{}` takes {} arguments but {} were given",
+                    name,
+                    param_count,
+                    args.len()
+                ),
+            )
+            .with_primary(span, format!("expected {} arguments", param_count)));
+        }
+
+        let mut argsv: Vec<BasicValueEnum<'ctx>> = Vec::new();
+        for arg in args {
+            argsv.push(self.compile_expr(arg)?);
+        }
+
+        match self
+            .builder
+            .build_call(function, argsv.as_slice(), name)
+            .try_as_basic_value()
+            .left()
+        {
+            Some(value) => Ok(value),
+            None => unreachable!("user functions always return a value"),
+        }
+    }
+}
@@ -0,0 +1,850 @@
+use std::convert::TryFrom;
+use std::mem;
+
+use inkwell::basic_block::BasicBlock;
+use inkwell::builder::Builder;
+use inkwell::module::Linkage;
+use inkwell::types::{BasicTypeEnum, FunctionType, StructType};
+use inkwell::values::{
+    BasicValue, BasicValueEnum, CallableValue, FunctionValue, GlobalValue, InstructionValue,
+    IntValue, PointerValue,
+};
+use inkwell::{AddressSpace, IntPredicate};
+
+use crate::codegen::Codegen;
+
+/// Bytes a program allocates before its first collection. After
+/// that, it collects whenever the heap has doubled since the last one.
+pub const GC_INITIAL_THRESHOLD: u64 = 256 * 1024;
+
+/// Environment variable that makes a program print the statistics of
+/// its garbage collector when it exits, whatever its value
+pub const GC_STATS_VAR: &str = "TISP_GC_STATS";
+
+// fields of the header in front of every object
+const HEADER_NEXT: u32 = 0;
+const HEADER_TRACE: u32 = 1;
+const HEADER_SIZE: u32 = 2;
+const HEADER_MARK: u32 = 3;
+
+// values of the mark field; static objects are part of the module,
+// like string literals, and are never marked or freed
+const UNMARKED: u64 = 0;
+const MARKED: u64 = 1;
+const STATIC: u64 = 2;
+
+/// The function being generated, as far as the collector is concerned:
+/// the stack slots that can hold heap objects, which become its frame
+/// on the shadow stack, and the returns that have to pop the frame
+pub struct GcFrame<'ctx> {
+    entry: BasicBlock<'ctx>,
+    body: BasicBlock<'ctx>,
+    roots: Vec<(PointerValue<'ctx>, BasicTypeEnum<'ctx>)>,
+    returns: Vec<InstructionValue<'ctx>>,
+}
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// add_gc_runtime
+    ///
+    /// Defines the garbage collector in the module. Strings and closure
+    /// environments are allocated with `gc_alloc`, behind a header that
+    /// links every object into a list and points to a function marking
+    /// the objects it refers to. When the heap grows past a threshold,
+    /// `gc_collect` marks everything reachable from the shadow stack, a
+    /// linked list of frames holding the addresses of the stack slots
+    /// of every running function, and frees the rest.
+    ///
+    /// Must be called after the C library functions (or the WebAssembly
+    /// runtime) are added, since the collector is built on `malloc`.
+    pub fn add_gc_runtime(&mut self) {
+        let current_block = self.builder.get_insert_block();
+
+        let ptr_type = self.opaque_ptr_type();
+        let i64_type = self.context.i64_type();
+        let globals: [(&str, BasicValueEnum<'ctx>); 7] = [
+            // headers of every object, newest first
+            ("objects", ptr_type.const_null().into()),
+            // the frame of the innermost running function
+            ("roots", ptr_type.const_null().into()),
+            ("heap_size", i64_type.const_zero().into()),
+            (
+                "threshold",
+                i64_type.const_int(GC_INITIAL_THRESHOLD, false).into(),
+            ),
+            ("collections", i64_type.const_zero().into()),
+            ("freed", i64_type.const_zero().into()),
+            // set by the host of a WebAssembly module through `gc_stats`
+            ("stats", self.context.bool_type().const_zero().into()),
+        ];
+        for (name, initial) in globals.iter() {
+            let global =
+                self.module
+                    .add_global(initial.get_type(), None, &format!("__tisp_gc_{}", name));
+            global.set_linkage(Linkage::Internal);
+            global.set_initializer(initial);
+        }
+
+        if !self.targets_wasm() {
+            let i32_type = self.context.i32_type();
+            let ptr_arg = BasicTypeEnum::PointerType(ptr_type);
+            let functions = [
+                ("free", self.context.void_type().fn_type(&[ptr_arg], false)),
+                // prints to a file descriptor, so the statistics go to
+                // stderr without naming the C library's `stderr`
+                (
+                    "dprintf",
+                    i32_type.fn_type(&[i32_type.into(), ptr_arg], true),
+                ),
+                ("getenv", ptr_type.fn_type(&[ptr_arg], false)),
+            ];
+            for (name, fn_type) in functions.iter() {
+                let function = self
+                    .module
+                    .add_function(name, *fn_type, Some(Linkage::External));
+                self.builtins.insert(name, function);
+            }
+        }
+
+        let mark = self.define_gc_mark();
+        let collect = self.define_gc_collect(mark);
+        let alloc = self.define_gc_alloc(collect);
+        let exit = self.define_gc_exit();
+        if self.targets_wasm() {
+            self.define_gc_stats_export();
+        }
+        self.builtins.insert("gc_mark", mark);
+        self.builtins.insert("gc_collect", collect);
+        self.builtins.insert("gc_alloc", alloc);
+        self.builtins.insert("gc_exit", exit);
+
+        if let Some(block) = current_block {
+            self.builder.position_at_end(block);
+        }
+    }
+
+    /// Makes the collector never run. The REPL keeps top-level
+    /// variables in globals, which aren't on the shadow stack.
+    pub fn disable_gc_collections(&self) {
+        self.gc_global("threshold")
+            .set_initializer(&self.context.i64_type().const_all_ones());
+    }
+
+    /// Allocates an object of `size` bytes on the heap. `trace` marks
+    /// the objects it refers to, if it can refer to any.
+    pub fn build_gc_alloc(
+        &self,
+        size: IntValue<'ctx>,
+        trace: Option<FunctionValue<'ctx>>,
+        name: &str,
+    ) -> PointerValue<'ctx> {
+        let gc_alloc = *self.builtins.get("gc_alloc").unwrap();
+        let trace = match trace {
+            Some(trace) => self.builder.build_pointer_cast(
+                trace.as_global_value().as_pointer_value(),
+                self.opaque_ptr_type(),
+                "trace",
+            ),
+            None => self.opaque_ptr_type().const_null(),
+        };
+
+        self.builder
+            .build_call(gc_alloc, &[size.into(), trace.into()], name)
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value()
+    }
+
+    /// Stores `payload` in a constant global with a header, so that it
+    /// can be used wherever a heap object can, and returns a pointer to
+    /// the payload
+    pub fn build_static_object(
+        &self,
+        payload: BasicValueEnum<'ctx>,
+        name: &str,
+    ) -> PointerValue<'ctx> {
+        let ptr_type = self.opaque_ptr_type();
+        let i64_type = self.context.i64_type();
+        // the size is only read when an object is freed
+        let header = self.context.const_struct(
+            &[
+                ptr_type.const_null().into(),
+                ptr_type.const_null().into(),
+                i64_type.const_zero().into(),
+                i64_type.const_int(STATIC, false).into(),
+            ],
+            false,
+        );
+        let object = self.context.const_struct(&[header.into(), payload], false);
+
+        let global = self.module.add_global(object.get_type(), None, name);
+        global.set_linkage(Linkage::Private);
+        global.set_constant(true);
+        global.set_initializer(&object);
+
+        let payload = self
+            .builder
+            .build_struct_gep(global.as_pointer_value(), 1, name)
+            .unwrap();
+        self.builder.build_pointer_cast(payload, ptr_type, name)
+    }
+
+    /// Defines a function that marks the objects the fields of a struct
+    /// of type `ty` point to, or returns `None` if none of them can
+    pub fn build_trace_function(&self, ty: StructType<'ctx>) -> Option<FunctionValue<'ctx>> {
+        let fields: Vec<(usize, BasicTypeEnum<'ctx>)> = ty
+            .get_field_types()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, field_type)| self.is_gc_type(*field_type))
+            .collect();
+        if fields.is_empty() {
+            return None;
+        }
+
+        let function =
+            self.module
+                .add_function("trace", self.trace_fn_type(), Some(Linkage::Internal));
+        // a builder of its own, so the function gets no debug locations
+        // from the one being generated
+        let builder = self.context.create_builder();
+        builder.position_at_end(self.context.append_basic_block(function, "entry"));
+
+        let object = function.get_nth_param(0).unwrap().into_pointer_value();
+        let object =
+            builder.build_pointer_cast(object, ty.ptr_type(AddressSpace::Generic), "fields");
+        let gc_mark = *self.builtins.get("gc_mark").unwrap();
+        for (index, field_type) in fields {
+            let field = builder
+                .build_struct_gep(object, index as u32, "field")
+                .unwrap();
+            let slot = self.gc_pointer_slot(&builder, field, field_type);
+            let pointer = builder.build_load(slot, "pointer");
+            builder.build_call(gc_mark, &[pointer], "");
+        }
+        builder.build_return(None);
+
+        Some(function)
+    }
+
+    /// Calls `gc_exit`, which prints the statistics if the program was
+    /// asked for them when it started
+    pub fn build_gc_exit(&self) {
+        let gc_exit = *self.builtins.get("gc_exit").unwrap();
+        self.builder.build_call(gc_exit, &[], "");
+    }
+
+    /// begin_gc_frame
+    ///
+    /// Starts generating `function`, whose entry block has been added,
+    /// with a frame on the shadow stack. The entry block is left for
+    /// `end_gc_frame`, which fills it in once the stack slots the frame
+    /// has to hold are known, and the builder moves to a new block
+    /// after it. Returns the frame of the function whose generation is
+    /// interrupted, for `end_gc_frame`.
+    pub fn begin_gc_frame(&mut self, function: FunctionValue<'ctx>) -> Option<GcFrame<'ctx>> {
+        let entry = function.get_first_basic_block().unwrap();
+        let body = self.context.append_basic_block(function, "body");
+        self.builder.position_at_end(body);
+
+        self.gc_frame.replace(GcFrame {
+            entry,
+            body,
+            roots: Vec::new(),
+            returns: Vec::new(),
+        })
+    }
+
+    /// Adds `storage`, a stack slot of type `ty`, to the frame of the
+    /// function being generated, if values of that type can point to
+    /// heap objects
+    pub fn add_gc_root(&mut self, storage: PointerValue<'ctx>, ty: BasicTypeEnum<'ctx>) {
+        if !self.is_gc_type(ty) {
+            return;
+        }
+        if let Some(frame) = self.gc_frame.as_mut() {
+            frame.roots.push((storage, ty));
+        }
+    }
+
+    /// Keeps `value`, the result of a call, alive until the function
+    /// returns. Otherwise an allocation later in the same expression,
+    /// e.g. `(str-concat (f) (g))`, could free it before it is used.
+    pub fn root_temporary(&mut self, value: BasicValueEnum<'ctx>) {
+        let ty = value.get_type();
+        if self.gc_frame.is_none() || !self.is_gc_type(ty) {
+            return;
+        }
+
+        let storage = self.build_entry_alloca(ty, "temporary");
+        self.builder.build_store(storage, value);
+        self.add_gc_root(storage, ty);
+    }
+
+    /// Returns `value` from the function being generated, popping its
+    /// frame off the shadow stack
+    pub fn build_gc_return(&mut self, value: &dyn BasicValue<'ctx>) {
+        let ret = self.builder.build_return(Some(value));
+        if let Some(frame) = self.gc_frame.as_mut() {
+            frame.returns.push(ret);
+        }
+    }
+
+    /// end_gc_frame
+    ///
+    /// Finishes the frame of the function started with `begin_gc_frame`
+    /// and goes back to the frame of `caller`. The entry block allocates
+    /// the frame, clears the slots in it, since a collection can happen
+    /// before they are stored to, and pushes it on the shadow stack;
+    /// every return pops it.
+    pub fn end_gc_frame(&mut self, caller: Option<GcFrame<'ctx>>) {
+        let frame = match mem::replace(&mut self.gc_frame, caller) {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        let builder = self.context.create_builder();
+        builder.position_at_end(frame.entry);
+        if frame.roots.is_empty() {
+            builder.build_unconditional_branch(frame.body);
+            return;
+        }
+
+        let ptr_type = self.opaque_ptr_type();
+        let i64_type = self.context.i64_type();
+        let frame_type = self.gc_frame_type(frame.roots.len() as u32);
+        let frame_ptr = builder.build_alloca(frame_type, "gc_frame");
+
+        let slots = builder.build_struct_gep(frame_ptr, 2, "slots").unwrap();
+        for (index, (storage, ty)) in frame.roots.iter().enumerate() {
+            let slot = self.gc_pointer_slot(&builder, *storage, *ty);
+            builder.build_store(slot, ptr_type.const_null());
+
+            let indexes = [
+                i64_type.const_zero(),
+                i64_type.const_int(index as u64, false),
+            ];
+            let frame_slot = unsafe { builder.build_in_bounds_gep(slots, &indexes, "slot") };
+            builder.build_store(frame_slot, slot);
+        }
+
+        let count = builder.build_struct_gep(frame_ptr, 1, "count").unwrap();
+        builder.build_store(count, i64_type.const_int(frame.roots.len() as u64, false));
+
+        let roots = self.gc_global("roots").as_pointer_value();
+        let caller_frame = builder.build_load(roots, "caller_frame");
+        let prev = builder.build_struct_gep(frame_ptr, 0, "prev").unwrap();
+        builder.build_store(prev, caller_frame);
+        builder.build_store(
+            roots,
+            builder.build_pointer_cast(frame_ptr, ptr_type, "frame"),
+        );
+        builder.build_unconditional_branch(frame.body);
+
+        for ret in frame.returns.iter() {
+            builder.position_before(ret);
+            builder.build_store(roots, caller_frame);
+        }
+    }
+
+    /// Whether values of type `ty` can point to heap objects: strings,
+    /// and closures through their environment
+    fn is_gc_type(&self, ty: BasicTypeEnum<'ctx>) -> bool {
+        match ty {
+            BasicTypeEnum::PointerType(_) => true,
+            BasicTypeEnum::StructType(struct_type) => struct_type == self.closure_type(),
+            _ => false,
+        }
+    }
+
+    /// The `i8*` in `storage`, of type `ty`, that points to a heap object
+    fn gc_pointer_slot(
+        &self,
+        builder: &Builder<'ctx>,
+        storage: PointerValue<'ctx>,
+        ty: BasicTypeEnum<'ctx>,
+    ) -> PointerValue<'ctx> {
+        match ty {
+            BasicTypeEnum::StructType(_) => builder.build_struct_gep(storage, 1, "env").unwrap(),
+            _ => storage,
+        }
+    }
+
+    fn gc_global(&self, name: &str) -> GlobalValue<'ctx> {
+        self.module
+            .get_global(&format!("__tisp_gc_{}", name))
+            .expect("the GC runtime is added by `init`")
+    }
+
+    /// `{ i8* next, i8* trace, i64 size, i64 mark }`, where `size`
+    /// includes the header
+    fn gc_header_type(&self) -> StructType<'ctx> {
+        let ptr_type = self.opaque_ptr_type().into();
+        let i64_type = self.context.i64_type().into();
+        self.context
+            .struct_type(&[ptr_type, ptr_type, i64_type, i64_type], false)
+    }
+
+    /// `{ i8* prev, i64 count, [count x i8**] slots }`
+    fn gc_frame_type(&self, count: u32) -> StructType<'ctx> {
+        let slot_type = self.opaque_ptr_type().ptr_type(AddressSpace::Generic);
+        self.context.struct_type(
+            &[
+                self.opaque_ptr_type().into(),
+                self.context.i64_type().into(),
+                slot_type.array_type(count).into(),
+            ],
+            false,
+        )
+    }
+
+    fn trace_fn_type(&self) -> FunctionType<'ctx> {
+        let ptr_arg = BasicTypeEnum::PointerType(self.opaque_ptr_type());
+        self.context.void_type().fn_type(&[ptr_arg], false)
+    }
+
+    /// Header of the heap object `object` points to
+    fn build_header_ptr(&self, object: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let header_ptr_type = self.gc_header_type().ptr_type(AddressSpace::Generic);
+        let header = self
+            .builder
+            .build_pointer_cast(object, header_ptr_type, "object");
+        let minus_one = self.context.i64_type().const_int(-1i64 as u64, true);
+        unsafe {
+            self.builder
+                .build_in_bounds_gep(header, &[minus_one], "header")
+        }
+    }
+
+    fn build_global_add(&self, name: &str, amount: IntValue<'ctx>) {
+        let global = self.gc_global(name).as_pointer_value();
+        let value = self.builder.build_load(global, name).into_int_value();
+        let value = self.builder.build_int_add(value, amount, name);
+        self.builder.build_store(global, value);
+    }
+
+    fn build_global_sub(&self, name: &str, amount: IntValue<'ctx>) {
+        let global = self.gc_global(name).as_pointer_value();
+        let value = self.builder.build_load(global, name).into_int_value();
+        let value = self.builder.build_int_sub(value, amount, name);
+        self.builder.build_store(global, value);
+    }
+
+    fn define_gc_mark(&self) -> FunctionValue<'ctx> {
+        let function = self.add_runtime_function("gc_mark", self.trace_fn_type());
+        let object = function.get_nth_param(0).unwrap().into_pointer_value();
+
+        let check = self.context.append_basic_block(function, "check");
+        let visit = self.context.append_basic_block(function, "visit");
+        let trace = self.context.append_basic_block(function, "trace");
+        let done = self.context.append_basic_block(function, "done");
+
+        // strings and environments in cleared slots are null
+        let is_null = self.builder.build_is_null(object, "is_null");
+        self.builder.build_conditional_branch(is_null, done, check);
+
+        // objects are visited once, which also stops at cycles
+        self.builder.position_at_end(check);
+        let header = self.build_header_ptr(object);
+        let mark_ptr = self
+            .builder
+            .build_struct_gep(header, HEADER_MARK, "mark_ptr")
+            .unwrap();
+        let mark = self.builder.build_load(mark_ptr, "mark").into_int_value();
+        let unmarked = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            mark,
+            self.context.i64_type().const_int(UNMARKED, false),
+            "unmarked",
+        );
+        self.builder.build_conditional_branch(unmarked, visit, done);
+
+        self.builder.position_at_end(visit);
+        self.builder
+            .build_store(mark_ptr, self.context.i64_type().const_int(MARKED, false));
+        let trace_ptr = self
+            .builder
+            .build_struct_gep(header, HEADER_TRACE, "trace_ptr")
+            .unwrap();
+        let trace_fn = self
+            .builder
+            .build_load(trace_ptr, "trace_fn")
+            .into_pointer_value();
+        let has_trace = self.builder.build_is_not_null(trace_fn, "has_trace");
+        self.builder
+            .build_conditional_branch(has_trace, trace, done);
+
+        self.builder.position_at_end(trace);
+        let trace_fn = self.builder.build_pointer_cast(
+            trace_fn,
+            self.trace_fn_type().ptr_type(AddressSpace::Generic),
+            "trace_fn",
+        );
+        let trace_fn = CallableValue::try_from(trace_fn).unwrap();
+        self.builder.build_call(trace_fn, &[object.into()], "");
+        self.builder.build_unconditional_branch(done);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None);
+
+        function
+    }
+
+    fn define_gc_collect(&self, gc_mark: FunctionValue<'ctx>) -> FunctionValue<'ctx> {
+        let ptr_type = self.opaque_ptr_type();
+        let i64_type = self.context.i64_type();
+        let function =
+            self.add_runtime_function("gc_collect", self.context.void_type().fn_type(&[], false));
+
+        let entry = self.builder.get_insert_block().unwrap();
+        let frames = self.context.append_basic_block(function, "frames");
+        let scan = self.context.append_basic_block(function, "scan");
+        let slots = self.context.append_basic_block(function, "slots");
+        let slot = self.context.append_basic_block(function, "slot");
+        let next_frame = self.context.append_basic_block(function, "next_frame");
+        let sweep = self.context.append_basic_block(function, "sweep");
+        let walk = self.context.append_basic_block(function, "walk");
+        let check = self.context.append_basic_block(function, "check");
+        let keep = self.context.append_basic_block(function, "keep");
+        let free = self.context.append_basic_block(function, "free");
+        let done = self.context.append_basic_block(function, "done");
+
+        let innermost = self
+            .builder
+            .build_load(self.gc_global("roots").as_pointer_value(), "innermost");
+        self.builder.build_unconditional_branch(frames);
+
+        // mark everything the slots of the running functions point to
+        self.builder.position_at_end(frames);
+        let frame = self.builder.build_phi(ptr_type, "frame");
+        let frame_value = frame.as_basic_value().into_pointer_value();
+        let at_bottom = self.builder.build_is_null(frame_value, "at_bottom");
+        self.builder
+            .build_conditional_branch(at_bottom, sweep, scan);
+
+        self.builder.position_at_end(scan);
+        let frame_ptr = self.builder.build_pointer_cast(
+            frame_value,
+            self.gc_frame_type(0).ptr_type(AddressSpace::Generic),
+            "frame_ptr",
+        );
+        let count_ptr = self
+            .builder
+            .build_struct_gep(frame_ptr, 1, "count_ptr")
+            .unwrap();
+        let count = self.builder.build_load(count_ptr, "count").into_int_value();
+        let frame_slots = self
+            .builder
+            .build_struct_gep(frame_ptr, 2, "frame_slots")
+            .unwrap();
+        self.builder.build_unconditional_branch(slots);
+
+        self.builder.position_at_end(slots);
+        let index = self.builder.build_phi(i64_type, "index");
+        let index_value = index.as_basic_value().into_int_value();
+        let scanned =
+            self.builder
+                .build_int_compare(IntPredicate::UGE, index_value, count, "scanned");
+        self.builder
+            .build_conditional_branch(scanned, next_frame, slot);
+
+        self.builder.position_at_end(slot);
+        let slot_ptr = unsafe {
+            self.builder.build_in_bounds_gep(
+                frame_slots,
+                &[i64_type.const_zero(), index_value],
+                "slot_ptr",
+            )
+        };
+        let slot_value = self
+            .builder
+            .build_load(slot_ptr, "slot")
+            .into_pointer_value();
+        let object = self.builder.build_load(slot_value, "object");
+        self.builder.build_call(gc_mark, &[object], "");
+        let next = self
+            .builder
+            .build_int_add(index_value, i64_type.const_int(1, false), "next");
+        self.builder.build_unconditional_branch(slots);
+        index.add_incoming(&[(&i64_type.const_zero(), scan), (&next, slot)]);
+
+        self.builder.position_at_end(next_frame);
+        let prev_ptr = self
+            .builder
+            .build_struct_gep(frame_ptr, 0, "prev_ptr")
+            .unwrap();
+        let prev = self.builder.build_load(prev_ptr, "prev");
+        self.builder.build_unconditional_branch(frames);
+        frame.add_incoming(&[(&innermost, entry), (&prev, next_frame)]);
+
+        // free the objects that weren't marked; `link` is the pointer
+        // to the object looked at, so it can be unlinked from the list
+        self.builder.position_at_end(sweep);
+        self.builder.build_unconditional_branch(walk);
+
+        self.builder.position_at_end(walk);
+        let link = self
+            .builder
+            .build_phi(ptr_type.ptr_type(AddressSpace::Generic), "link");
+        let link_value = link.as_basic_value().into_pointer_value();
+        let header_value = self
+            .builder
+            .build_load(link_value, "header")
+            .into_pointer_value();
+        let at_end = self.builder.build_is_null(header_value, "at_end");
+        self.builder.build_conditional_branch(at_end, done, check);
+
+        self.builder.position_at_end(check);
+        let header = self.builder.build_pointer_cast(
+            header_value,
+            self.gc_header_type().ptr_type(AddressSpace::Generic),
+            "header",
+        );
+        let mark_ptr = self
+            .builder
+            .build_struct_gep(header, HEADER_MARK, "mark_ptr")
+            .unwrap();
+        let mark = self.builder.build_load(mark_ptr, "mark").into_int_value();
+        let marked = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            mark,
+            i64_type.const_int(MARKED, false),
+            "marked",
+        );
+        let next_ptr = self
+            .builder
+            .build_struct_gep(header, HEADER_NEXT, "next_ptr")
+            .unwrap();
+        self.builder.build_conditional_branch(marked, keep, free);
+
+        // survivors start the next collection unmarked
+        self.builder.position_at_end(keep);
+        self.builder
+            .build_store(mark_ptr, i64_type.const_int(UNMARKED, false));
+        self.builder.build_unconditional_branch(walk);
+
+        self.builder.position_at_end(free);
+        let next_object = self.builder.build_load(next_ptr, "next_object");
+        self.builder.build_store(link_value, next_object);
+        let size_ptr = self
+            .builder
+            .build_struct_gep(header, HEADER_SIZE, "size_ptr")
+            .unwrap();
+        let size = self.builder.build_load(size_ptr, "size").into_int_value();
+        self.build_global_add("freed", size);
+        self.build_global_sub("heap_size", size);
+        let free_fn = *self.builtins.get("free").unwrap();
+        self.builder.build_call(free_fn, &[header_value.into()], "");
+        self.builder.build_unconditional_branch(walk);
+
+        link.add_incoming(&[
+            (&self.gc_global("objects").as_pointer_value(), sweep),
+            (&next_ptr, keep),
+            (&link_value, free),
+        ]);
+
+        // collect again once the surviving heap has doubled
+        self.builder.position_at_end(done);
+        self.build_global_add("collections", i64_type.const_int(1, false));
+        let heap_size = self
+            .builder
+            .build_load(self.gc_global("heap_size").as_pointer_value(), "heap_size")
+            .into_int_value();
+        let doubled = self.builder.build_int_add(heap_size, heap_size, "doubled");
+        let initial = i64_type.const_int(GC_INITIAL_THRESHOLD, false);
+        let above_initial =
+            self.builder
+                .build_int_compare(IntPredicate::UGT, doubled, initial, "above_initial");
+        let threshold = self
+            .builder
+            .build_select(above_initial, doubled, initial, "threshold");
+        self.builder
+            .build_store(self.gc_global("threshold").as_pointer_value(), threshold);
+        self.builder.build_return(None);
+
+        function
+    }
+
+    fn define_gc_alloc(&self, gc_collect: FunctionValue<'ctx>) -> FunctionValue<'ctx> {
+        let ptr_type = self.opaque_ptr_type();
+        let i64_type = self.context.i64_type();
+        let function = self.add_runtime_function(
+            "gc_alloc",
+            ptr_type.fn_type(&[i64_type.into(), ptr_type.into()], false),
+        );
+        let size = function.get_nth_param(0).unwrap().into_int_value();
+        let trace = function.get_nth_param(1).unwrap();
+
+        let collect = self.context.append_basic_block(function, "collect");
+        let allocate = self.context.append_basic_block(function, "allocate");
+
+        // rounded up to a multiple of 8, so every header is aligned
+        // even where `malloc` doesn't align anything
+        let header_type = self.gc_header_type();
+        let total = self
+            .builder
+            .build_int_add(size, header_type.size_of().unwrap(), "total");
+        let total = self
+            .builder
+            .build_int_add(total, i64_type.const_int(7, false), "total");
+        let total = self
+            .builder
+            .build_and(total, i64_type.const_int(!7, false), "total");
+
+        let heap_size = self
+            .builder
+            .build_load(self.gc_global("heap_size").as_pointer_value(), "heap_size")
+            .into_int_value();
+        let new_size = self.builder.build_int_add(heap_size, total, "new_size");
+        let threshold = self
+            .builder
+            .build_load(self.gc_global("threshold").as_pointer_value(), "threshold")
+            .into_int_value();
+        let over = self
+            .builder
+            .build_int_compare(IntPredicate::UGT, new_size, threshold, "over");
+        self.builder
+            .build_conditional_branch(over, collect, allocate);
+
+        self.builder.position_at_end(collect);
+        self.builder.build_call(gc_collect, &[], "");
+        self.builder.build_unconditional_branch(allocate);
+
+        self.builder.position_at_end(allocate);
+        self.build_global_add("heap_size", total);
+        let malloc = *self.builtins.get("malloc").unwrap();
+        let memory = self
+            .builder
+            .build_call(malloc, &[total.into()], "memory")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+
+        let header = self.builder.build_pointer_cast(
+            memory,
+            header_type.ptr_type(AddressSpace::Generic),
+            "header",
+        );
+        let objects = self.gc_global("objects").as_pointer_value();
+        let fields: [(u32, BasicValueEnum<'ctx>); 4] = [
+            (HEADER_NEXT, self.builder.build_load(objects, "next")),
+            (HEADER_TRACE, trace),
+            (HEADER_SIZE, total.into()),
+            (HEADER_MARK, i64_type.const_int(UNMARKED, false).into()),
+        ];
+        for (index, value) in fields.iter() {
+            let field = self
+                .builder
+                .build_struct_gep(header, *index, "field")
+                .unwrap();
+            self.builder.build_store(field, *value);
+        }
+        self.builder.build_store(objects, memory);
+
+        let one = i64_type.const_int(1, false);
+        let payload = unsafe { self.builder.build_in_bounds_gep(header, &[one], "payload") };
+        let payload = self
+            .builder
+            .build_pointer_cast(payload, ptr_type, "payload");
+        self.builder.build_return(Some(&payload));
+
+        function
+    }
+
+    fn define_gc_exit(&self) -> FunctionValue<'ctx> {
+        let function =
+            self.add_runtime_function("gc_exit", self.context.void_type().fn_type(&[], false));
+
+        let report = self.context.append_basic_block(function, "report");
+        let done = self.context.append_basic_block(function, "done");
+
+        // checked now rather than when the program is compiled, so that
+        // the same program can run with or without the statistics
+        let stats = if self.targets_wasm() {
+            self.builder
+                .build_load(self.gc_global("stats").as_pointer_value(), "stats")
+                .into_int_value()
+        } else {
+            let getenv = *self.builtins.get("getenv").unwrap();
+            let name = self
+                .builder
+                .build_global_string_ptr(GC_STATS_VAR, "gc_stats_var")
+                .as_pointer_value();
+            let value = self
+                .builder
+                .build_call(getenv, &[name.into()], "getenv")
+                .try_as_basic_value()
+                .left()
+                .unwrap()
+                .into_pointer_value();
+            self.builder.build_is_not_null(value, "stats")
+        };
+        self.builder.build_conditional_branch(stats, report, done);
+
+        self.builder.position_at_end(report);
+        let collections = self.builder.build_load(
+            self.gc_global("collections").as_pointer_value(),
+            "collections",
+        );
+        let freed = self
+            .builder
+            .build_load(self.gc_global("freed").as_pointer_value(), "freed");
+
+        if self.targets_wasm() {
+            let print_str = *self.builtins.get("print_str").unwrap();
+            let print_u64 = *self.builtins.get("print_u64").unwrap();
+            let parts = [
+                ("gc: ", Some(collections)),
+                (" collections, ", Some(freed)),
+                (" bytes freed\n", None),
+            ];
+            for (text, value) in parts.iter() {
+                let text = self
+                    .builder
+                    .build_global_string_ptr(text, "gc_stats")
+                    .as_pointer_value();
+                self.builder.build_call(print_str, &[text.into()], "");
+                if let Some(value) = value {
+                    self.builder.build_call(print_u64, &[*value], "");
+                }
+            }
+        } else {
+            let dprintf = *self.builtins.get("dprintf").unwrap();
+            let stderr = self.context.i32_type().const_int(2, false);
+            let format = self
+                .builder
+                .build_global_string_ptr("gc: %llu collections, %llu bytes freed\n", "gc_stats")
+                .as_pointer_value();
+            self.builder.build_call(
+                dprintf,
+                &[stderr.into(), format.into(), collections, freed],
+                "dprintf",
+            );
+        }
+        self.builder.build_unconditional_branch(done);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(None);
+
+        function
+    }
+
+    /// A WebAssembly module has no environment to read `TISP_GC_STATS`
+    /// from, so it exports `gc_stats` for the host to call before `main`
+    /// instead
+    fn define_gc_stats_export(&self) {
+        let function = self.module.add_function(
+            "gc_stats",
+            self.context.void_type().fn_type(&[], false),
+            Some(Linkage::External),
+        );
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        self.builder.build_store(
+            self.gc_global("stats").as_pointer_value(),
+            self.context.bool_type().const_int(1, false),
+        );
+        self.builder.build_return(None);
+    }
+}
@@ -0,0 +1,171 @@
+use std::convert::TryFrom;
+
+use inkwell::module::Linkage;
+use inkwell::types::{BasicTypeEnum, StructType};
+use inkwell::values::{BasicValueEnum, CallableValue, PointerValue};
+use inkwell::AddressSpace;
+
+use crate::codegen::Codegen;
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::Span;
+use crate::tispc_parser::{captured_names, Expr, Param};
+
+/// Variables a `fn` copies when it is created, and the struct they
+/// are stored in
+pub struct Environment<'a, 'ctx> {
+    pub names: Vec<&'a str>,
+    pub ty: StructType<'ctx>,
+}
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// compile_lambda
+    ///
+    /// Compiles a `fn` into an internal function and returns a closure
+    /// pointing to it. The values of the variables the body uses are
+    /// copied into an environment allocated on the heap, which the
+    /// function gets before its parameters. A `fn` that captures
+    /// nothing gets a null environment.
+    pub fn compile_lambda(
+        &mut self,
+        params: Vec<Param<'a>>,
+        body: Vec<Expr<'a>>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let ty = self.type_of(span);
+
+        // the other names the body uses are functions
+        let mut names = Vec::new();
+        let mut values: Vec<BasicValueEnum<'ctx>> = Vec::new();
+        for name in captured_names(&params, &body) {
+            if let Some(ptr) = self.variables.get(name) {
+                values.push(self.builder.build_load(*ptr, name));
+                names.push(name);
+            }
+        }
+
+        let field_types: Vec<BasicTypeEnum<'ctx>> =
+            values.iter().map(|value| value.get_type()).collect();
+        let env = Environment {
+            names,
+            ty: self.context.struct_type(&field_types, false),
+        };
+
+        let function =
+            self.module
+                .add_function("lambda", self.closure_fn_type(&ty), Some(Linkage::Internal));
+        self.define_function(function, Some(&env), params, body, &ty, span)?;
+
+        let env_ptr = self.build_environment(&env, &values);
+        let function_ptr = self.builder.build_pointer_cast(
+            function.as_global_value().as_pointer_value(),
+            self.opaque_ptr_type(),
+            "function",
+        );
+
+        let closure = self.closure_type().get_undef();
+        let closure = self
+            .builder
+            .build_insert_value(closure, function_ptr, 0, "closure")
+            .unwrap();
+        let closure = self
+            .builder
+            .build_insert_value(closure, env_ptr, 1, "closure")
+            .unwrap();
+
+        Ok(closure.into_struct_value().into())
+    }
+
+    /// Allocates the environment of a closure and stores the captured
+    /// values in it
+    fn build_environment(
+        &self,
+        env: &Environment<'a, 'ctx>,
+        values: &[BasicValueEnum<'ctx>],
+    ) -> PointerValue<'ctx> {
+        if values.is_empty() {
+            return self.opaque_ptr_type().const_null();
+        }
+
+        // sizes are i64 constants
+        let size = env.ty.size_of().unwrap();
+        let trace = self.build_trace_function(env.ty);
+        let env_ptr = self.build_gc_alloc(size, trace, "env");
+
+        let fields = self.builder.build_pointer_cast(
+            env_ptr,
+            env.ty.ptr_type(AddressSpace::Generic),
+            "fields",
+        );
+        for (index, (name, value)) in env.names.iter().zip(values).enumerate() {
+            let field = self
+                .builder
+                .build_struct_gep(fields, index as u32, name)
+                .unwrap();
+            self.builder.build_store(field, *value);
+        }
+
+        env_ptr
+    }
+
+    /// Copies the captured variables out of the environment of the
+    /// `fn` being compiled, into variables of its own
+    pub fn load_environment(&mut self, env: &Environment<'a, 'ctx>, env_ptr: PointerValue<'ctx>) {
+        let fields = self.builder.build_pointer_cast(
+            env_ptr,
+            env.ty.ptr_type(AddressSpace::Generic),
+            "fields",
+        );
+        for (index, name) in env.names.iter().enumerate() {
+            let field = self
+                .builder
+                .build_struct_gep(fields, index as u32, name)
+                .unwrap();
+            let value = self.builder.build_load(field, name);
+            let ptr = self.build_entry_alloca(value.get_type(), name);
+            self.add_gc_root(ptr, value.get_type());
+            self.builder.build_store(ptr, value);
+            self.variables.declare(name, ptr);
+        }
+    }
+
+    /// compile_closure_call
+    ///
+    /// Calls the closure `callee` evaluates to, passing its
+    /// environment before the arguments
+    pub fn compile_closure_call(
+        &mut self,
+        callee: Expr<'a>,
+        args: Vec<Expr<'a>>,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let fn_type = self.closure_fn_type(&self.type_of(callee.span()));
+        let closure = self.compile_expr(callee)?.into_struct_value();
+
+        let function_ptr = self
+            .builder
+            .build_extract_value(closure, 0, "function")
+            .unwrap()
+            .into_pointer_value();
+        let function_ptr = self.builder.build_pointer_cast(
+            function_ptr,
+            fn_type.ptr_type(AddressSpace::Generic),
+            "function",
+        );
+        let env_ptr = self.builder.build_extract_value(closure, 1, "env").unwrap();
+
+        let mut argsv: Vec<BasicValueEnum<'ctx>> = vec![env_ptr];
+        for arg in args {
+            argsv.push(self.compile_expr(arg)?);
+        }
+
+        let function = CallableValue::try_from(function_ptr).unwrap();
+        match self
+            .builder
+            .build_call(function, argsv.as_slice(), "call")
+            .try_as_basic_value()
+            .left()
+        {
+            Some(value) => Ok(value),
+            None => unreachable!("closures always return a value"),
+        }
+    }
+}
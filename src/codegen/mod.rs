@@ -3,3 +3,30 @@ pub use codegen::*;
 
 mod builtins;
 pub use builtins::*;
+
+mod conditionals;
+
+mod debug_info;
+pub use debug_info::*;
+
+mod errors;
+
+mod functions;
+pub use functions::*;
+
+mod gc;
+pub use gc::*;
+
+mod lambdas;
+pub use lambdas::*;
+
+mod numbers;
+
+mod repl;
+
+mod strings;
+
+mod types;
+
+mod wasm;
+pub use wasm::*;
@@ -0,0 +1,172 @@
+use inkwell::types::{BasicTypeEnum, IntType};
+use inkwell::values::{BasicValueEnum, FloatValue};
+use inkwell::FloatPredicate;
+
+use crate::codegen::Codegen;
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::Span;
+use crate::tispc_parser::Expr;
+use crate::typeck::Type;
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// Compiles an integer literal to the type the checker picked for
+    /// it, which may also be a float type
+    pub fn compile_integer(
+        &self,
+        val: i128,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let ty = self.type_of(span);
+
+        if ty.is_float() {
+            return Ok(self.compile_float(val as f64, &ty));
+        }
+
+        let (min, max) = ty
+            .int_bounds()
+            .expect("integer literal was given a non-numeric type");
+        if val < min || val > max {
+            return Err(Diagnostic::error("E0211", "integer literal out of range")
+                .with_primary(span, format!("does not fit in `{}`", ty))
+                .with_note(format!("`{}` holds values from {} to {}", ty, min, max)));
+        }
+
+        // const_int takes the bits of the value, so negative numbers
+        // are passed as their two's complement
+        Ok(self
+            .llvm_type(&ty)
+            .into_int_type()
+            .const_int(val as u64, ty.is_signed())
+            .into())
+    }
+
+    pub fn compile_float(&self, val: f64, ty: &Type) -> BasicValueEnum<'ctx> {
+        self.llvm_type(ty).into_float_type().const_float(val).into()
+    }
+
+    /// Compiles a conversion such as `(f64 n)`
+    pub fn compile_cast(
+        &mut self,
+        value: Expr<'a>,
+        span: Span,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        let from = self.type_of(value.span());
+        let to = self.type_of(span);
+        let compiled = self.compile_expr(value)?;
+
+        Ok(self.build_conversion(compiled, &from, &to))
+    }
+
+    /// Converts a number between two numeric types. Integers are
+    /// extended according to the signedness of their own type, and
+    /// truncated by dropping their high bits. Floats are converted to
+    /// integers as in `build_float_to_int`.
+    fn build_conversion(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        from: &Type,
+        to: &Type,
+    ) -> BasicValueEnum<'ctx> {
+        match (value, self.llvm_type(to)) {
+            (BasicValueEnum::IntValue(val), BasicTypeEnum::IntType(int_type)) => {
+                let (from_bits, to_bits) = (from.bit_width(), to.bit_width());
+                if from_bits < to_bits && from.is_signed() {
+                    self.builder
+                        .build_int_s_extend(val, int_type, "sext")
+                        .into()
+                } else if from_bits < to_bits {
+                    self.builder
+                        .build_int_z_extend(val, int_type, "zext")
+                        .into()
+                } else if from_bits > to_bits {
+                    self.builder
+                        .build_int_truncate(val, int_type, "trunc")
+                        .into()
+                } else {
+                    value
+                }
+            }
+            (BasicValueEnum::IntValue(val), BasicTypeEnum::FloatType(float_type)) => {
+                if from.is_signed() {
+                    self.builder
+                        .build_signed_int_to_float(val, float_type, "sitofp")
+                        .into()
+                } else {
+                    self.builder
+                        .build_unsigned_int_to_float(val, float_type, "uitofp")
+                        .into()
+                }
+            }
+            (BasicValueEnum::FloatValue(val), BasicTypeEnum::IntType(int_type)) => {
+                self.build_float_to_int(val, int_type, to)
+            }
+            (BasicValueEnum::FloatValue(val), BasicTypeEnum::FloatType(float_type)) => {
+                if from.bit_width() < to.bit_width() {
+                    self.builder
+                        .build_float_ext(val, float_type, "fpext")
+                        .into()
+                } else if from.bit_width() > to.bit_width() {
+                    self.builder
+                        .build_float_trunc(val, float_type, "fptrunc")
+                        .into()
+                } else {
+                    value
+                }
+            }
+            _ => unreachable!("conversion between non-numeric types passed the type checker"),
+        }
+    }
+
+    /// Converts a float to the integer type `to`, rounding towards
+    /// zero. LLVM gives poison for floats outside the range of `to`,
+    /// so those become the closest integer in range instead, and NaN
+    /// becomes 0, which is what the interpreter does too.
+    fn build_float_to_int(
+        &self,
+        val: FloatValue<'ctx>,
+        int_type: IntType<'ctx>,
+        to: &Type,
+    ) -> BasicValueEnum<'ctx> {
+        let converted = if to.is_signed() {
+            self.builder
+                .build_float_to_signed_int(val, int_type, "fptosi")
+        } else {
+            self.builder
+                .build_float_to_unsigned_int(val, int_type, "fptoui")
+        };
+
+        // the lower bound is exact as a float, and the upper one
+        // rounds up to a power of two if at all, so every float
+        // strictly between them converts exactly
+        let (min, max) = to.int_bounds().unwrap();
+        let float_type = val.get_type();
+        let below = self.builder.build_float_compare(
+            FloatPredicate::OLE,
+            val,
+            float_type.const_float(min as f64),
+            "below",
+        );
+        let above = self.builder.build_float_compare(
+            FloatPredicate::OGE,
+            val,
+            float_type.const_float(max as f64),
+            "above",
+        );
+        let nan = self
+            .builder
+            .build_float_compare(FloatPredicate::UNO, val, val, "nan");
+
+        let min = int_type.const_int(min as u64, to.is_signed());
+        let max = int_type.const_int(max as u64, false);
+        let result = self.builder.build_select(below, min, converted, "saturate");
+        let result = self
+            .builder
+            .build_select(above, max, result.into_int_value(), "saturate");
+        self.builder.build_select(
+            nan,
+            int_type.const_zero(),
+            result.into_int_value(),
+            "saturate",
+        )
+    }
+}
@@ -0,0 +1,79 @@
+use inkwell::module::Linkage;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::BasicValueEnum;
+use inkwell::AddressSpace;
+
+use crate::codegen::{printf_conversion, Codegen};
+use crate::diagnostics::Diagnostics;
+use crate::tispc_parser::Expr;
+use crate::typeck::Type;
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// generate_repl_entry
+    ///
+    /// Lowers one REPL input into a function `name` that takes no
+    /// arguments, runs the input and prints the value of its last
+    /// expression. Unlike `main` it flushes stdout before returning,
+    /// since the process keeps running afterwards.
+    pub fn generate_repl_entry(
+        &mut self,
+        name: &str,
+        expression_tree: Vec<Expr<'a>>,
+        diagnostics: &mut Diagnostics,
+    ) {
+        let i32_type = self.context.i32_type();
+        let entry_fn = self
+            .module
+            .add_function(name, i32_type.fn_type(&[], false), None);
+        self.context.append_basic_block(entry_fn, "entry");
+        let caller_frame = self.begin_gc_frame(entry_fn);
+
+        let last_type = expression_tree.last().map(|expr| self.type_of(expr.span()));
+        let last_value = self.compile_program(expression_tree, diagnostics);
+
+        if let (Some(value), Some(ty)) = (last_value, last_type) {
+            self.print_result(value, &ty);
+        }
+
+        self.flush_stdout();
+        self.build_gc_return(&i32_type.const_int(0, false));
+        self.end_gc_frame(caller_frame);
+    }
+
+    /// Prints the value of an input on its own line. Values that have
+    /// nothing to show, like `unit`, are skipped.
+    fn print_result(&mut self, value: BasicValueEnum<'ctx>, ty: &Type) {
+        let format = match printf_conversion(ty) {
+            Some(conversion) => format!("{}\n", conversion),
+            None => return,
+        };
+
+        let printf = *self.builtins.get("printf").unwrap();
+        let format_string = self
+            .builder
+            .build_global_string_ptr(&format, "format_string")
+            .as_pointer_value();
+        self.builder.build_call(
+            printf,
+            &[format_string.into(), self.printf_arg(value, ty)],
+            "printf",
+        );
+    }
+
+    // printf buffers its output, which would otherwise show up after
+    // the REPL's next prompt
+    fn flush_stdout(&mut self) {
+        let str_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let fflush_type = self
+            .context
+            .i32_type()
+            .fn_type(&[BasicTypeEnum::PointerType(str_type)], false);
+        let fflush = self
+            .module
+            .add_function("fflush", fflush_type, Some(Linkage::External));
+
+        // fflush(NULL) flushes every open stream
+        self.builder
+            .build_call(fflush, &[str_type.const_null().into()], "fflush");
+    }
+}
@@ -0,0 +1,205 @@
+use std::iter;
+
+use inkwell::module::Linkage;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{BasicValueEnum, IntValue, PointerValue};
+use inkwell::{AddressSpace, IntPredicate};
+
+use crate::codegen::Codegen;
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::IdentKind;
+use crate::tispc_parser::Expr;
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    // declare the C library functions the string builtins are made of
+    pub fn add_string_functions(&mut self) {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let str_type = self.context.i8_type().ptr_type(AddressSpace::Generic);
+        let str_arg = BasicTypeEnum::PointerType(str_type);
+
+        let functions = [
+            ("strlen", i64_type.fn_type(&[str_arg], false)),
+            ("strcmp", i32_type.fn_type(&[str_arg, str_arg], false)),
+            ("malloc", str_type.fn_type(&[i64_type.into()], false)),
+        ];
+        for (name, fn_type) in functions.iter() {
+            let function = self
+                .module
+                .add_function(name, *fn_type, Some(Linkage::External));
+            self.builtins.insert(name, function);
+        }
+    }
+
+    /// compile_string_builtin
+    ///
+    /// Compiles a call to `str-concat`, `str-len`, `substr` or `str=`.
+    /// Strings are pointers to null-terminated UTF-8 bytes, so lengths
+    /// and positions count bytes. New strings are allocated on the
+    /// heap and freed by the garbage collector.
+    pub fn compile_string_builtin(
+        &mut self,
+        builtin: &IdentKind,
+        args: Vec<Expr<'a>>,
+    ) -> Result<BasicValueEnum<'ctx>, Diagnostic> {
+        // the type checker makes sure the operands are right
+        let mut operands: Vec<BasicValueEnum<'ctx>> = Vec::new();
+        for arg in args {
+            operands.push(self.compile_expr(arg)?);
+        }
+
+        let value = match builtin {
+            IdentKind::StrConcat => {
+                let strings: Vec<PointerValue<'ctx>> = operands
+                    .iter()
+                    .map(|operand| operand.into_pointer_value())
+                    .collect();
+                self.build_str_concat(&strings).into()
+            }
+            IdentKind::StrLen => self.build_strlen(operands[0].into_pointer_value()).into(),
+            IdentKind::Substr => self
+                .build_substr(
+                    operands[0].into_pointer_value(),
+                    operands[1].into_int_value(),
+                    operands[2].into_int_value(),
+                )
+                .into(),
+            IdentKind::StrEqual => {
+                let strcmp = *self.builtins.get("strcmp").unwrap();
+                let order = self
+                    .builder
+                    .build_call(strcmp, &[operands[0], operands[1]], "strcmp")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                let zero = self.context.i32_type().const_zero();
+
+                self.builder
+                    .build_int_compare(IntPredicate::EQ, order, zero, "str_eq")
+                    .into()
+            }
+            _ => unreachable!("{:?} is not a string builtin", builtin),
+        };
+
+        Ok(value)
+    }
+
+    fn build_str_concat(&self, strings: &[PointerValue<'ctx>]) -> PointerValue<'ctx> {
+        let lengths: Vec<IntValue<'ctx>> = strings
+            .iter()
+            .map(|string| self.build_strlen(*string))
+            .collect();
+
+        let mut total = self.context.i64_type().const_zero();
+        for length in lengths.iter() {
+            total = self.builder.build_int_add(total, *length, "total_len");
+        }
+
+        let buffer = self.build_string_alloc(total);
+        let mut offset = self.context.i64_type().const_zero();
+        for (string, length) in strings.iter().zip(lengths.iter()) {
+            let dest = unsafe { self.builder.build_in_bounds_gep(buffer, &[offset], "dest") };
+            self.build_copy(dest, *string, *length);
+            offset = self.builder.build_int_add(offset, *length, "offset");
+        }
+
+        buffer
+    }
+
+    // positions past either end are clamped to the string, so
+    // `substr` can't read outside of it
+    fn build_substr(
+        &self,
+        string: PointerValue<'ctx>,
+        start: IntValue<'ctx>,
+        count: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let zero = self.context.i64_type().const_zero();
+        let length = self.build_strlen(string);
+
+        let start = self.build_clamp(start, zero, length);
+        let rest = self.builder.build_int_sub(length, start, "rest");
+        let count = self.build_clamp(count, zero, rest);
+
+        let buffer = self.build_string_alloc(count);
+        let src = unsafe { self.builder.build_in_bounds_gep(string, &[start], "src") };
+        self.build_copy(buffer, src, count);
+
+        buffer
+    }
+
+    /// Pointer to a string literal, which is a static object so that
+    /// it looks like any other string to the garbage collector
+    pub fn build_string_literal(&self, text: &str) -> PointerValue<'ctx> {
+        let i8_type = self.context.i8_type();
+        let bytes: Vec<IntValue<'ctx>> = text
+            .bytes()
+            .chain(iter::once(0))
+            .map(|byte| i8_type.const_int(byte as u64, false))
+            .collect();
+
+        self.build_static_object(i8_type.const_array(&bytes).into(), "str")
+    }
+
+    /// Allocates a string of `length` bytes and null-terminates it.
+    /// Strings don't point to other objects, so they have no trace
+    /// function.
+    fn build_string_alloc(&self, length: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let one = self.context.i64_type().const_int(1, false);
+        let size = self.builder.build_int_add(length, one, "size");
+
+        let buffer = self.build_gc_alloc(size, None, "buffer");
+
+        let end = unsafe { self.builder.build_in_bounds_gep(buffer, &[length], "end") };
+        self.builder
+            .build_store(end, self.context.i8_type().const_zero());
+
+        buffer
+    }
+
+    fn build_strlen(&self, string: PointerValue<'ctx>) -> IntValue<'ctx> {
+        let strlen = *self.builtins.get("strlen").unwrap();
+
+        self.builder
+            .build_call(strlen, &[string.into()], "len")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value()
+    }
+
+    fn build_copy(
+        &self,
+        dest: PointerValue<'ctx>,
+        src: PointerValue<'ctx>,
+        length: IntValue<'ctx>,
+    ) {
+        self.builder
+            .build_memcpy(dest, 1, src, 1, length)
+            .expect("1 is a valid alignment");
+    }
+
+    /// `value` limited to `low..=high` (signed)
+    fn build_clamp(
+        &self,
+        value: IntValue<'ctx>,
+        low: IntValue<'ctx>,
+        high: IntValue<'ctx>,
+    ) -> IntValue<'ctx> {
+        let below = self
+            .builder
+            .build_int_compare(IntPredicate::SLT, value, low, "below");
+        let value = self
+            .builder
+            .build_select(below, low, value, "clamp_low")
+            .into_int_value();
+
+        let above = self
+            .builder
+            .build_int_compare(IntPredicate::SGT, value, high, "above");
+        self.builder
+            .build_select(above, high, value, "clamp_high")
+            .into_int_value()
+    }
+}
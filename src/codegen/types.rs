@@ -0,0 +1,112 @@
+use inkwell::types::{BasicType, BasicTypeEnum, FunctionType, PointerType, StructType};
+use inkwell::values::BasicValueEnum;
+use inkwell::AddressSpace;
+
+use crate::codegen::Codegen;
+use crate::tispc_lexer::Span;
+use crate::typeck::Type;
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// Type the checker assigned to the expression at `span`, with
+    /// the type parameters of the generic function being compiled
+    /// filled in
+    pub fn type_of(&self, span: Span) -> Type {
+        self.types
+            .get(span)
+            .expect("codegen ran on an expression that was not type checked")
+            .substitute(self.substitution)
+    }
+
+    /// Type of the function called by the call at `call_span`
+    pub fn instance_of(&self, call_span: Span) -> Type {
+        self.types
+            .instance(call_span)
+            .expect("codegen ran on a call that was not type checked")
+            .substitute(self.substitution)
+    }
+
+    /// LLVM representation of a checked type
+    pub fn llvm_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        match ty {
+            Type::I8 | Type::U8 => self.context.i8_type().into(),
+            Type::I16 | Type::U16 => self.context.i16_type().into(),
+            Type::I32 | Type::U32 => self.context.i32_type().into(),
+            Type::I64 | Type::U64 => self.context.i64_type().into(),
+            Type::F32 => self.context.f32_type().into(),
+            Type::F64 => self.context.f64_type().into(),
+            Type::Bool => self.context.bool_type().into(),
+            Type::Str => self
+                .context
+                .i8_type()
+                .ptr_type(AddressSpace::Generic)
+                .into(),
+            // unit is an empty struct, so it can still be stored and returned
+            Type::Unit => self.context.struct_type(&[], false).into(),
+            Type::Fn(..) => self.closure_type().into(),
+            Type::Var(_) => unreachable!("generic functions are compiled per instance"),
+        }
+    }
+
+    pub fn llvm_fn_type(&self, ty: &Type) -> FunctionType<'ctx> {
+        match ty {
+            Type::Fn(params, ret) => {
+                let param_types: Vec<BasicTypeEnum<'ctx>> =
+                    params.iter().map(|param| self.llvm_type(param)).collect();
+                self.llvm_type(ret).fn_type(param_types.as_slice(), false)
+            }
+            _ => unreachable!("{} is not a function type", ty),
+        }
+    }
+
+    /// Function values are closures: a pointer to the function and a
+    /// pointer to the variables it captured
+    pub fn closure_type(&self) -> StructType<'ctx> {
+        let ptr_type = self.opaque_ptr_type().into();
+        self.context.struct_type(&[ptr_type, ptr_type], false)
+    }
+
+    /// LLVM type of the function a closure of type `ty` points to,
+    /// which takes the environment before the parameters
+    pub fn closure_fn_type(&self, ty: &Type) -> FunctionType<'ctx> {
+        match ty {
+            Type::Fn(params, ret) => {
+                let mut param_types: Vec<BasicTypeEnum<'ctx>> = vec![self.opaque_ptr_type().into()];
+                param_types.extend(params.iter().map(|param| self.llvm_type(param)));
+                self.llvm_type(ret).fn_type(param_types.as_slice(), false)
+            }
+            _ => unreachable!("{} is not a function type", ty),
+        }
+    }
+
+    /// `i8*`, for pointers to memory of any type
+    pub fn opaque_ptr_type(&self) -> PointerType<'ctx> {
+        self.context.i8_type().ptr_type(AddressSpace::Generic)
+    }
+
+    /// Value a variable declared without one starts off with
+    pub fn zero_value(&self, ty: &Type) -> BasicValueEnum<'ctx> {
+        match ty {
+            // the empty string rather than a null pointer, which the
+            // string builtins can't handle
+            Type::Str => self.build_string_literal("").into(),
+            _ => self.const_zero(ty),
+        }
+    }
+
+    /// All-zero constant of a type, e.g. `0` or a null pointer
+    pub fn const_zero(&self, ty: &Type) -> BasicValueEnum<'ctx> {
+        match self.llvm_type(ty) {
+            BasicTypeEnum::IntType(int_type) => int_type.const_zero().into(),
+            BasicTypeEnum::FloatType(float_type) => float_type.const_zero().into(),
+            BasicTypeEnum::PointerType(pointer_type) => pointer_type.const_null().into(),
+            BasicTypeEnum::StructType(struct_type) => struct_type.const_zero().into(),
+            BasicTypeEnum::ArrayType(array_type) => array_type.const_zero().into(),
+            BasicTypeEnum::VectorType(vector_type) => vector_type.const_zero().into(),
+        }
+    }
+
+    /// Value of forms run only for their effect
+    pub fn unit_value(&self) -> BasicValueEnum<'ctx> {
+        self.context.struct_type(&[], false).const_zero().into()
+    }
+}
@@ -0,0 +1,367 @@
+use inkwell::attributes::AttributeLoc;
+use inkwell::module::Linkage;
+use inkwell::types::{BasicTypeEnum, FunctionType, PointerType};
+use inkwell::values::{BasicValueEnum, FunctionValue};
+use inkwell::{AddressSpace, IntPredicate};
+
+use crate::codegen::{printf_conversion, unprintable_argument, Codegen};
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::Span;
+use crate::typeck::Type;
+
+/// Module that WebAssembly programs import their host functions from
+pub const WASM_IMPORT_MODULE: &str = "tisp";
+
+impl<'a, 'ctx> Codegen<'a, 'ctx> {
+    /// Whether the module is compiled to WebAssembly, where there is
+    /// no C library and printing is up to the host
+    pub fn targets_wasm(&self) -> bool {
+        self.module
+            .get_triple()
+            .as_str()
+            .to_string_lossy()
+            .starts_with("wasm32")
+    }
+
+    /// Declares the functions a WebAssembly program imports from the
+    /// host to print values with: `print_i64` and `print_u64` take
+    /// 64-bit integers, `print_f64` a double and `print_str` a pointer
+    /// to a null-terminated string in the module's memory
+    pub fn add_host_imports(&mut self) {
+        let void_type = self.context.void_type();
+        let i64_arg = BasicTypeEnum::IntType(self.context.i64_type());
+        let f64_arg = BasicTypeEnum::FloatType(self.context.f64_type());
+        let str_arg = BasicTypeEnum::PointerType(self.str_type());
+
+        let imports = [
+            ("print_i64", void_type.fn_type(&[i64_arg], false)),
+            ("print_u64", void_type.fn_type(&[i64_arg], false)),
+            ("print_f64", void_type.fn_type(&[f64_arg], false)),
+            ("print_str", void_type.fn_type(&[str_arg], false)),
+        ];
+        for (name, fn_type) in imports.iter() {
+            let function = self.module.add_function(
+                &format!("__tisp_{}", name),
+                *fn_type,
+                Some(Linkage::External),
+            );
+
+            // makes the linker import the function instead of
+            // looking for its definition
+            let module = self
+                .context
+                .create_string_attribute("wasm-import-module", WASM_IMPORT_MODULE);
+            let import_name = self
+                .context
+                .create_string_attribute("wasm-import-name", name);
+            function.add_attribute(AttributeLoc::Function, module);
+            function.add_attribute(AttributeLoc::Function, import_name);
+
+            self.builtins.insert(name, function);
+        }
+    }
+
+    /// add_wasm_runtime
+    ///
+    /// Defines `strlen`, `strcmp`, `malloc` and `free` in the module,
+    /// for the string builtins and the garbage collector to call when
+    /// there is no C library to link them from. `malloc` hands out
+    /// memory after the program's data and grows the memory when it
+    /// runs out, and `free` does nothing, so the memory of collected
+    /// objects is never reused.
+    pub fn add_wasm_runtime(&mut self) {
+        let current_block = self.builder.get_insert_block();
+
+        let strlen = self.build_wasm_strlen();
+        let strcmp = self.build_wasm_strcmp();
+        let malloc = self.build_wasm_malloc();
+        let free = self.build_wasm_free();
+        self.builtins.insert("strlen", strlen);
+        self.builtins.insert("strcmp", strcmp);
+        self.builtins.insert("malloc", malloc);
+        self.builtins.insert("free", free);
+
+        if let Some(block) = current_block {
+            self.builder.position_at_end(block);
+        }
+    }
+
+    /// Adds an internal function `__tisp_{name}` to the module and
+    /// moves the builder to its entry block
+    pub fn add_runtime_function(
+        &self,
+        name: &str,
+        fn_type: FunctionType<'ctx>,
+    ) -> FunctionValue<'ctx> {
+        let function = self.module.add_function(
+            &format!("__tisp_{}", name),
+            fn_type,
+            Some(Linkage::Internal),
+        );
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+
+        function
+    }
+
+    fn build_wasm_strlen(&self) -> FunctionValue<'ctx> {
+        let i64_type = self.context.i64_type();
+        let str_arg = BasicTypeEnum::PointerType(self.str_type());
+        let function = self.add_runtime_function("strlen", i64_type.fn_type(&[str_arg], false));
+        let string = function.get_nth_param(0).unwrap().into_pointer_value();
+
+        let entry = self.builder.get_insert_block().unwrap();
+        let scan = self.context.append_basic_block(function, "scan");
+        let done = self.context.append_basic_block(function, "done");
+        self.builder.build_unconditional_branch(scan);
+
+        // count bytes up to the null terminator
+        self.builder.position_at_end(scan);
+        let index = self.builder.build_phi(i64_type, "index");
+        let index_value = index.as_basic_value().into_int_value();
+        let ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(string, &[index_value], "ptr")
+        };
+        let byte = self.builder.build_load(ptr, "byte").into_int_value();
+        let next = self
+            .builder
+            .build_int_add(index_value, i64_type.const_int(1, false), "next");
+        index.add_incoming(&[(&i64_type.const_zero(), entry), (&next, scan)]);
+        let at_end = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            byte,
+            self.context.i8_type().const_zero(),
+            "at_end",
+        );
+        self.builder.build_conditional_branch(at_end, done, scan);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&index_value));
+
+        function
+    }
+
+    fn build_wasm_strcmp(&self) -> FunctionValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let str_arg = BasicTypeEnum::PointerType(self.str_type());
+        let function =
+            self.add_runtime_function("strcmp", i32_type.fn_type(&[str_arg, str_arg], false));
+        let lhs = function.get_nth_param(0).unwrap().into_pointer_value();
+        let rhs = function.get_nth_param(1).unwrap().into_pointer_value();
+
+        let entry = self.builder.get_insert_block().unwrap();
+        let compare = self.context.append_basic_block(function, "compare");
+        let same = self.context.append_basic_block(function, "same");
+        let done = self.context.append_basic_block(function, "done");
+        self.builder.build_unconditional_branch(compare);
+
+        // compare byte by byte until the strings differ or both end
+        self.builder.position_at_end(compare);
+        let index = self.builder.build_phi(i64_type, "index");
+        let index_value = index.as_basic_value().into_int_value();
+        let lhs_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(lhs, &[index_value], "lhs_ptr")
+        };
+        let rhs_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(rhs, &[index_value], "rhs_ptr")
+        };
+        let lhs_byte = self
+            .builder
+            .build_load(lhs_ptr, "lhs_byte")
+            .into_int_value();
+        let rhs_byte = self
+            .builder
+            .build_load(rhs_ptr, "rhs_byte")
+            .into_int_value();
+        let differ = self
+            .builder
+            .build_int_compare(IntPredicate::NE, lhs_byte, rhs_byte, "differ");
+        self.builder.build_conditional_branch(differ, done, same);
+
+        self.builder.position_at_end(same);
+        let next = self
+            .builder
+            .build_int_add(index_value, i64_type.const_int(1, false), "next");
+        let at_end = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            lhs_byte,
+            self.context.i8_type().const_zero(),
+            "at_end",
+        );
+        self.builder.build_conditional_branch(at_end, done, compare);
+        index.add_incoming(&[(&i64_type.const_zero(), entry), (&next, same)]);
+
+        // bytes compare as unsigned, like in C
+        self.builder.position_at_end(done);
+        let lhs_byte = self.builder.build_int_z_extend(lhs_byte, i32_type, "lhs");
+        let rhs_byte = self.builder.build_int_z_extend(rhs_byte, i32_type, "rhs");
+        let order = self.builder.build_int_sub(lhs_byte, rhs_byte, "order");
+        self.builder.build_return(Some(&order));
+
+        function
+    }
+
+    fn build_wasm_malloc(&self) -> FunctionValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let str_type = self.str_type();
+        let function =
+            self.add_runtime_function("malloc", str_type.fn_type(&[i64_type.into()], false));
+        let size = function.get_nth_param(0).unwrap().into_int_value();
+
+        // the linker puts the heap after the data and the stack
+        let heap_base = self
+            .module
+            .add_global(self.context.i8_type(), None, "__heap_base");
+        heap_base.set_linkage(Linkage::External);
+
+        // null until the first allocation, as `__heap_base` is only
+        // known when linking
+        let heap_top = self.module.add_global(str_type, None, "__tisp_heap_top");
+        heap_top.set_linkage(Linkage::Internal);
+        heap_top.set_initializer(&str_type.const_null());
+
+        let memory_size = self.module.add_function(
+            "llvm.wasm.memory.size.i32",
+            i32_type.fn_type(&[i32_type.into()], false),
+            None,
+        );
+        let memory_grow = self.module.add_function(
+            "llvm.wasm.memory.grow.i32",
+            i32_type.fn_type(&[i32_type.into(), i32_type.into()], false),
+            None,
+        );
+
+        // the collector only asks for multiples of 8 bytes, which
+        // keeps every allocation aligned
+        let top = self
+            .builder
+            .build_load(heap_top.as_pointer_value(), "top")
+            .into_pointer_value();
+        let first = self.builder.build_is_null(top, "first");
+        let start = self
+            .builder
+            .build_select(first, heap_base.as_pointer_value(), top, "start")
+            .into_pointer_value();
+        let size = self.builder.build_int_truncate(size, i32_type, "size");
+        let end = unsafe { self.builder.build_in_bounds_gep(start, &[size], "end") };
+        self.builder.build_store(heap_top.as_pointer_value(), end);
+
+        let grow = self.context.append_basic_block(function, "grow");
+        let done = self.context.append_basic_block(function, "done");
+
+        // memory is counted in pages of 64KiB
+        let memory_index = i32_type.const_zero();
+        let page_bits = i32_type.const_int(16, false);
+        let pages = self
+            .builder
+            .build_call(memory_size, &[memory_index.into()], "pages")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        let limit = self.builder.build_left_shift(pages, page_bits, "limit");
+        let end_address = self.builder.build_ptr_to_int(end, i32_type, "end_address");
+        let too_small =
+            self.builder
+                .build_int_compare(IntPredicate::UGT, end_address, limit, "too_small");
+        self.builder.build_conditional_branch(too_small, grow, done);
+
+        // if the host refuses to grow the memory, using it traps
+        self.builder.position_at_end(grow);
+        let missing = self.builder.build_int_sub(end_address, limit, "missing");
+        let missing = self.builder.build_int_add(
+            missing,
+            i32_type.const_int((1 << 16) - 1, false),
+            "missing",
+        );
+        let new_pages = self
+            .builder
+            .build_right_shift(missing, page_bits, false, "new_pages");
+        self.builder.build_call(
+            memory_grow,
+            &[memory_index.into(), new_pages.into()],
+            "grown",
+        );
+        self.builder.build_unconditional_branch(done);
+
+        self.builder.position_at_end(done);
+        self.builder.build_return(Some(&start));
+
+        function
+    }
+
+    fn build_wasm_free(&self) -> FunctionValue<'ctx> {
+        let str_arg = BasicTypeEnum::PointerType(self.str_type());
+        let void_type = self.context.void_type();
+        let function = self.add_runtime_function("free", void_type.fn_type(&[str_arg], false));
+        self.builder.build_return(None);
+
+        function
+    }
+
+    /// build_host_print
+    ///
+    /// Prints `args`, whose checked types are `arg_types`, by calling
+    /// the host imports. Integers are widened to 64 bits and floats to
+    /// doubles, and every value is followed by a space, so the output
+    /// is the same as that of `printf` elsewhere.
+    pub fn build_host_print(
+        &self,
+        args: Vec<BasicValueEnum<'ctx>>,
+        arg_types: &[Type],
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        if arg_types.iter().any(|ty| printf_conversion(ty).is_none()) {
+            return Err(unprintable_argument(span));
+        }
+
+        let print_str = self.builtins["print_str"];
+        let space = self
+            .builder
+            .build_global_string_ptr(" ", "space")
+            .as_pointer_value();
+
+        let i64_type = self.context.i64_type();
+        for (arg, ty) in args.into_iter().zip(arg_types.iter()) {
+            let (import, value): (&str, BasicValueEnum<'ctx>) = match arg {
+                BasicValueEnum::IntValue(val) if *ty != Type::Bool => {
+                    if ty.is_signed() {
+                        let val = self
+                            .builder
+                            .build_int_s_extend_or_bit_cast(val, i64_type, "widen");
+                        ("print_i64", val.into())
+                    } else {
+                        let val = self
+                            .builder
+                            .build_int_z_extend_or_bit_cast(val, i64_type, "widen");
+                        ("print_u64", val.into())
+                    }
+                }
+                BasicValueEnum::FloatValue(val) => {
+                    let val = self
+                        .builder
+                        .build_float_cast(val, self.context.f64_type(), "widen");
+                    ("print_f64", val.into())
+                }
+                // booleans are printed as strings
+                _ => ("print_str", self.printf_arg(arg, ty)),
+            };
+
+            self.builder
+                .build_call(self.builtins[import], &[value], import);
+            self.builder
+                .build_call(print_str, &[space.into()], "print_str");
+        }
+
+        Ok(())
+    }
+
+    fn str_type(&self) -> PointerType<'ctx> {
+        self.context.i8_type().ptr_type(AddressSpace::Generic)
+    }
+}
@@ -0,0 +1,7 @@
+mod diagnostic;
+mod render;
+mod runtime;
+
+pub use diagnostic::*;
+pub use render::*;
+pub use runtime::*;
@@ -0,0 +1,136 @@
+use std::fmt::Write;
+
+use super::diagnostic::{Diagnostic, Diagnostics, Label};
+use crate::tispc_lexer::LineIndex;
+
+/// render_diagnostic
+///
+/// Formats a diagnostic the way it is shown to the user, quoting every
+/// labelled source line with the labelled range underlined:
+///
+/// ```text
+/// error[E0201]: cannot find variable `y`
+///  --> test.tp:2:11
+///   |
+/// 2 | (print (+ y 1))
+///   |           ^ not found in this scope
+/// ```
+pub fn render_diagnostic(diagnostic: &Diagnostic, filename: &str, source: &str) -> String {
+    let index = LineIndex::new(source);
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "{}[{}]: {}",
+        diagnostic.severity, diagnostic.code, diagnostic.message
+    );
+
+    // sort labels by their position in the file
+    let mut labels: Vec<(usize, usize, &Label)> = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            let start = index.line_col(label.span.start);
+            (start.line, start.col, label)
+        })
+        .collect();
+    labels.sort_by_key(|(line, col, _)| (*line, *col));
+
+    let gutter_width = labels
+        .iter()
+        .map(|(line, _, _)| line.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let pad = " ".repeat(gutter_width);
+
+    if let Some(primary) = diagnostic
+        .labels
+        .iter()
+        .find(|label| label.primary)
+        .or_else(|| diagnostic.labels.first())
+    {
+        let location = index.line_col(primary.span.start);
+        let _ = writeln!(
+            out,
+            "{}--> {}:{}:{}",
+            pad, filename, location.line, location.col
+        );
+        let _ = writeln!(out, "{} |", pad);
+    }
+
+    let mut previous_line: Option<usize> = None;
+    for (line, col, label) in labels.iter() {
+        if previous_line != Some(*line) {
+            if let Some(previous) = previous_line {
+                if *line > previous + 1 {
+                    let _ = writeln!(out, "{}...", pad);
+                }
+            }
+
+            let _ = writeln!(
+                out,
+                "{:>width$} | {}",
+                line,
+                index.line_text(*line),
+                width = gutter_width
+            );
+            previous_line = Some(*line);
+        }
+
+        let line_text = index.line_text(*line);
+
+        // keep tabs so the underline lines up with the quoted source
+        let indent: String = line_text
+            .chars()
+            .take(col - 1)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        // spans running past the end of the line are cut off there
+        let underline_len = {
+            let line_end = line_text.chars().count() + 1;
+            let end = index.line_col(label.span.end);
+            let end_col = if end.line == *line { end.col } else { line_end };
+            end_col.saturating_sub(*col).max(1)
+        };
+        let marker = if label.primary { "^" } else { "-" };
+
+        let _ = write!(out, "{} | {}{}", pad, indent, marker.repeat(underline_len));
+        if label.message.is_empty() {
+            out.push('\n');
+        } else {
+            let _ = writeln!(out, " {}", label.message);
+        }
+    }
+
+    if !diagnostic.notes.is_empty() {
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{} |", pad);
+        }
+        for note in diagnostic.notes.iter() {
+            let _ = writeln!(out, "{} = note: {}", pad, note);
+        }
+    }
+
+    out
+}
+
+/// Renders every collected diagnostic followed by a summary line
+pub fn render_diagnostics(diagnostics: &Diagnostics, filename: &str, source: &str) -> String {
+    let mut out = String::new();
+
+    for diagnostic in diagnostics.iter() {
+        out.push_str(&render_diagnostic(diagnostic, filename, source));
+        out.push('\n');
+    }
+
+    match diagnostics.error_count() {
+        0 => (),
+        1 => out.push_str("error: aborting due to previous error\n"),
+        count => {
+            let _ = writeln!(out, "error: aborting due to {} previous errors", count);
+        }
+    }
+
+    out
+}
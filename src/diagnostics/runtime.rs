@@ -0,0 +1,9 @@
+use super::Diagnostic;
+use crate::tispc_lexer::Span;
+
+/// The error a program stops at when it divides by zero, pointing at
+/// the divisor. The interpreter reports it, and compiled programs
+/// print it rendered the same way before exiting.
+pub fn division_by_zero(divisor: Span) -> Diagnostic {
+    Diagnostic::error("E0400", "division by zero").with_primary(divisor, "this is zero")
+}
@@ -0,0 +1,26 @@
+use inkwell::module::Module;
+use inkwell::targets::{InitializationConfig, Target};
+use inkwell::OptimizationLevel;
+
+/// run_jit
+///
+/// Compiles `module` in memory and runs its `main` function inside
+/// the compiler's own process, returning the exit code of the
+/// program. The program prints to the compiler's stdout, and no
+/// files are written. Machine code is generated at `opt_level`.
+pub fn run_jit(module: &Module, opt_level: OptimizationLevel) -> Result<i32, String> {
+    Target::initialize_native(&InitializationConfig::default())?;
+
+    let engine = module
+        .create_jit_execution_engine(opt_level)
+        .map_err(|err| err.to_string())?;
+    let main = match module.get_function("main") {
+        Some(main) => main,
+        None => return Err(String::from("the program has no `main` function")),
+    };
+
+    // generated code is trusted as much as the compiler that made it
+    let exit_code = unsafe { engine.run_function_as_main(main, &[]) };
+
+    Ok(exit_code)
+}
@@ -0,0 +1,46 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// link
+///
+/// Links an object file into an executable with the system C
+/// compiler, which also links in the C library for `printf`.
+/// Set `CC` to use a compiler other than `cc`.
+pub fn link(object: &Path, output: &Path) -> Result<(), String> {
+    let linker = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    run_linker(&linker, object, output, &[])
+}
+
+/// link_wasm
+///
+/// Links a WebAssembly object file into a module with `wasm-ld`. The
+/// module has no entry point; it exports `main` and its memory for the
+/// host to call and to read strings from, and `gc_stats` for the host
+/// to ask for the statistics of the garbage collector. Set `WASM_LD` to use a
+/// linker other than `wasm-ld`.
+pub fn link_wasm(object: &Path, output: &Path) -> Result<(), String> {
+    let linker = env::var("WASM_LD").unwrap_or_else(|_| String::from("wasm-ld"));
+    run_linker(
+        &linker,
+        object,
+        output,
+        &["--no-entry", "--export=main", "--export=gc_stats"],
+    )
+}
+
+fn run_linker(linker: &str, object: &Path, output: &Path, args: &[&str]) -> Result<(), String> {
+    let status = Command::new(linker)
+        .arg(object)
+        .arg("-o")
+        .arg(output)
+        .args(args)
+        .status()
+        .map_err(|err| format!("could not run linker `{}`: {}", linker, err))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("linker `{}` failed ({})", linker, status))
+    }
+}
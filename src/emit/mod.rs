@@ -0,0 +1,14 @@
+mod jit;
+pub use jit::*;
+
+mod linker;
+pub use linker::*;
+
+mod optimize;
+pub use optimize::*;
+
+mod output;
+pub use output::*;
+
+mod target;
+pub use target::*;
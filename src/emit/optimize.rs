@@ -0,0 +1,171 @@
+use inkwell::module::Module;
+use inkwell::passes::{PassManager, PassManagerBuilder};
+use inkwell::OptimizationLevel;
+
+/// Optimization level chosen with `-O`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+    // like O2, but avoids making the code bigger
+    Os,
+}
+
+impl OptLevel {
+    /// Values accepted by `-O`
+    pub const NAMES: [&'static str; 5] = ["0", "1", "2", "3", "s"];
+
+    pub fn from_name(name: &str) -> Option<OptLevel> {
+        match name {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            "3" => Some(OptLevel::O3),
+            "s" => Some(OptLevel::Os),
+            _ => None,
+        }
+    }
+
+    /// Level the target machine and the JIT generate code at
+    pub fn llvm_level(self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+
+    // inlining thresholds used by clang for the same levels
+    fn inline_threshold(self) -> Option<u32> {
+        match self {
+            OptLevel::O0 | OptLevel::O1 => None,
+            OptLevel::O2 => Some(225),
+            OptLevel::O3 => Some(250),
+            OptLevel::Os => Some(75),
+        }
+    }
+}
+
+/// Optimizations chosen on the command line: the pipeline of an
+/// `-O` level, followed by the passes listed with `--passes`
+#[derive(Debug, Clone)]
+pub struct Optimizations {
+    pub level: OptLevel,
+    pub passes: Vec<String>,
+}
+
+/// Passes accepted by `--passes`, named as in LLVM's `opt`
+pub const PASS_NAMES: [&str; 30] = [
+    "mem2reg",
+    "sroa",
+    "instcombine",
+    "reassociate",
+    "gvn",
+    "newgvn",
+    "sccp",
+    "ipsccp",
+    "early-cse",
+    "adce",
+    "dse",
+    "simplifycfg",
+    "jump-threading",
+    "licm",
+    "loop-rotate",
+    "loop-unroll",
+    "loop-deletion",
+    "indvars",
+    "loop-vectorize",
+    "slp-vectorizer",
+    "inline",
+    "always-inline",
+    "tailcallelim",
+    "globalopt",
+    "globaldce",
+    "constmerge",
+    "deadargelim",
+    "memcpyopt",
+    "functionattrs",
+    "strip-dead-prototypes",
+];
+
+/// optimize
+///
+/// Runs the optimizations in `optimizations` over `module`. At `-O0`
+/// only the passes listed with `--passes` run, so the effect of each
+/// pass can be seen on its own with `--emit=llvm-ir`.
+pub fn optimize(module: &Module, optimizations: &Optimizations) -> Result<(), String> {
+    let level = optimizations.level;
+    let module_passes = PassManager::create(());
+
+    if level != OptLevel::O0 {
+        let builder = PassManagerBuilder::create();
+        builder.set_optimization_level(level.llvm_level());
+        if level == OptLevel::Os {
+            builder.set_size_level(1);
+        }
+        if let Some(threshold) = level.inline_threshold() {
+            builder.set_inliner_with_threshold(threshold);
+        }
+
+        // function passes clean up each function before the module
+        // passes look at the program as a whole
+        let function_passes = PassManager::create(module);
+        builder.populate_function_pass_manager(&function_passes);
+        function_passes.initialize();
+        for function in module.get_functions() {
+            function_passes.run_on(&function);
+        }
+        function_passes.finalize();
+
+        builder.populate_module_pass_manager(&module_passes);
+    }
+
+    for pass in optimizations.passes.iter() {
+        add_pass(&module_passes, pass)?;
+    }
+
+    module_passes.run_on(module);
+
+    Ok(())
+}
+
+fn add_pass(pass_manager: &PassManager<Module>, name: &str) -> Result<(), String> {
+    match name {
+        "mem2reg" => pass_manager.add_promote_memory_to_register_pass(),
+        "sroa" => pass_manager.add_scalar_repl_aggregates_pass(),
+        "instcombine" => pass_manager.add_instruction_combining_pass(),
+        "reassociate" => pass_manager.add_reassociate_pass(),
+        "gvn" => pass_manager.add_gvn_pass(),
+        "newgvn" => pass_manager.add_new_gvn_pass(),
+        "sccp" => pass_manager.add_sccp_pass(),
+        "ipsccp" => pass_manager.add_ipsccp_pass(),
+        "early-cse" => pass_manager.add_early_cse_pass(),
+        "adce" => pass_manager.add_aggressive_dce_pass(),
+        "dse" => pass_manager.add_dead_store_elimination_pass(),
+        "simplifycfg" => pass_manager.add_cfg_simplification_pass(),
+        "jump-threading" => pass_manager.add_jump_threading_pass(),
+        "licm" => pass_manager.add_licm_pass(),
+        "loop-rotate" => pass_manager.add_loop_rotate_pass(),
+        "loop-unroll" => pass_manager.add_loop_unroll_pass(),
+        "loop-deletion" => pass_manager.add_loop_deletion_pass(),
+        "indvars" => pass_manager.add_ind_var_simplify_pass(),
+        "loop-vectorize" => pass_manager.add_loop_vectorize_pass(),
+        "slp-vectorizer" => pass_manager.add_slp_vectorize_pass(),
+        "inline" => pass_manager.add_function_inlining_pass(),
+        "always-inline" => pass_manager.add_always_inliner_pass(),
+        "tailcallelim" => pass_manager.add_tail_call_elimination_pass(),
+        "globalopt" => pass_manager.add_global_optimizer_pass(),
+        "globaldce" => pass_manager.add_global_dce_pass(),
+        "constmerge" => pass_manager.add_constant_merge_pass(),
+        "deadargelim" => pass_manager.add_dead_arg_elimination_pass(),
+        "memcpyopt" => pass_manager.add_memcpy_optimize_pass(),
+        "functionattrs" => pass_manager.add_function_attrs_pass(),
+        "strip-dead-prototypes" => pass_manager.add_strip_dead_prototypes_pass(),
+        _ => return Err(format!("unknown pass `{}`", name)),
+    }
+
+    Ok(())
+}
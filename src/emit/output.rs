@@ -0,0 +1,115 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+use inkwell::module::Module;
+use inkwell::targets::{FileType, TargetMachine};
+
+use crate::emit::{is_host, is_wasm, link, link_wasm};
+
+/// Kind of file `tispc` writes, chosen with `--emit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmitKind {
+    LlvmIr,
+    LlvmBc,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl EmitKind {
+    /// Values accepted by `--emit`
+    pub const NAMES: [&'static str; 5] = ["llvm-ir", "llvm-bc", "asm", "obj", "exe"];
+
+    pub fn from_name(name: &str) -> Option<EmitKind> {
+        match name {
+            "llvm-ir" => Some(EmitKind::LlvmIr),
+            "llvm-bc" => Some(EmitKind::LlvmBc),
+            "asm" => Some(EmitKind::Asm),
+            "obj" => Some(EmitKind::Obj),
+            "exe" => Some(EmitKind::Exe),
+            _ => None,
+        }
+    }
+
+    /// Extension of the output file; executables have none
+    pub fn extension(self) -> &'static str {
+        match self {
+            EmitKind::LlvmIr => "ll",
+            EmitKind::LlvmBc => "bc",
+            EmitKind::Asm => "s",
+            EmitKind::Obj => "o",
+            EmitKind::Exe => "",
+        }
+    }
+}
+
+/// Path to write to when no `-o` is given: the source file with
+/// the extension of the artifact, e.g. `~/fib.tp` -> `~/fib.o`.
+/// WebAssembly modules get a `.wasm` extension, as hosts load them
+/// by file name.
+pub fn default_output_path(input: &Path, kind: EmitKind, machine: &TargetMachine) -> PathBuf {
+    if kind == EmitKind::Exe && is_wasm(machine) {
+        return input.with_extension("wasm");
+    }
+
+    input.with_extension(kind.extension())
+}
+
+/// write_output
+///
+/// Writes `module` to `path` as the artifact chosen with `--emit`.
+/// Assembly, object files and executables are generated by `machine`;
+/// executables are linked by the system C compiler, so they can only
+/// be made for the machine the compiler runs on, or as WebAssembly
+/// modules with `wasm-ld`.
+pub fn write_output(
+    module: &Module,
+    kind: EmitKind,
+    path: &Path,
+    machine: &TargetMachine,
+) -> Result<(), String> {
+    match kind {
+        EmitKind::LlvmIr => module.print_to_file(path).map_err(|err| err.to_string()),
+        EmitKind::LlvmBc => {
+            if module.write_bitcode_to_path(path) {
+                Ok(())
+            } else {
+                Err(format!("could not write {}", path.display()))
+            }
+        }
+        EmitKind::Asm => write_machine_code(module, FileType::Assembly, path, machine),
+        EmitKind::Obj => write_machine_code(module, FileType::Object, path, machine),
+        EmitKind::Exe if !is_host(machine) && !is_wasm(machine) => Err(format!(
+            "cannot link executables for {}; use --emit=obj and link it with a linker for that target",
+            machine.get_triple()
+        )),
+        EmitKind::Exe => {
+            // the object file only lives until it has been linked
+            let stem = path.file_stem().unwrap_or_else(|| "out".as_ref());
+            let object =
+                env::temp_dir().join(format!("{}-{}.o", stem.to_string_lossy(), process::id()));
+
+            write_machine_code(module, FileType::Object, &object, machine)?;
+            let result = if is_wasm(machine) {
+                link_wasm(&object, path)
+            } else {
+                link(&object, path)
+            };
+            let _ = fs::remove_file(&object);
+
+            result
+        }
+    }
+}
+
+fn write_machine_code(
+    module: &Module,
+    file_type: FileType,
+    path: &Path,
+    machine: &TargetMachine,
+) -> Result<(), String> {
+    machine
+        .write_to_file(module, file_type, path)
+        .map_err(|err| format!("could not write {}: {}", path.display(), err))
+}
@@ -0,0 +1,106 @@
+use inkwell::module::Module;
+use inkwell::targets::{
+    CodeModel, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+};
+use inkwell::OptimizationLevel;
+
+/// Machine to generate code for, chosen with `--target`, `--cpu` and
+/// `--features`. Anything left out is that of the machine the compiler
+/// runs on, or the most generic CPU of a target other than it.
+#[derive(Debug, Clone, Default)]
+pub struct TargetOptions {
+    pub triple: Option<String>,
+    pub cpu: Option<String>,
+    pub features: Option<String>,
+}
+
+/// target_machine
+///
+/// Creates the target machine described by `options`, which generates
+/// code at `opt_level`. Code for the host uses its CPU and features by
+/// default, and code for other targets is generic.
+pub fn target_machine(
+    options: &TargetOptions,
+    opt_level: OptimizationLevel,
+) -> Result<TargetMachine, String> {
+    let (triple, cpu, features) = match &options.triple {
+        None => {
+            Target::initialize_native(&InitializationConfig::default())?;
+            let cpu = TargetMachine::get_host_cpu_name().to_string();
+            let features = TargetMachine::get_host_cpu_features().to_string();
+            (TargetMachine::get_default_triple(), cpu, features)
+        }
+        Some(triple) => {
+            Target::initialize_all(&InitializationConfig::default());
+            let triple = TargetMachine::normalize_triple(&TargetTriple::create(triple));
+            // without bulk memory, copying strings would need a
+            // `memcpy` that WebAssembly programs don't have
+            let features = if is_wasm_triple(&triple) {
+                String::from("+bulk-memory")
+            } else {
+                String::new()
+            };
+            (triple, String::from("generic"), features)
+        }
+    };
+
+    let target = Target::from_triple(&triple)
+        .map_err(|err| format!("unknown target `{}`: {}", triple, err))?;
+    let cpu = options.cpu.clone().unwrap_or(cpu);
+    let features = options.features.clone().unwrap_or(features);
+
+    target
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            opt_level,
+            // position independent, as most linkers default to PIE
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| format!("could not create a target machine for {}", triple))
+}
+
+/// Makes `module` a module for `machine`, so that it is optimized and
+/// written out for it
+pub fn set_target(module: &Module, machine: &TargetMachine) {
+    module.set_triple(&machine.get_triple());
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+}
+
+/// Whether `machine` generates code for the machine the compiler runs
+/// on, as opposed to cross compiling
+pub fn is_host(machine: &TargetMachine) -> bool {
+    let host = TargetMachine::normalize_triple(&TargetMachine::get_default_triple());
+    TargetMachine::normalize_triple(&machine.get_triple()) == host
+}
+
+/// Whether `machine` generates WebAssembly, which is linked into a
+/// module for a host to run rather than into an executable
+pub fn is_wasm(machine: &TargetMachine) -> bool {
+    is_wasm_triple(&machine.get_triple())
+}
+
+fn is_wasm_triple(triple: &TargetTriple) -> bool {
+    triple.as_str().to_string_lossy().starts_with("wasm32")
+}
+
+/// Names and descriptions of the targets the linked LLVM can generate
+/// code for, as listed by `--print targets`
+pub fn available_targets() -> Vec<(String, String)> {
+    Target::initialize_all(&InitializationConfig::default());
+
+    let mut targets = Vec::new();
+    let mut next = Target::get_first();
+    while let Some(target) = next {
+        targets.push((
+            target.get_name().to_string_lossy().into_owned(),
+            target.get_description().to_string_lossy().into_owned(),
+        ));
+        next = target.get_next();
+    }
+
+    targets.sort();
+    targets
+}
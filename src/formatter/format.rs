@@ -0,0 +1,27 @@
+use super::layout::layout;
+use super::tree::build_tree;
+use crate::diagnostics::Diagnostics;
+use crate::tispc_lexer::get_token_stream;
+use crate::tispc_parser::read_forms;
+
+/// Line width `tispc fmt` uses unless told otherwise
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// format_source
+///
+/// Formats a whole file, keeping its comments, so that lists fit in
+/// `width` columns where they can. Source code that doesn't lex, or
+/// whose parentheses don't balance, is left alone and its errors are
+/// returned instead. Formatting formatted code doesn't change it.
+pub fn format_source(source: &str, width: usize) -> Result<String, Diagnostics> {
+    let mut diagnostics = Diagnostics::new();
+    let token_stream = get_token_stream(source, &mut diagnostics);
+    read_forms(token_stream.clone(), &mut diagnostics);
+
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+
+    let tree = build_tree(source, &token_stream);
+    Ok(layout(&tree, width))
+}
@@ -0,0 +1,208 @@
+use super::tree::{Item, Node};
+use crate::tispc_lexer::{CommentKind, IdentKind, TokenKind};
+
+/// layout
+///
+/// Prints the tree of a file in the canonical style:
+///
+/// * a list that fits on the rest of its line is printed on one line
+/// * otherwise the body of a special form (`defn`, `while`, `do`, ...)
+///   goes on the following lines, indented two spaces from its `(`,
+///   and the arguments of a call go on separate lines, aligned with
+///   the first one
+/// * comments stay on the line they were on, or on a line of their own
+/// * single blank lines between forms are kept
+pub fn layout(items: &[Item], width: usize) -> String {
+    let mut printer = Printer {
+        width,
+        out: String::new(),
+    };
+
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            if stays_on_line(&items[i - 1], item) {
+                printer.out.push(' ');
+            } else {
+                printer.new_line(0, item.blank_line_before);
+            }
+        }
+        printer.node(&item.node, 0);
+    }
+
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+    printer.out
+}
+
+struct Printer {
+    width: usize,
+    out: String,
+}
+
+impl Printer {
+    /// Column the next character is printed at
+    fn column(&self) -> usize {
+        let line_start = match self.out.rfind('\n') {
+            Some(line_break) => line_break + 1,
+            None => 0,
+        };
+        self.out[line_start..].chars().count()
+    }
+
+    fn new_line(&mut self, indent: usize, blank_line: bool) {
+        self.out.push('\n');
+        if blank_line {
+            self.out.push('\n');
+        }
+        self.out.push_str(&" ".repeat(indent));
+    }
+
+    /// Prints `node` at the current column. `closing` is the number
+    /// of closing parentheses that will follow it on the same line.
+    fn node(&mut self, node: &Node, closing: usize) {
+        match node {
+            Node::Atom(text, _) => self.out.push_str(text),
+            Node::Comment(text, _) => self.out.push_str(text),
+            Node::List(items) => {
+                if let Some(flat) = flat(node) {
+                    if self.column() + flat.chars().count() + closing <= self.width {
+                        self.out.push_str(&flat);
+                        return;
+                    }
+                }
+                self.list(items, closing);
+            }
+        }
+    }
+
+    /// Prints a list over several lines
+    fn list(&mut self, items: &[Item], closing: usize) {
+        let column = self.column();
+        let (first_line, indent) = list_style(items, column);
+
+        self.out.push('(');
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                if i < first_line || stays_on_line(&items[i - 1], item) {
+                    self.out.push(' ');
+                } else {
+                    self.new_line(indent, item.blank_line_before);
+                }
+            }
+
+            let closing = if i + 1 == items.len() { closing + 1 } else { 0 };
+            self.node(&item.node, closing);
+        }
+
+        // a line comment runs to the end of the line
+        if let Some(Item {
+            node: Node::Comment(_, CommentKind::Line),
+            ..
+        }) = items.last()
+        {
+            self.new_line(indent, false);
+        }
+        self.out.push(')');
+    }
+}
+
+/// Whether `item` has to be printed on the same line as the item
+/// before it: a trailing comment, or the form after a `#_`
+fn stays_on_line(previous: &Item, item: &Item) -> bool {
+    match (&previous.node, &item.node) {
+        (_, Node::Comment(..)) => item.trailing,
+        (Node::Atom(_, TokenKind::Discard), _) => true,
+        _ => false,
+    }
+}
+
+/// The list printed on one line, or None if it can't be
+fn flat(node: &Node) -> Option<String> {
+    match node {
+        Node::Atom(text, _) if text.contains('\n') => None,
+        Node::Atom(text, _) => Some(text.to_string()),
+        Node::Comment(..) => None,
+        Node::List(items) if has_several_bodies(items) => None,
+        Node::List(items) => {
+            let mut line = String::from("(");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    line.push(' ');
+                }
+                line.push_str(&flat(&item.node)?);
+            }
+            line.push(')');
+            Some(line)
+        }
+    }
+}
+
+/// Whether a list is a special form with several forms in its body, or
+/// a `cond` with several clauses, which are always put on lines of
+/// their own
+fn has_several_bodies(items: &[Item]) -> bool {
+    match items.first().map(|item| &item.node) {
+        Some(Node::Atom(_, TokenKind::Ident(IdentKind::Cond))) => items.len() > 2,
+        // the value of `let` and `set!`, and the branches of `if`, aren't bodies
+        Some(Node::Atom(_, TokenKind::Ident(IdentKind::Let)))
+        | Some(Node::Atom(_, TokenKind::Ident(IdentKind::Set)))
+        | Some(Node::Atom(_, TokenKind::Ident(IdentKind::If))) => false,
+        Some(Node::Atom(_, TokenKind::Ident(kind))) => {
+            matches!(header_length(kind, items), Some(header) if items.len() > header + 2)
+        }
+        _ => false,
+    }
+}
+
+/// How many items of a list printed over several lines go on its first
+/// line, and the column the other items are indented to
+fn list_style(items: &[Item], column: usize) -> (usize, usize) {
+    // items after a comment go on the lines after it
+    let before_comment = items
+        .iter()
+        .position(|item| item.node.is_comment())
+        .unwrap_or(items.len());
+
+    let head = items.first().map(|item| &item.node);
+    let header = match head {
+        Some(Node::Atom(_, TokenKind::Ident(kind))) => header_length(kind, items),
+        _ => None,
+    };
+
+    let (first_line, indent) = match (header, head) {
+        (Some(header), _) => (1 + header, column + 2),
+        // a call, with its arguments aligned with the first one
+        (None, Some(Node::Atom(head, _))) if before_comment > 1 => {
+            (2, column + head.chars().count() + 2)
+        }
+        _ => (1, column + 1),
+    };
+
+    (first_line.min(before_comment), indent)
+}
+
+/// Number of items between a special form and its body, which are
+/// printed on the first line of the form
+fn header_length(kind: &IdentKind, items: &[Item]) -> Option<usize> {
+    match kind {
+        IdentKind::Do => Some(0),
+        IdentKind::Let
+        | IdentKind::Set
+        | IdentKind::While
+        | IdentKind::When
+        | IdentKind::Unless
+        | IdentKind::If => Some(1),
+        // the name, the parameters and maybe the return type
+        IdentKind::Defn => match items.get(3).map(|item| &item.node) {
+            Some(Node::Atom(_, TokenKind::Arrow)) => Some(3),
+            _ => Some(2),
+        },
+        // the parameters and maybe the return type
+        IdentKind::Fn => match items.get(2).map(|item| &item.node) {
+            Some(Node::Atom(_, TokenKind::Arrow)) => Some(2),
+            _ => Some(1),
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,7 @@
+mod format;
+mod layout;
+mod tree;
+
+pub use format::*;
+pub use layout::*;
+pub use tree::*;
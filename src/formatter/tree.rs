@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+
+use crate::tispc_lexer::{CommentKind, Token, TokenKind};
+
+/// Node of the tree the formatter lays out. Unlike the `SExpr`s of
+/// the reader, it keeps comments, the `#_` before commented out forms
+/// and the text of every token as it was written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node<'a> {
+    // an annotation like `x: i64` or `-> str` is a single atom, so it
+    // is never split across lines
+    Atom(Cow<'a, str>, TokenKind),
+    List(Vec<Item<'a>>),
+    Comment(&'a str, CommentKind),
+}
+
+impl<'a> Node<'a> {
+    pub fn is_comment(&self) -> bool {
+        matches!(self, Node::Comment(..))
+    }
+}
+
+/// Node in a list or at the top level, with the line breaks before it
+/// that are worth keeping
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item<'a> {
+    pub node: Node<'a>,
+    // the source has a blank line between the node and the one before
+    pub blank_line_before: bool,
+    // a comment on the same line as the node before it
+    pub trailing: bool,
+}
+
+/// build_tree
+///
+/// Groups the tokens of `source`, comments included, into the tree of
+/// the formatter. The parentheses of `token_stream` must be balanced.
+pub fn build_tree<'a>(source: &'a str, token_stream: &[Token<'a>]) -> Vec<Item<'a>> {
+    let mut builder = TreeBuilder {
+        source,
+        tokens: token_stream,
+        position: 0,
+        previous_end: 0,
+    };
+    builder.items()
+}
+
+struct TreeBuilder<'a, 't> {
+    source: &'a str,
+    tokens: &'t [Token<'a>],
+    position: usize,
+    // where the last token read ends
+    previous_end: usize,
+}
+
+impl<'a, 't> TreeBuilder<'a, 't> {
+    fn peek(&self) -> Option<&'t Token<'a>> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> &'t Token<'a> {
+        let token = &self.tokens[self.position];
+        self.position += 1;
+        self.previous_end = token.span.end;
+        token
+    }
+
+    fn text(&self, token: &Token) -> &'a str {
+        &self.source[token.span.start..token.span.end]
+    }
+
+    /// Reads nodes up to the end of the input or of the current list
+    fn items(&mut self) -> Vec<Item<'a>> {
+        let mut items = Vec::new();
+
+        while let Some(token) = self.peek() {
+            if token.kind == TokenKind::CloseParen {
+                break;
+            }
+
+            let gap = &self.source[self.previous_end..token.span.start];
+            let line_breaks = gap.matches('\n').count();
+            let node = self.node();
+
+            items.push(Item {
+                trailing: node.is_comment() && line_breaks == 0 && !items.is_empty(),
+                blank_line_before: line_breaks > 1,
+                node,
+            });
+        }
+
+        items
+    }
+
+    fn node(&mut self) -> Node<'a> {
+        let token = self.next();
+
+        match &token.kind {
+            TokenKind::OpenParen => {
+                let items = self.items();
+                // the closing parenthesis
+                self.next();
+                Node::List(items)
+            }
+            TokenKind::Comment(kind) => Node::Comment(self.text(token).trim_end(), kind.clone()),
+            TokenKind::Arrow => match self.annotation() {
+                Some(ty) => Node::Atom(Cow::Owned(format!("-> {}", ty)), TokenKind::Arrow),
+                None => Node::Atom(Cow::Borrowed(self.text(token)), TokenKind::Arrow),
+            },
+            kind => {
+                let text = self.text(token);
+                if self.peek().map(|token| &token.kind) != Some(&TokenKind::Colon) {
+                    return Node::Atom(Cow::Borrowed(text), kind.clone());
+                }
+
+                // `name: type`
+                let colon = self.position;
+                self.next();
+                match self.annotation() {
+                    Some(ty) => Node::Atom(Cow::Owned(format!("{}: {}", text, ty)), kind.clone()),
+                    None => {
+                        self.position = colon;
+                        self.previous_end = token.span.end;
+                        Node::Atom(Cow::Borrowed(text), kind.clone())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the type after a `:` or `->`, if there is one
+    fn annotation(&mut self) -> Option<&'a str> {
+        match self.peek()?.kind {
+            TokenKind::OpenParen
+            | TokenKind::CloseParen
+            | TokenKind::Comment(_)
+            | TokenKind::Discard => None,
+            _ => {
+                let token = self.next();
+                Some(self.text(token))
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::slice;
+
+use super::interpreter::Interpreter;
+use super::value::RuntimeValue;
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::IdentKind;
+use crate::tispc_parser::{CondClause, Expr};
+
+impl<'a, 'e> Interpreter<'a, 'e> {
+    /// Runs the branch picked by the condition, in a scope of its own.
+    /// An `if` without an `else` yields unit when the condition is
+    /// false.
+    pub fn eval_if(
+        &mut self,
+        condition: &'e Expr<'a>,
+        then_branch: &'e Expr<'a>,
+        else_branch: Option<&'e Expr<'a>>,
+    ) -> Result<RuntimeValue, Diagnostic> {
+        if self.eval(condition)?.as_bool() {
+            self.eval_block(slice::from_ref(then_branch))
+        } else {
+            match else_branch {
+                Some(else_branch) => self.eval_block(slice::from_ref(else_branch)),
+                None => Ok(RuntimeValue::Unit),
+            }
+        }
+    }
+
+    /// Runs the body of the first clause whose condition is true, or
+    /// of the `else` clause
+    pub fn eval_cond(&mut self, clauses: &'e [CondClause<'a>]) -> Result<RuntimeValue, Diagnostic> {
+        for clause in clauses {
+            let matches = match &clause.condition {
+                Some(condition) => self.eval(condition)?.as_bool(),
+                None => true,
+            };
+
+            if matches {
+                return self.eval_block(&clause.body);
+            }
+        }
+
+        Ok(RuntimeValue::Unit)
+    }
+
+    /// Runs the body of `(when condition body...)` if the condition is
+    /// true, or of `(unless condition body...)` if it is false
+    pub fn eval_when(
+        &mut self,
+        condition: &'e Expr<'a>,
+        body: &'e [Expr<'a>],
+        negated: bool,
+    ) -> Result<RuntimeValue, Diagnostic> {
+        if self.eval(condition)?.as_bool() != negated {
+            self.eval_block(body)?;
+        }
+
+        Ok(RuntimeValue::Unit)
+    }
+
+    /// Evaluates the operands of `and` and `or` until one decides the
+    /// result: `and` stops at the first false operand and `or` at the
+    /// first true one
+    pub fn eval_logical(
+        &mut self,
+        operator: &IdentKind,
+        args: &'e [Expr<'a>],
+    ) -> Result<RuntimeValue, Diagnostic> {
+        let decisive = *operator == IdentKind::Or;
+
+        for arg in args {
+            if self.eval(arg)?.as_bool() == decisive {
+                return Ok(RuntimeValue::Bool(decisive));
+            }
+        }
+
+        Ok(RuntimeValue::Bool(!decisive))
+    }
+}
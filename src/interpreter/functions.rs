@@ -0,0 +1,199 @@
+use std::mem;
+use std::rc::Rc;
+
+use super::interpreter::Interpreter;
+use super::value::{Closure, RuntimeValue};
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::Span;
+use crate::tispc_parser::{captured_names, Expr, Param};
+use crate::typeck::{Scopes, Substitution};
+
+/// Calls deeper than this are stopped, before the interpreter runs out
+/// of stack itself
+const MAX_DEPTH: usize = 10_000;
+
+/// Stack the interpreter needs to run `MAX_DEPTH` calls deep, even in
+/// a debug build. It is much larger than the stack of the main thread,
+/// so the interpreter runs on a thread of its own.
+pub const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+/// Function defined with `defn`
+#[derive(Debug, Clone, Copy)]
+pub struct Function<'a, 'e> {
+    pub params: &'e [Param<'a>],
+    pub body: &'e [Expr<'a>],
+    pub span: Span,
+}
+
+/// Anonymous function written with `fn`
+#[derive(Debug, Clone)]
+pub struct Lambda<'a, 'e> {
+    pub params: &'e [Param<'a>],
+    pub body: &'e [Expr<'a>],
+    // variables the body uses from where the `fn` runs
+    pub captures: Vec<&'a str>,
+}
+
+impl<'a, 'e> Interpreter<'a, 'e> {
+    /// Makes a function callable from the point its `defn` runs at.
+    /// Top-level functions are defined before the program starts.
+    pub fn eval_defn(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        params: &'e [Param<'a>],
+        body: &'e [Expr<'a>],
+        span: Span,
+    ) -> Result<RuntimeValue, Diagnostic> {
+        if let Some(function) = self.functions.get(name) {
+            if function.span != span {
+                return Err(Diagnostic::error(
+                    "E0210",
+                    format!("function `{}` is defined twice", name),
+                )
+                .with_primary(name_span, "redefined here"));
+            }
+        }
+
+        self.functions.insert(name, Function { params, body, span });
+        Ok(RuntimeValue::Unit)
+    }
+
+    /// eval_user_call
+    ///
+    /// Calls a function defined with `defn`. The body runs with only
+    /// the parameters in scope, and generic functions run with their
+    /// type parameters set to the types of this call.
+    pub fn eval_user_call(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        args: &'e [Expr<'a>],
+        span: Span,
+    ) -> Result<RuntimeValue, Diagnostic> {
+        let function = match self.functions.get(name) {
+            Some(function) => *function,
+            None => {
+                return Err(Diagnostic::error(
+                    "E0401",
+                    format!("function `{}` is not defined yet", name),
+                )
+                .with_primary(name_span, "called before its `defn` ran"))
+            }
+        };
+
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.eval(arg)?);
+        }
+
+        let function_type = self
+            .types
+            .get(function.span)
+            .cloned()
+            .expect("interpreter ran a function that was not type checked");
+        let mut substitution = Substitution::new();
+        if function_type.has_vars() {
+            function_type.bind_vars(&self.instance_of(span), &mut substitution);
+        }
+
+        let mut variables = Scopes::new();
+        for (param, value) in function.params.iter().zip(values) {
+            variables.declare(param.name, value);
+        }
+
+        self.run_function(function.body, variables, substitution, span)
+    }
+
+    /// Makes a function value from a `fn`, with copies of the
+    /// variables its body uses
+    pub fn eval_lambda(
+        &mut self,
+        params: &'e [Param<'a>],
+        body: &'e [Expr<'a>],
+        span: Span,
+    ) -> RuntimeValue {
+        let variables = &self.variables;
+        let lambda = self.lambdas.entry(span).or_insert_with(|| Lambda {
+            params,
+            body,
+            // the other names the body uses are functions
+            captures: captured_names(params, body)
+                .into_iter()
+                .filter(|name| variables.get(name).is_some())
+                .collect(),
+        });
+
+        let captures = lambda
+            .captures
+            .iter()
+            .map(|name| variables.get(name).unwrap().clone())
+            .collect();
+
+        RuntimeValue::Closure(Rc::new(Closure {
+            span,
+            captures,
+            substitution: self.substitution.clone(),
+        }))
+    }
+
+    /// eval_closure_call
+    ///
+    /// Calls the function value `callee` evaluates to. The body runs
+    /// with the captured variables and the parameters in scope, in the
+    /// generic function instance the `fn` ran in.
+    pub fn eval_closure_call(
+        &mut self,
+        callee: &'e Expr<'a>,
+        args: &'e [Expr<'a>],
+        span: Span,
+    ) -> Result<RuntimeValue, Diagnostic> {
+        let closure = match self.eval(callee)? {
+            RuntimeValue::Closure(closure) => closure,
+            value => unreachable!("call of {:?} passed the type checker", value),
+        };
+
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.eval(arg)?);
+        }
+
+        let lambda = &self.lambdas[&closure.span];
+        let body = lambda.body;
+        let mut variables = Scopes::new();
+        for (name, value) in lambda.captures.iter().zip(&closure.captures) {
+            variables.declare(name, value.clone());
+        }
+        for (param, value) in lambda.params.iter().zip(values) {
+            variables.declare(param.name, value);
+        }
+
+        self.run_function(body, variables, closure.substitution.clone(), span)
+    }
+
+    /// Runs the body of a function with `variables` in scope instead
+    /// of the caller's, and goes back to the caller
+    fn run_function(
+        &mut self,
+        body: &'e [Expr<'a>],
+        variables: Scopes<'a, RuntimeValue>,
+        substitution: Substitution,
+        span: Span,
+    ) -> Result<RuntimeValue, Diagnostic> {
+        if self.depth == MAX_DEPTH {
+            return Err(Diagnostic::error("E0402", "stack overflow")
+                .with_primary(span, format!("more than {} calls deep", MAX_DEPTH)));
+        }
+
+        let caller_substitution = mem::replace(&mut self.substitution, substitution);
+        let caller_variables = mem::replace(&mut self.variables, variables);
+
+        self.depth += 1;
+        let result = self.eval_block(body);
+        self.depth -= 1;
+
+        self.variables = caller_variables;
+        self.substitution = caller_substitution;
+        result
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use super::functions::{Function, Lambda};
+use super::numbers::{arithmetic, comparison, convert, wrap};
+use super::value::RuntimeValue;
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::{Ident, IdentKind, Span, Value};
+use crate::tispc_parser::Expr;
+use crate::typeck::{Scopes, Substitution, Type, TypeTable};
+
+/// interpret
+///
+/// Runs a type checked program by walking its expression tree, as an
+/// alternative to compiling it with LLVM. Everything the program
+/// prints is written to `out`, exactly as the compiled program would
+/// print it. The program stops at the first runtime error, such as a
+/// division by zero, which is returned for the caller to report the
+/// way `Codegen::build_runtime_check` makes compiled programs do.
+pub fn interpret<'a, 'e>(
+    expression_tree: &'e [Expr<'a>],
+    types: &'e TypeTable,
+    out: &'e mut dyn Write,
+) -> Result<(), Diagnostic> {
+    let mut interpreter = Interpreter {
+        types,
+        variables: Scopes::new(),
+        functions: HashMap::new(),
+        lambdas: HashMap::new(),
+        substitution: Substitution::new(),
+        depth: 0,
+        out,
+    };
+
+    interpreter.run_program(expression_tree)
+}
+
+pub struct Interpreter<'a, 'e> {
+    pub types: &'e TypeTable,
+    pub variables: Scopes<'a, RuntimeValue>,
+    pub functions: HashMap<&'a str, Function<'a, 'e>>,
+    // `fn`s that have run, by their span
+    pub lambdas: HashMap<Span, Lambda<'a, 'e>>,
+    // types of the generic function instance being run
+    pub substitution: Substitution,
+    // number of calls in progress
+    pub depth: usize,
+    pub out: &'e mut dyn Write,
+}
+
+impl<'a, 'e> Interpreter<'a, 'e> {
+    fn run_program(&mut self, expression_tree: &'e [Expr<'a>]) -> Result<(), Diagnostic> {
+        // top-level functions can be called before their definition
+        for expression in expression_tree {
+            if let Expr::Defn {
+                name,
+                params,
+                body,
+                span,
+                ..
+            } = expression
+            {
+                self.functions.entry(name).or_insert(Function {
+                    params,
+                    body,
+                    span: *span,
+                });
+            }
+        }
+
+        for expression in expression_tree {
+            self.eval(expression)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn eval(&mut self, expression: &'e Expr<'a>) -> Result<RuntimeValue, Diagnostic> {
+        match expression {
+            Expr::Call(head, args, span) => match &**head {
+                // resolved as in `TypeChecker::infer_call`
+                Expr::Builtin(
+                    Ident {
+                        kind: IdentKind::Variable,
+                        value: Some(Value::String(name)),
+                    },
+                    name_span,
+                ) if self.variables.get(name).is_none() => {
+                    self.eval_user_call(name, *name_span, args, *span)
+                }
+                Expr::Builtin(Ident { kind, .. }, _) if *kind != IdentKind::Variable => {
+                    self.eval_builtin(kind, args)
+                }
+                callee => self.eval_closure_call(callee, args, *span),
+            },
+
+            Expr::Constant(Value::Integer(val), span) => self.eval_integer(*val, *span),
+
+            Expr::Constant(Value::Float(val), span) => Ok(self.eval_float(*val, *span)),
+
+            Expr::Constant(Value::Boolean(val), _) => Ok(RuntimeValue::Bool(*val)),
+
+            Expr::Constant(Value::Str(text), _) => Ok(RuntimeValue::Str(text.as_bytes().into())),
+
+            Expr::Cast { value, span, .. } => {
+                let value = self.eval(value)?;
+                Ok(convert(value, &self.type_of(*span)))
+            }
+
+            Expr::Builtin(
+                Ident {
+                    kind: IdentKind::Variable,
+                    value: Some(Value::String(name)),
+                },
+                _,
+            ) => Ok(self
+                .variables
+                .get(name)
+                .expect("undefined variable passed the type checker")
+                .clone()),
+
+            Expr::Let {
+                name, value, span, ..
+            } => {
+                // `(let name)` starts the variable off at 0
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => RuntimeValue::zero(&self.type_of(*span)),
+                };
+                self.variables.declare(name, value.clone());
+                Ok(value)
+            }
+
+            Expr::Set { name, value, .. } => {
+                let value = self.eval(value)?;
+                *self
+                    .variables
+                    .get_mut(name)
+                    .expect("undefined variable passed the type checker") = value.clone();
+                Ok(value)
+            }
+
+            Expr::Do { body, .. } => self.eval_block(body),
+
+            Expr::Print { args, span } => self.eval_print(args, *span),
+
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => self.eval_if(condition, then_branch, else_branch.as_deref()),
+
+            Expr::Cond { clauses, .. } => self.eval_cond(clauses),
+
+            Expr::When {
+                condition,
+                body,
+                negated,
+                ..
+            } => self.eval_when(condition, body, *negated),
+
+            Expr::Defn {
+                name,
+                name_span,
+                params,
+                body,
+                span,
+                ..
+            } => self.eval_defn(name, *name_span, params, body, *span),
+
+            Expr::Lambda {
+                params, body, span, ..
+            } => Ok(self.eval_lambda(params, body, *span)),
+
+            Expr::While {
+                condition, body, ..
+            } => {
+                while self.eval(condition)?.as_bool() {
+                    self.eval_block(body)?;
+                }
+                Ok(RuntimeValue::Unit)
+            }
+
+            _ => unreachable!("{:?} passed the type checker", expression),
+        }
+    }
+
+    fn eval_print(&mut self, args: &'e [Expr<'a>], span: Span) -> Result<RuntimeValue, Diagnostic> {
+        // every value is followed by a space, like in the printf
+        // format string of a compiled program
+        let mut line = Vec::new();
+        for arg in args {
+            self.eval(arg)?.print(&mut line);
+            line.push(b' ');
+        }
+
+        self.out.write_all(&line).map_err(|err| {
+            Diagnostic::error("E0403", format!("could not print: {}", err))
+                .with_primary(span, "while running this `print`")
+        })?;
+
+        Ok(RuntimeValue::Unit)
+    }
+
+    /// Runs a sequence of expressions in a scope of their own and
+    /// returns the value of the last one, or unit if there are none
+    pub fn eval_block(&mut self, body: &'e [Expr<'a>]) -> Result<RuntimeValue, Diagnostic> {
+        self.variables.push();
+
+        let mut result = Ok(RuntimeValue::Unit);
+        for expr in body {
+            result = self.eval(expr);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.variables.pop();
+        result
+    }
+
+    fn eval_builtin(
+        &mut self,
+        builtin: &IdentKind,
+        args: &'e [Expr<'a>],
+    ) -> Result<RuntimeValue, Diagnostic> {
+        match builtin {
+            IdentKind::Plus
+            | IdentKind::Minus
+            | IdentKind::Mult
+            | IdentKind::Div
+            | IdentKind::Rem
+            | IdentKind::BitAnd
+            | IdentKind::BitOr
+            | IdentKind::BitXor
+            | IdentKind::Shl
+            | IdentKind::Shr => {
+                // folded as in `Codegen::compile_builtin`
+                let mut result = self.eval(&args[0])?;
+                for arg in &args[1..] {
+                    let operand = self.eval(arg)?;
+                    result = arithmetic(builtin, result, operand, arg.span())?;
+                }
+                Ok(result)
+            }
+            IdentKind::Greater
+            | IdentKind::Smaller
+            | IdentKind::GreaterEqual
+            | IdentKind::SmallerEqual
+            | IdentKind::Equal
+            | IdentKind::NotEqual => {
+                let lhs = self.eval(&args[0])?;
+                let rhs = self.eval(&args[1])?;
+                Ok(RuntimeValue::Bool(comparison(builtin, &lhs, &rhs)))
+            }
+            IdentKind::And | IdentKind::Or => self.eval_logical(builtin, args),
+            IdentKind::Not => Ok(RuntimeValue::Bool(!self.eval(&args[0])?.as_bool())),
+            IdentKind::BitNot => match self.eval(&args[0])? {
+                // the bits of the value are flipped within its type
+                RuntimeValue::Int(val, ty) => Ok(RuntimeValue::Int(wrap(!val, &ty), ty)),
+                value => unreachable!("`~` of {:?} passed the type checker", value),
+            },
+            IdentKind::StrConcat | IdentKind::StrLen | IdentKind::Substr | IdentKind::StrEqual => {
+                self.eval_string_builtin(builtin, args)
+            }
+            _ => unreachable!("call of {:?} passed the type checker", builtin),
+        }
+    }
+
+    /// Type the checker assigned to the expression at `span`, with
+    /// the type parameters of the generic function being run filled in
+    pub fn type_of(&self, span: Span) -> Type {
+        self.types
+            .get(span)
+            .expect("interpreter ran an expression that was not type checked")
+            .substitute(&self.substitution)
+    }
+
+    /// Type of the function called by the call at `call_span`
+    pub fn instance_of(&self, call_span: Span) -> Type {
+        self.types
+            .instance(call_span)
+            .expect("interpreter ran a call that was not type checked")
+            .substitute(&self.substitution)
+    }
+}
@@ -0,0 +1,15 @@
+mod interpreter;
+pub use interpreter::*;
+
+mod conditionals;
+
+mod functions;
+pub use functions::*;
+
+mod numbers;
+pub use numbers::*;
+
+mod strings;
+
+mod value;
+pub use value::*;
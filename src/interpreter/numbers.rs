@@ -0,0 +1,159 @@
+use super::interpreter::Interpreter;
+use super::value::RuntimeValue;
+use crate::diagnostics::{division_by_zero, Diagnostic};
+use crate::tispc_lexer::{IdentKind, Span};
+use crate::typeck::Type;
+
+impl<'a, 'e> Interpreter<'a, 'e> {
+    /// Evaluates an integer literal as the type the checker picked for
+    /// it, which may also be a float type
+    pub fn eval_integer(&self, val: i128, span: Span) -> Result<RuntimeValue, Diagnostic> {
+        let ty = self.type_of(span);
+
+        if ty.is_float() {
+            return Ok(float_value(val as f64, &ty));
+        }
+
+        let (min, max) = ty
+            .int_bounds()
+            .expect("integer literal was given a non-numeric type");
+        if val < min || val > max {
+            // the same error the code generator reports
+            return Err(Diagnostic::error("E0211", "integer literal out of range")
+                .with_primary(span, format!("does not fit in `{}`", ty))
+                .with_note(format!("`{}` holds values from {} to {}", ty, min, max)));
+        }
+
+        Ok(RuntimeValue::Int(val, ty))
+    }
+
+    pub fn eval_float(&self, val: f64, span: Span) -> RuntimeValue {
+        float_value(val, &self.type_of(span))
+    }
+}
+
+fn float_value(val: f64, ty: &Type) -> RuntimeValue {
+    match ty {
+        Type::F32 => RuntimeValue::F32(val as f32),
+        _ => RuntimeValue::F64(val),
+    }
+}
+
+/// Wraps an integer around to the range of an integer type by keeping
+/// its low bits, as two's complement arithmetic does
+pub fn wrap(val: i128, ty: &Type) -> i128 {
+    let shift = 128 - ty.bit_width().expect("wrap called on a non-numeric type");
+
+    if ty.is_signed() {
+        (val << shift) >> shift
+    } else {
+        ((val << shift) as u128 >> shift) as i128
+    }
+}
+
+/// Applies an arithmetic or bitwise builtin to two operands of the
+/// same type. Integers wrap around on overflow. `rhs_span` points at
+/// the divisor if it is zero.
+pub fn arithmetic(
+    operator: &IdentKind,
+    lhs: RuntimeValue,
+    rhs: RuntimeValue,
+    rhs_span: Span,
+) -> Result<RuntimeValue, Diagnostic> {
+    let value = match (lhs, rhs) {
+        (RuntimeValue::Int(lhs, ty), RuntimeValue::Int(rhs, _)) => {
+            // unsigned values are never negative, so the signed
+            // operations on i128 work for them too
+            let val = match operator {
+                IdentKind::Plus => lhs.wrapping_add(rhs),
+                IdentKind::Minus => lhs.wrapping_sub(rhs),
+                IdentKind::Mult => lhs.wrapping_mul(rhs),
+                IdentKind::Div | IdentKind::Rem if rhs == 0 => {
+                    return Err(division_by_zero(rhs_span))
+                }
+                IdentKind::Div => lhs.wrapping_div(rhs),
+                IdentKind::Rem => lhs.wrapping_rem(rhs),
+                IdentKind::BitAnd => lhs & rhs,
+                IdentKind::BitOr => lhs | rhs,
+                IdentKind::BitXor => lhs ^ rhs,
+                IdentKind::Shl => lhs << shift_amount(rhs, &ty),
+                IdentKind::Shr => lhs >> shift_amount(rhs, &ty),
+                _ => unreachable!("{:?} is not an arithmetic builtin", operator),
+            };
+            RuntimeValue::Int(wrap(val, &ty), ty)
+        }
+        (RuntimeValue::F32(lhs), RuntimeValue::F32(rhs)) => match operator {
+            IdentKind::Plus => RuntimeValue::F32(lhs + rhs),
+            IdentKind::Minus => RuntimeValue::F32(lhs - rhs),
+            IdentKind::Mult => RuntimeValue::F32(lhs * rhs),
+            IdentKind::Div => RuntimeValue::F32(lhs / rhs),
+            _ => unreachable!("{:?} is not an arithmetic builtin", operator),
+        },
+        (RuntimeValue::F64(lhs), RuntimeValue::F64(rhs)) => match operator {
+            IdentKind::Plus => RuntimeValue::F64(lhs + rhs),
+            IdentKind::Minus => RuntimeValue::F64(lhs - rhs),
+            IdentKind::Mult => RuntimeValue::F64(lhs * rhs),
+            IdentKind::Div => RuntimeValue::F64(lhs / rhs),
+            _ => unreachable!("{:?} is not an arithmetic builtin", operator),
+        },
+        _ => unreachable!("operands of different types passed the type checker"),
+    };
+
+    Ok(value)
+}
+
+// wraps around at the width of the type, as in
+// `Codegen::build_shift_amount`
+fn shift_amount(amount: i128, ty: &Type) -> u32 {
+    let bits = ty.bit_width().unwrap() as i128;
+    (amount & (bits - 1)) as u32
+}
+
+/// Compares two operands of the same type. Comparisons with NaN are
+/// false, except for `!=`.
+pub fn comparison(operator: &IdentKind, lhs: &RuntimeValue, rhs: &RuntimeValue) -> bool {
+    match (lhs, rhs) {
+        (RuntimeValue::Int(lhs, _), RuntimeValue::Int(rhs, _)) => compare(operator, lhs, rhs),
+        (RuntimeValue::F32(lhs), RuntimeValue::F32(rhs)) => compare(operator, lhs, rhs),
+        (RuntimeValue::F64(lhs), RuntimeValue::F64(rhs)) => compare(operator, lhs, rhs),
+        (RuntimeValue::Bool(lhs), RuntimeValue::Bool(rhs)) => compare(operator, lhs, rhs),
+        _ => unreachable!("operands of different types passed the type checker"),
+    }
+}
+
+fn compare<T: PartialOrd>(operator: &IdentKind, lhs: T, rhs: T) -> bool {
+    match operator {
+        IdentKind::Greater => lhs > rhs,
+        IdentKind::Smaller => lhs < rhs,
+        IdentKind::GreaterEqual => lhs >= rhs,
+        IdentKind::SmallerEqual => lhs <= rhs,
+        IdentKind::Equal => lhs == rhs,
+        IdentKind::NotEqual => lhs != rhs,
+        _ => unreachable!("{:?} is not a comparison builtin", operator),
+    }
+}
+
+/// Converts a number to another numeric type. Integers keep their low
+/// bits, and floats are converted to integers as in
+/// `Codegen::build_float_to_int`.
+pub fn convert(value: RuntimeValue, to: &Type) -> RuntimeValue {
+    match (value, to) {
+        (RuntimeValue::Int(val, _), Type::F32) => RuntimeValue::F32(val as f32),
+        (RuntimeValue::Int(val, _), Type::F64) => RuntimeValue::F64(val as f64),
+        (RuntimeValue::Int(val, _), _) => RuntimeValue::Int(wrap(val, to), to.clone()),
+        (RuntimeValue::F32(val), Type::F32) => RuntimeValue::F32(val),
+        (RuntimeValue::F32(val), Type::F64) => RuntimeValue::F64(val as f64),
+        (RuntimeValue::F32(val), _) => RuntimeValue::Int(saturate(val as f64, to), to.clone()),
+        (RuntimeValue::F64(val), Type::F32) => RuntimeValue::F32(val as f32),
+        (RuntimeValue::F64(val), Type::F64) => RuntimeValue::F64(val),
+        (RuntimeValue::F64(val), _) => RuntimeValue::Int(saturate(val, to), to.clone()),
+        (value, _) => unreachable!("conversion of {:?} passed the type checker", value),
+    }
+}
+
+// `as` rounds towards zero, saturates at the bounds of i128 and turns
+// NaN into 0
+fn saturate(val: f64, ty: &Type) -> i128 {
+    let (min, max) = ty.int_bounds().unwrap();
+    (val as i128).max(min).min(max)
+}
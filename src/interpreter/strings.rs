@@ -0,0 +1,54 @@
+use super::interpreter::Interpreter;
+use super::value::RuntimeValue;
+use crate::diagnostics::Diagnostic;
+use crate::tispc_lexer::IdentKind;
+use crate::tispc_parser::Expr;
+use crate::typeck::Type;
+
+impl<'a, 'e> Interpreter<'a, 'e> {
+    /// eval_string_builtin
+    ///
+    /// Runs `str-concat`, `str-len`, `substr` or `str=`. Lengths and
+    /// positions count bytes, and positions out of range are clamped
+    /// to the string, as in compiled programs.
+    pub fn eval_string_builtin(
+        &mut self,
+        builtin: &IdentKind,
+        args: &'e [Expr<'a>],
+    ) -> Result<RuntimeValue, Diagnostic> {
+        // operands are trusted as in `Codegen::compile_string_builtin`
+        let mut operands = Vec::new();
+        for arg in args {
+            operands.push(self.eval(arg)?);
+        }
+
+        let value = match builtin {
+            IdentKind::StrConcat => {
+                let bytes: Vec<u8> = operands
+                    .iter()
+                    .flat_map(|operand| operand.as_str().iter().copied())
+                    .collect();
+                RuntimeValue::Str(bytes.into())
+            }
+            IdentKind::StrLen => RuntimeValue::Int(operands[0].as_str().len() as i128, Type::I64),
+            IdentKind::Substr => {
+                let string = operands[0].as_str();
+                let length = string.len() as i128;
+                let start = clamp(operands[1].as_int(), 0, length);
+                let count = clamp(operands[2].as_int(), 0, length - start);
+
+                let start = start as usize;
+                RuntimeValue::Str(string[start..start + count as usize].into())
+            }
+            IdentKind::StrEqual => RuntimeValue::Bool(operands[0].as_str() == operands[1].as_str()),
+            _ => unreachable!("{:?} is not a string builtin", builtin),
+        };
+
+        Ok(value)
+    }
+}
+
+/// `value` limited to `low..=high`
+fn clamp(value: i128, low: i128, high: i128) -> i128 {
+    value.max(low).min(high)
+}
@@ -0,0 +1,97 @@
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::tispc_lexer::Span;
+use crate::typeck::{Substitution, Type};
+
+/// Value of an expression while the interpreter runs a program.
+/// Integers of every type are held in an `i128`, and always kept
+/// within the range of their type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeValue {
+    Int(i128, Type),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    // UTF-8 bytes, counted and sliced by byte like the strings of
+    // compiled programs
+    Str(Rc<[u8]>),
+    Unit,
+    Closure(Rc<Closure>),
+}
+
+/// Function value made by running a `fn`: which `fn` it is, and the
+/// values of the variables it captured at that point
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closure {
+    // span of the `fn`
+    pub span: Span,
+    // in the order of `Lambda::captures`
+    pub captures: Vec<RuntimeValue>,
+    // types of the generic function instance the `fn` ran in
+    pub substitution: Substitution,
+}
+
+impl RuntimeValue {
+    /// Value a variable declared without one starts off with
+    pub fn zero(ty: &Type) -> RuntimeValue {
+        match ty {
+            Type::F32 => RuntimeValue::F32(0.0),
+            Type::F64 => RuntimeValue::F64(0.0),
+            Type::Bool => RuntimeValue::Bool(false),
+            Type::Str => RuntimeValue::Str(Rc::from(&b""[..])),
+            Type::Unit | Type::Fn(..) | Type::Var(_) => RuntimeValue::Unit,
+            _ => RuntimeValue::Int(0, ty.clone()),
+        }
+    }
+
+    pub fn as_bool(&self) -> bool {
+        match self {
+            RuntimeValue::Bool(val) => *val,
+            _ => unreachable!("{:?} passed the type checker as a bool", self),
+        }
+    }
+
+    pub fn as_int(&self) -> i128 {
+        match self {
+            RuntimeValue::Int(val, _) => *val,
+            _ => unreachable!("{:?} passed the type checker as an integer", self),
+        }
+    }
+
+    pub fn as_str(&self) -> &[u8] {
+        match self {
+            RuntimeValue::Str(bytes) => bytes,
+            _ => unreachable!("{:?} passed the type checker as a string", self),
+        }
+    }
+
+    /// Appends the value to `out` the way `print` shows it, which is
+    /// how printf formats it in a compiled program
+    pub fn print(&self, out: &mut Vec<u8>) {
+        match self {
+            RuntimeValue::Int(val, _) => write!(out, "{}", val).unwrap(),
+            RuntimeValue::F32(val) => print_float(*val as f64, out),
+            RuntimeValue::F64(val) => print_float(*val, out),
+            RuntimeValue::Bool(true) => out.extend_from_slice(b"true"),
+            RuntimeValue::Bool(false) => out.extend_from_slice(b"false"),
+            RuntimeValue::Str(bytes) => out.extend_from_slice(bytes),
+            RuntimeValue::Unit | RuntimeValue::Closure(_) => {
+                unreachable!("{:?} passed the type checker as printable", self)
+            }
+        }
+    }
+}
+
+// like printf's `%f`
+fn print_float(val: f64, out: &mut Vec<u8>) {
+    let sign = if val.is_sign_negative() { "-" } else { "" };
+
+    if val.is_nan() {
+        write!(out, "{}nan", sign).unwrap();
+    } else if val.is_infinite() {
+        write!(out, "{}inf", sign).unwrap();
+    } else {
+        write!(out, "{:.6}", val).unwrap();
+    }
+}
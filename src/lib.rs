@@ -0,0 +1,19 @@
+//! The Tisp compiler, as a library for the `tispc` binary and its tests
+
+#[cfg(not(any(feature = "llvm", feature = "interpreter")))]
+compile_error!("tispc needs the `llvm` feature, the `interpreter` feature or both");
+
+#[cfg(feature = "llvm")]
+pub mod codegen;
+pub mod diagnostics;
+#[cfg(feature = "llvm")]
+pub mod emit;
+pub mod formatter;
+#[cfg(feature = "interpreter")]
+pub mod interpreter;
+pub mod lsp;
+#[cfg(feature = "llvm")]
+pub mod repl;
+pub mod tispc_lexer;
+pub mod tispc_parser;
+pub mod typeck;
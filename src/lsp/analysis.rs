@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::mem;
+
+use crate::diagnostics::Diagnostics;
+use crate::tispc_lexer::{
+    get_token_stream, Ident, IdentKind, LiteralKind, Span, Token, TokenKind, Value,
+};
+use crate::tispc_parser::{generate_expression_tree, Expr};
+use crate::typeck::{Scopes, Type, TypeChecker, TypeTable};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DefinitionKind {
+    Variable,
+    Parameter,
+    Function,
+}
+
+/// Name bound by a `let`, a parameter or a `defn`
+#[derive(Debug, Clone)]
+pub struct Definition<'a> {
+    pub name: &'a str,
+    // span of the name where it's bound
+    pub span: Span,
+    pub kind: DefinitionKind,
+    // None if the program didn't type check
+    pub ty: Option<Type>,
+}
+
+/// How a token is highlighted. The order matches the legend the
+/// server sends to the editor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Highlight {
+    Keyword,
+    Function,
+    Variable,
+    Parameter,
+    Type,
+    Number,
+    String,
+    Comment,
+    Operator,
+}
+
+/// Analysis
+///
+/// What the compiler front end knows about one document: its tokens,
+/// its diagnostics, the types of its expressions and which names
+/// refer to which definitions.
+pub struct Analysis<'a> {
+    pub tokens: Vec<Token<'a>>,
+    pub diagnostics: Diagnostics,
+    pub types: TypeTable,
+    pub definitions: Vec<Definition<'a>>,
+    // every definition and use of a name, with the index of the
+    // definition in `definitions`
+    names: HashMap<Span, usize>,
+    // kept to show types the way diagnostics and the REPL do
+    checker: TypeChecker<'a>,
+}
+
+/// analyze
+///
+/// Runs the lexer, parser and type checker over a document. Like the
+/// compiler, it doesn't type check a document that failed to parse,
+/// so that one mistake isn't reported again as a type error.
+pub fn analyze<'a>(source: &'a str) -> Analysis<'a> {
+    let mut diagnostics = Diagnostics::new();
+    let tokens = get_token_stream(source, &mut diagnostics);
+    let expression_tree = generate_expression_tree(tokens.clone(), &mut diagnostics);
+
+    let mut checker = TypeChecker::new();
+    let types = if diagnostics.has_errors() {
+        TypeTable::new()
+    } else {
+        checker.check_program(&expression_tree, &mut diagnostics)
+    };
+
+    let mut resolver = Resolver {
+        types: &types,
+        definitions: Vec::new(),
+        names: HashMap::new(),
+        variables: Scopes::new(),
+        functions: HashMap::new(),
+    };
+    resolver.resolve_program(&expression_tree);
+    let (definitions, names) = (resolver.definitions, resolver.names);
+
+    Analysis {
+        tokens,
+        diagnostics,
+        types,
+        definitions,
+        names,
+        checker,
+    }
+}
+
+impl<'a> Analysis<'a> {
+    /// Token under the cursor at `offset`. A cursor right after a
+    /// name, e.g. before a closing parenthesis, is on that name.
+    pub fn token_at(&self, offset: usize) -> Option<&Token<'a>> {
+        let mut touching = self
+            .tokens
+            .iter()
+            .filter(|token| token.span.start <= offset && offset <= token.span.end);
+
+        let first = touching.next()?;
+        match touching.next() {
+            Some(second) if self.names.contains_key(&second.span) => Some(second),
+            _ => Some(first),
+        }
+    }
+
+    /// Definition of the name under the cursor, or of the definition
+    /// the cursor is on
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition<'a>> {
+        let token = self.token_at(offset)?;
+        self.definition_of(token.span)
+    }
+
+    /// Definition of the name at exactly `span`
+    pub fn definition_of(&self, span: Span) -> Option<&Definition<'a>> {
+        self.names.get(&span).map(|index| &self.definitions[*index])
+    }
+
+    /// Type of the name or literal under the cursor, and the span of
+    /// that token
+    pub fn hover_at(&self, offset: usize) -> Option<(Span, String)> {
+        let token = self.token_at(offset)?;
+        let text = match (self.definition_of(token.span), &token.kind) {
+            (Some(definition), _) => {
+                let ty = self.describe(definition.ty.as_ref()?);
+                format!("{}: {}", definition.name, ty)
+            }
+            (None, TokenKind::Literal(_)) => self.describe(self.types.get(token.span)?),
+            _ => return None,
+        };
+        Some((token.span, text))
+    }
+
+    /// Renders a type of this document, with its type variables
+    /// named `'a`, `'b`, ... and numbers of unknown type shown as
+    /// `{number}`, rather than by their internal numbers
+    pub fn describe(&self, ty: &Type) -> String {
+        self.checker.describe(ty)
+    }
+
+    /// Spans of every use of `definition`, in the order they appear,
+    /// including the definition itself
+    pub fn references(&self, definition: &Definition) -> Vec<Span> {
+        let mut spans: Vec<Span> = self
+            .names
+            .iter()
+            .filter(|(_, index)| self.definitions[**index].span == definition.span)
+            .map(|(span, _)| *span)
+            .collect();
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    /// How every token that should be highlighted is highlighted, in
+    /// the order of the tokens
+    pub fn highlights(&self) -> Vec<(Span, Highlight)> {
+        let mut highlights = Vec::new();
+
+        for token in self.tokens.iter() {
+            let highlight = match &token.kind {
+                TokenKind::OpenParen | TokenKind::CloseParen => continue,
+                TokenKind::Ident(IdentKind::Variable) => match self.definition_of(token.span) {
+                    Some(definition) => match definition.kind {
+                        DefinitionKind::Variable => Highlight::Variable,
+                        DefinitionKind::Parameter => Highlight::Parameter,
+                        DefinitionKind::Function => Highlight::Function,
+                    },
+                    None => match &token.value {
+                        Some(Value::String(name)) if Type::from_name(name).is_some() => {
+                            Highlight::Type
+                        }
+                        _ => Highlight::Variable,
+                    },
+                },
+                TokenKind::Ident(kind) if kind.is_special_form() => Highlight::Keyword,
+                TokenKind::Ident(_) => Highlight::Function,
+                TokenKind::Literal(LiteralKind::Number) => Highlight::Number,
+                TokenKind::Literal(LiteralKind::String) => Highlight::String,
+                TokenKind::Literal(LiteralKind::Boolean) => Highlight::Keyword,
+                TokenKind::Comment(_) | TokenKind::Discard => Highlight::Comment,
+                _ => Highlight::Operator,
+            };
+
+            highlights.push((token.span, highlight));
+        }
+
+        highlights
+    }
+}
+
+/// Finds the definition every name refers to, following the same
+/// scoping rules as the type checker
+struct Resolver<'a, 't> {
+    types: &'t TypeTable,
+    definitions: Vec<Definition<'a>>,
+    names: HashMap<Span, usize>,
+    variables: Scopes<'a, usize>,
+    functions: HashMap<&'a str, usize>,
+}
+
+impl<'a, 't> Resolver<'a, 't> {
+    fn resolve_program(&mut self, expression_tree: &[Expr<'a>]) {
+        // top-level functions can be called before their definition
+        for expression in expression_tree {
+            if let Expr::Defn {
+                name,
+                name_span,
+                span,
+                ..
+            } = expression
+            {
+                self.define_function(name, *name_span, *span);
+            }
+        }
+
+        for expression in expression_tree {
+            self.resolve(expression);
+        }
+    }
+
+    fn resolve(&mut self, expr: &Expr<'a>) {
+        match expr {
+            Expr::Constant(..) => (),
+            Expr::Builtin(
+                Ident {
+                    kind: IdentKind::Variable,
+                    value: Some(Value::String(name)),
+                },
+                span,
+            ) => {
+                let definition = self.variables.get(name).copied();
+                self.refer(*span, definition);
+            }
+            Expr::Builtin(..) => (),
+            Expr::Call(head, args, _) => {
+                match &**head {
+                    // resolved as in `TypeChecker::infer_call`
+                    Expr::Builtin(
+                        Ident {
+                            kind: IdentKind::Variable,
+                            value: Some(Value::String(name)),
+                        },
+                        span,
+                    ) if self.variables.get(name).is_none() => {
+                        let definition = self.functions.get(name).copied();
+                        self.refer(*span, definition);
+                    }
+                    head => self.resolve(head),
+                }
+                self.resolve_all(args);
+            }
+            Expr::Let {
+                name,
+                name_span,
+                value,
+                span,
+                ..
+            } => {
+                if let Some(value) = value {
+                    self.resolve(value);
+                }
+                let ty = self.types.get(*span).cloned();
+                let index = self.define(name, *name_span, DefinitionKind::Variable, ty);
+                self.variables.declare(name, index);
+            }
+            Expr::Set {
+                name,
+                name_span,
+                value,
+                ..
+            } => {
+                self.resolve(value);
+                let definition = self.variables.get(name).copied();
+                self.refer(*name_span, definition);
+            }
+            Expr::Do { body, .. } => self.in_scope(|resolver| resolver.resolve_all(body)),
+            Expr::While {
+                condition, body, ..
+            }
+            | Expr::When {
+                condition, body, ..
+            } => {
+                self.resolve(condition);
+                self.in_scope(|resolver| resolver.resolve_all(body));
+            }
+            Expr::Print { args, .. } => self.resolve_all(args),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.resolve(condition);
+                self.in_scope(|resolver| resolver.resolve(then_branch));
+                if let Some(else_branch) = else_branch {
+                    self.in_scope(|resolver| resolver.resolve(else_branch));
+                }
+            }
+            Expr::Cond { clauses, .. } => {
+                for clause in clauses {
+                    if let Some(condition) = &clause.condition {
+                        self.resolve(condition);
+                    }
+                    self.in_scope(|resolver| resolver.resolve_all(&clause.body));
+                }
+            }
+            Expr::Cast { value, .. } => self.resolve(value),
+            Expr::Defn {
+                name,
+                name_span,
+                params,
+                body,
+                span,
+                ..
+            } => {
+                self.define_function(name, *name_span, *span);
+                let param_types = self.param_types(*span);
+
+                // functions only see their parameters
+                let caller_variables = mem::take(&mut self.variables);
+                for (i, param) in params.iter().enumerate() {
+                    let ty = param_types.get(i).cloned();
+                    let index = self.define(param.name, param.span, DefinitionKind::Parameter, ty);
+                    self.variables.declare(param.name, index);
+                }
+                self.resolve_all(body);
+                self.variables = caller_variables;
+            }
+            Expr::Lambda {
+                params, body, span, ..
+            } => {
+                let param_types = self.param_types(*span);
+                self.in_scope(|resolver| {
+                    for (i, param) in params.iter().enumerate() {
+                        let ty = param_types.get(i).cloned();
+                        let index =
+                            resolver.define(param.name, param.span, DefinitionKind::Parameter, ty);
+                        resolver.variables.declare(param.name, index);
+                    }
+                    resolver.resolve_all(body);
+                });
+            }
+        }
+    }
+
+    /// Parameter types of the function defined at `span`, or none if
+    /// the program didn't type check
+    fn param_types(&self, span: Span) -> Vec<Type> {
+        match self.types.get(span) {
+            Some(Type::Fn(param_types, _)) => param_types.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn resolve_all(&mut self, exprs: &[Expr<'a>]) {
+        for expr in exprs {
+            self.resolve(expr);
+        }
+    }
+
+    fn in_scope(&mut self, f: impl FnOnce(&mut Self)) {
+        self.variables.push();
+        f(self);
+        self.variables.pop();
+    }
+
+    fn define(
+        &mut self,
+        name: &'a str,
+        span: Span,
+        kind: DefinitionKind,
+        ty: Option<Type>,
+    ) -> usize {
+        let index = self.definitions.len();
+        self.definitions.push(Definition {
+            name,
+            span,
+            kind,
+            ty,
+        });
+        self.names.insert(span, index);
+        index
+    }
+
+    fn define_function(&mut self, name: &'a str, name_span: Span, span: Span) {
+        // top-level functions are defined before the program is walked
+        if self.names.contains_key(&name_span) {
+            return;
+        }
+
+        let ty = self.types.get(span).cloned();
+        let index = self.define(name, name_span, DefinitionKind::Function, ty);
+        self.functions.insert(name, index);
+    }
+
+    fn refer(&mut self, span: Span, definition: Option<usize>) {
+        if let Some(definition) = definition {
+            self.names.insert(span, definition);
+        }
+    }
+}
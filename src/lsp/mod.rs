@@ -0,0 +1,5 @@
+mod analysis;
+mod server;
+
+pub use analysis::*;
+pub use server::*;
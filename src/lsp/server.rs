@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationType, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, GotoDefinition, HoverRequest, References, Request as RequestType,
+    SemanticTokensFullRequest,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    DiagnosticRelatedInformation, DiagnosticSeverity, GotoDefinitionParams, GotoDefinitionResponse,
+    Hover, HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent,
+    MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range, ReferenceParams,
+    SemanticToken, SemanticTokenType, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+
+use super::analysis::{analyze, Analysis, DefinitionKind};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::tispc_lexer::{IdentKind, LineCol, LineIndex, Span};
+
+// indexed by `Highlight`
+const LEGEND: [SemanticTokenType; 9] = [
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::STRING,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::OPERATOR,
+];
+
+/// run_lsp
+///
+/// Runs a language server on stdin and stdout until the editor asks
+/// it to shut down.
+pub fn run_lsp() -> Result<(), String> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = serde_json::to_value(capabilities()).unwrap();
+    connection
+        .initialize(capabilities)
+        .map_err(|err| err.to_string())?;
+
+    let mut server = Server {
+        connection: &connection,
+        documents: HashMap::new(),
+    };
+    server.run()?;
+
+    // the writer thread only finishes once the connection is gone
+    drop(connection);
+    io_threads.join().map_err(|err| err.to_string())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        // documents are small enough to be sent whole on every change
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: LEGEND.to_vec(),
+                    token_modifiers: Vec::new(),
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..SemanticTokensOptions::default()
+            },
+        )),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Server
+///
+/// Keeps the text of every open document. Documents are analyzed
+/// again for every request, which is fast enough for programs of the
+/// size Tisp is used for and means nothing can go stale.
+struct Server<'c> {
+    connection: &'c Connection,
+    documents: HashMap<Url, String>,
+}
+
+impl<'c> Server<'c> {
+    fn run(&mut self) -> Result<(), String> {
+        for message in &self.connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    let shutdown = self
+                        .connection
+                        .handle_shutdown(&request)
+                        .map_err(|err| err.to_string())?;
+                    if shutdown {
+                        return Ok(());
+                    }
+
+                    let response = self.handle_request(request);
+                    self.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(&self, message: Message) -> Result<(), String> {
+        self.connection
+            .sender
+            .send(message)
+            .map_err(|err| err.to_string())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => self.dispatch::<GotoDefinition>(request, Server::definition),
+            References::METHOD => self.dispatch::<References>(request, Server::references),
+            HoverRequest::METHOD => self.dispatch::<HoverRequest>(request, Server::hover),
+            Completion::METHOD => self.dispatch::<Completion>(request, Server::completion),
+            SemanticTokensFullRequest::METHOD => {
+                self.dispatch::<SemanticTokensFullRequest>(request, Server::semantic_tokens)
+            }
+            method => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request `{}`", method),
+                )
+            }
+        };
+
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err(message) => Response::new_err(id, ErrorCode::InvalidParams as i32, message),
+        }
+    }
+
+    /// Decodes the parameters of a request of type `R` and encodes
+    /// the result of `handler`
+    fn dispatch<R: RequestType>(
+        &self,
+        request: Request,
+        handler: impl Fn(&Self, R::Params) -> R::Result,
+    ) -> Result<serde_json::Value, String> {
+        let params = serde_json::from_value(request.params).map_err(|err| err.to_string())?;
+        serde_json::to_value(handler(self, params)).map_err(|err| err.to_string())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<(), String> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = decode::<DidOpenTextDocument>(notification)?;
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                self.publish_diagnostics(uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params = decode::<DidChangeTextDocument>(notification)?;
+                let uri = params.text_document.uri;
+                // with full sync, the last change holds the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(uri.clone(), change.text);
+                }
+                self.publish_diagnostics(uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = decode::<DidCloseTextDocument>(notification)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                // clear the diagnostics of the closed document
+                self.publish_diagnostics(uri)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: Url) -> Result<(), String> {
+        let diagnostics = match self.documents.get(&uri) {
+            Some(text) => {
+                let analysis = analyze(text);
+                let index = LineIndex::new(text);
+                analysis
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| lsp_diagnostic(diagnostic, &uri, &index))
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        self.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))
+    }
+
+    /// Runs `f` on the analysis of the document at `uri`, if it's open
+    fn with_analysis<T>(
+        &self,
+        uri: &Url,
+        f: impl FnOnce(&Analysis, &LineIndex) -> Option<T>,
+    ) -> Option<T> {
+        let text = self.documents.get(uri)?;
+        f(&analyze(text), &LineIndex::new(text))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        self.with_analysis(&uri, |analysis, index| {
+            let definition = analysis.definition_at(offset(index, position))?;
+            let location = Location::new(uri.clone(), range(index, definition.span));
+            Some(GotoDefinitionResponse::Scalar(location))
+        })
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let include_declaration = params.context.include_declaration;
+
+        self.with_analysis(&uri, |analysis, index| {
+            let definition = analysis.definition_at(offset(index, position))?;
+            let locations = analysis
+                .references(definition)
+                .into_iter()
+                .filter(|span| include_declaration || *span != definition.span)
+                .map(|span| Location::new(uri.clone(), range(index, span)))
+                .collect();
+            Some(locations)
+        })
+    }
+
+    /// Shows the type of the name or literal under the cursor
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+
+        self.with_analysis(&position.text_document.uri, |analysis, index| {
+            let (span, text) = analysis.hover_at(offset(index, position.position))?;
+
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!("```tisp\n{}\n```", text),
+                }),
+                range: Some(range(index, span)),
+            })
+        })
+    }
+
+    /// Offers every builtin and every name defined in the document;
+    /// the editor narrows them down to the ones matching the word
+    /// being typed
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let uri = params.text_document_position.text_document.uri;
+
+        let mut items: Vec<CompletionItem> = IdentKind::BUILTINS
+            .iter()
+            .map(|kind| CompletionItem {
+                label: kind.name().unwrap().to_string(),
+                kind: Some(if kind.is_special_form() {
+                    CompletionItemKind::KEYWORD
+                } else {
+                    CompletionItemKind::FUNCTION
+                }),
+                detail: Some(String::from("builtin")),
+                ..CompletionItem::default()
+            })
+            .collect();
+
+        let definitions = self.with_analysis(&uri, |analysis, _| {
+            let mut definitions: Vec<CompletionItem> = Vec::new();
+            for definition in analysis.definitions.iter() {
+                if definitions.iter().any(|item| item.label == definition.name) {
+                    continue;
+                }
+                definitions.push(CompletionItem {
+                    label: definition.name.to_string(),
+                    kind: Some(match definition.kind {
+                        DefinitionKind::Function => CompletionItemKind::FUNCTION,
+                        DefinitionKind::Variable | DefinitionKind::Parameter => {
+                            CompletionItemKind::VARIABLE
+                        }
+                    }),
+                    detail: definition.ty.as_ref().map(|ty| analysis.describe(ty)),
+                    ..CompletionItem::default()
+                });
+            }
+            Some(definitions)
+        });
+        items.extend(definitions.unwrap_or_default());
+
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Option<SemanticTokensResult> {
+        let uri = params.text_document.uri;
+
+        self.with_analysis(&uri, |analysis, index| {
+            let mut data: Vec<SemanticToken> = Vec::new();
+            let (mut last_line, mut last_start) = (0, 0);
+
+            for (span, highlight) in analysis.highlights() {
+                // tokens are sent one line at a time, since not every
+                // editor can highlight a token across lines
+                let range = range(index, span);
+                for line in range.start.line..=range.end.line {
+                    let start = if line == range.start.line {
+                        range.start.character
+                    } else {
+                        0
+                    };
+                    let end = if line == range.end.line {
+                        range.end.character
+                    } else {
+                        index.utf16_line_length(line as usize + 1) as u32
+                    };
+                    if end <= start {
+                        continue;
+                    }
+
+                    let delta_start = if line == last_line {
+                        start - last_start
+                    } else {
+                        start
+                    };
+                    data.push(SemanticToken {
+                        delta_line: line - last_line,
+                        delta_start,
+                        length: end - start,
+                        token_type: highlight as u32,
+                        token_modifiers_bitset: 0,
+                    });
+                    last_line = line;
+                    last_start = start;
+                }
+            }
+
+            Some(SemanticTokensResult::Tokens(SemanticTokens {
+                result_id: None,
+                data,
+            }))
+        })
+    }
+}
+
+/// Decodes the parameters of a notification of type `N`
+fn decode<N: NotificationType>(notification: Notification) -> Result<N::Params, String> {
+    serde_json::from_value(notification.params).map_err(|err| err.to_string())
+}
+
+/// LSP position of a byte offset. LSP counts lines from 0 and
+/// characters in UTF-16 code units.
+fn position(index: &LineIndex, offset: usize) -> Position {
+    let LineCol { line, col } = index.utf16_line_col(offset);
+    Position::new(line as u32 - 1, col as u32 - 1)
+}
+
+fn range(index: &LineIndex, span: Span) -> Range {
+    Range::new(position(index, span.start), position(index, span.end))
+}
+
+/// Byte offset of an LSP position
+fn offset(index: &LineIndex, position: Position) -> usize {
+    index.utf16_offset(LineCol {
+        line: position.line as usize + 1,
+        col: position.character as usize + 1,
+    })
+}
+
+/// Converts a compiler diagnostic for the editor. Its primary label
+/// becomes the range, and secondary labels become related locations.
+fn lsp_diagnostic(diagnostic: &Diagnostic, uri: &Url, index: &LineIndex) -> lsp_types::Diagnostic {
+    let mut message = diagnostic.message.clone();
+    let primary = diagnostic.labels.iter().find(|label| label.primary);
+    if let Some(label) = primary {
+        message.push_str(&format!(": {}", label.message));
+    }
+    for note in diagnostic.notes.iter() {
+        message.push_str(&format!("\nnote: {}", note));
+    }
+
+    let related_information = diagnostic
+        .labels
+        .iter()
+        .filter(|label| !label.primary)
+        .map(|label| DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), range(index, label.span)),
+            message: label.message.clone(),
+        })
+        .collect();
+
+    lsp_types::Diagnostic {
+        range: range(
+            index,
+            primary.map_or_else(Default::default, |label| label.span),
+        ),
+        severity: Some(match diagnostic.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Note => DiagnosticSeverity::INFORMATION,
+        }),
+        code: Some(NumberOrString::String(diagnostic.code.to_string())),
+        source: Some(String::from("tispc")),
+        message,
+        related_information: Some(related_information),
+        ..lsp_types::Diagnostic::default()
+    }
+}
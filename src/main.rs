@@ -1,21 +1,47 @@
+#[cfg(feature = "llvm")]
 use std::collections::HashMap;
+#[cfg(feature = "llvm")]
+use std::env;
 use std::fs;
-use std::path::Path;
+#[cfg(feature = "interpreter")]
+use std::io::Write;
+use std::io::{self, Read};
+#[cfg(feature = "llvm")]
+use std::path::{Path, PathBuf};
+use std::process;
+#[cfg(feature = "interpreter")]
+use std::thread;
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
+#[cfg(feature = "llvm")]
 use inkwell::context::Context;
+#[cfg(feature = "llvm")]
+use inkwell::module::Module;
+#[cfg(feature = "llvm")]
+use inkwell::targets::TargetMachine;
 
-mod codegen;
-use codegen::Codegen;
-
-mod tispc_lexer;
-use tispc_lexer::get_token_stream;
-
-mod tispc_parser;
-use tispc_parser::generate_expression_tree;
+#[cfg(feature = "llvm")]
+use tispc::codegen::{Codegen, GC_STATS_VAR};
+use tispc::diagnostics::{render_diagnostics, Diagnostics};
+#[cfg(feature = "llvm")]
+use tispc::emit::{
+    available_targets, default_output_path, optimize, run_jit, set_target, target_machine,
+    write_output, EmitKind, OptLevel, Optimizations, TargetOptions, PASS_NAMES,
+};
+use tispc::formatter::{format_source, DEFAULT_WIDTH};
+#[cfg(feature = "interpreter")]
+use tispc::interpreter::{interpret, STACK_SIZE};
+use tispc::lsp::run_lsp;
+#[cfg(feature = "llvm")]
+use tispc::repl::run_repl;
+use tispc::tispc_lexer::get_token_stream;
+use tispc::tispc_parser::{generate_expression_tree, Expr};
+#[cfg(feature = "llvm")]
+use tispc::typeck::Scopes;
+use tispc::typeck::{check_types, TypeTable};
 
 fn main() {
-    let matches = App::new("tispc")
+    let app = App::new("tispc")
         .arg(
             Arg::with_name("input")
                 .short("i")
@@ -23,64 +49,616 @@ fn main() {
                 .takes_value(true)
                 .help("Tisp file to compile"),
         )
+        .arg(
+            Arg::with_name("debug")
+                .short("d")
+                .long("debug")
+                .takes_value(false)
+                .global(true)
+                .help("Print debug information (token stream and expression tree)"),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Runs a Tisp file right away, without writing any files")
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .index(1)
+                        .help("Tisp file to run"),
+                )
+                .arg(
+                    Arg::with_name("interpret")
+                        .long("interpret")
+                        .takes_value(false)
+                        .help("Run the program with the interpreter rather than compiling it with LLVM"),
+                )
+                .arg(
+                    Arg::with_name("gc-stats")
+                        .long("gc-stats")
+                        .takes_value(false)
+                        .help("Print how many garbage collections the program ran and the bytes they freed when it exits"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Formats Tisp files in place, or standard input to standard output if no files are given")
+                .arg(
+                    Arg::with_name("files")
+                        .multiple(true)
+                        .index(1)
+                        .help("Tisp files to format"),
+                )
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .takes_value(false)
+                        .help("Write nothing, and fail if any file isn't formatted"),
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .long("width")
+                        .takes_value(true)
+                        .value_name("COLUMNS")
+                        .help("Maximum width of a line (defaults to 80)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lsp")
+                .about("Runs a language server for editors, speaking LSP over stdin and stdout"),
+        );
+    let matches = with_llvm_args(app).get_matches();
+
+    if let Some(fmt_matches) = matches.subcommand_matches("fmt") {
+        process::exit(format_files(fmt_matches));
+    }
+
+    if matches.subcommand_matches("lsp").is_some() {
+        if let Err(err) = run_lsp() {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if matches.subcommand_matches("repl").is_some() {
+        process::exit(start_repl());
+    }
+
+    if let Some(run_matches) = matches.subcommand_matches("run") {
+        // without LLVM, the interpreter is the only way to run a program
+        if run_matches.is_present("interpret") || cfg!(not(feature = "llvm")) {
+            if run_matches.is_present("gc-stats") {
+                eprintln!("error: --gc-stats needs a compiled program; the interpreter has no garbage collector");
+                process::exit(1);
+            }
+            process::exit(interpret_file(run_matches));
+        }
+        process::exit(jit_file(run_matches));
+    }
+
+    if let Some(what) = matches.value_of("print") {
+        process::exit(print_info(what));
+    }
+
+    process::exit(compile_file(&matches));
+}
+
+/// Adds the options and commands that need LLVM
+#[cfg(feature = "llvm")]
+fn with_llvm_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Where to write the output (defaults to the input path with the extension of --emit)"),
+        )
+        .arg(
+            Arg::with_name("emit")
+                .long("emit")
+                .takes_value(true)
+                .possible_values(&EmitKind::NAMES)
+                .default_value("exe")
+                .help("Kind of output to write"),
+        )
+        .arg(
+            Arg::with_name("target")
+                .long("target")
+                .takes_value(true)
+                .value_name("TRIPLE")
+                .help("Target to compile for, e.g. aarch64-unknown-linux-gnu (defaults to this machine)"),
+        )
+        .arg(
+            Arg::with_name("cpu")
+                .long("cpu")
+                .takes_value(true)
+                .value_name("CPU")
+                .help("CPU to generate code for (defaults to this machine's, or a generic one for other targets)"),
+        )
+        .arg(
+            Arg::with_name("features")
+                .long("features")
+                .takes_value(true)
+                .value_name("+FEATURE,-FEATURE,...")
+                .allow_hyphen_values(true)
+                .help("CPU features to turn on or off, e.g. +neon"),
+        )
+        .arg(
+            Arg::with_name("print")
+                .long("print")
+                .takes_value(true)
+                .possible_values(&["targets"])
+                .help("Print information about the compiler instead of compiling, e.g. the supported targets"),
+        )
+        .arg(
+            Arg::with_name("debug-info")
+                .short("g")
+                .takes_value(false)
+                .help("Include DWARF debug info in the output, for debuggers such as gdb and lldb"),
+        )
         .arg(
             Arg::with_name("emit-llvm")
                 .short("e")
                 .long("emit-llvm")
                 .takes_value(false)
+                .global(true)
                 .help("emits the llvm IR to console"),
         )
         .arg(
-            Arg::with_name("debug")
-                .short("d")
-                .long("debug")
-                .takes_value(false)
-                .help("Print debug information (token stream and expression tree)"),
+            Arg::with_name("opt-level")
+                .short("O")
+                .takes_value(true)
+                .value_name("LEVEL")
+                .possible_values(&OptLevel::NAMES)
+                .global(true)
+                .help("Optimization level, e.g. -O2 (defaults to 0)"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("passes")
+                .long("passes")
+                .takes_value(true)
+                .value_name("PASS,...")
+                .use_delimiter(true)
+                .possible_values(&PASS_NAMES)
+                .global(true)
+                .help("LLVM passes to run, in order, after the pipeline of the -O level"),
+        )
+        .subcommand(
+            SubCommand::with_name("repl")
+                .about("Starts an interactive session that evaluates expressions as they are entered"),
+        )
+}
 
-    let filename = matches
-        .value_of("input")
-        .expect("Please enter the input file to compile");
+#[cfg(not(feature = "llvm"))]
+fn with_llvm_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app
+}
+
+#[cfg(feature = "llvm")]
+fn start_repl() -> i32 {
+    match run_repl() {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            1
+        }
+    }
+}
+
+/// compile_file
+///
+/// Compiles the file given with `-i` into the output chosen with
+/// `--emit` and `-o`, and returns the exit code.
+#[cfg(feature = "llvm")]
+fn compile_file(matches: &ArgMatches) -> i32 {
+    let filename = match matches.value_of("input") {
+        Some(filename) => filename,
+        None => {
+            eprintln!("error: please enter the input file to compile");
+            return 1;
+        }
+    };
 
     let emit_llvm = matches.is_present("emit-llvm");
     let debug_flag = matches.is_present("debug");
+    let debug_info = matches.is_present("debug-info");
+    let emit_kind = EmitKind::from_name(matches.value_of("emit").unwrap()).unwrap();
+    let optimizations = optimizations(matches);
+    let target = TargetOptions {
+        triple: matches.value_of("target").map(String::from),
+        cpu: matches.value_of("cpu").map(String::from),
+        features: matches.value_of("features").map(String::from),
+    };
+    let machine = match target_machine(&target, optimizations.level.llvm_level()) {
+        Ok(machine) => machine,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return 1;
+        }
+    };
+    let out_file = match matches.value_of("output") {
+        Some(path) => PathBuf::from(path),
+        None => default_output_path(Path::new(filename), emit_kind, &machine),
+    };
+
+    let raw_code = read_source(filename);
+
+    let context = Context::create();
+    let module = compile(
+        &context,
+        &machine,
+        filename,
+        &raw_code,
+        debug_flag,
+        debug_info,
+        emit_llvm,
+        &optimizations,
+    );
+
+    if let Err(err) = write_output(&module, emit_kind, &out_file, &machine) {
+        eprintln!("error: {}", err);
+        return 1;
+    }
+
+    0
+}
+
+/// Prints what `--print` asks for, and returns the exit code
+#[cfg(feature = "llvm")]
+fn print_info(what: &str) -> i32 {
+    match what {
+        "targets" => {
+            println!("Targets supported by the linked LLVM:");
+            for (name, description) in available_targets() {
+                println!("  {:<12} - {}", name, description);
+            }
+            0
+        }
+        _ => unreachable!("clap only accepts known values of --print"),
+    }
+}
+
+/// Compiles the file given to `run` and runs it with the JIT, and
+/// returns its exit code
+#[cfg(feature = "llvm")]
+fn jit_file(matches: &ArgMatches) -> i32 {
+    let filename = matches.value_of("file").unwrap();
+    let raw_code = read_source(filename);
+
+    let optimizations = optimizations(matches);
+    let opt_level = optimizations.level.llvm_level();
+    // the JIT runs the program on this machine
+    let machine = match target_machine(&TargetOptions::default(), opt_level) {
+        Ok(machine) => machine,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return 1;
+        }
+    };
+
+    let context = Context::create();
+    let module = compile(
+        &context,
+        &machine,
+        filename,
+        &raw_code,
+        matches.is_present("debug"),
+        false,
+        matches.is_present("emit-llvm"),
+        &optimizations,
+    );
+
+    // the program runs in this process, so it sees our environment
+    if matches.is_present("gc-stats") {
+        env::set_var(GC_STATS_VAR, "1");
+    }
+
+    match run_jit(&module, opt_level) {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            1
+        }
+    }
+}
+
+// stand-ins for the commands that need LLVM, in builds without it
+
+#[cfg(not(feature = "llvm"))]
+fn start_repl() -> i32 {
+    without_llvm("the REPL")
+}
+
+#[cfg(not(feature = "llvm"))]
+fn compile_file(_matches: &ArgMatches) -> i32 {
+    without_llvm("compiling files")
+}
+
+#[cfg(not(feature = "llvm"))]
+fn print_info(_what: &str) -> i32 {
+    without_llvm("--print")
+}
+
+#[cfg(not(feature = "llvm"))]
+fn jit_file(_matches: &ArgMatches) -> i32 {
+    without_llvm("the JIT")
+}
+
+#[cfg(not(feature = "llvm"))]
+fn without_llvm(what: &str) -> i32 {
+    eprintln!(
+        "error: {} needs LLVM, which this build of tispc was built without; use `tispc run` to interpret programs",
+        what
+    );
+    1
+}
+
+/// interpret_file
+///
+/// Runs the file given to `run` with the interpreter, and returns the
+/// exit code: 0, or 1 if the program stopped at a runtime error.
+#[cfg(feature = "interpreter")]
+fn interpret_file(matches: &ArgMatches) -> i32 {
+    let filename = matches.value_of("file").unwrap().to_string();
+    let raw_code = read_source(&filename);
+    let debug_flag = matches.is_present("debug");
+
+    // recursion in the program is recursion in the interpreter
+    let interpreter = thread::Builder::new()
+        .name(String::from("interpreter"))
+        .stack_size(STACK_SIZE)
+        .spawn(move || run_interpreter(&filename, &raw_code, debug_flag));
+
+    match interpreter.map(|interpreter| interpreter.join()) {
+        Ok(Ok(exit_code)) => exit_code,
+        // the panic has been reported already
+        Ok(Err(_)) => 101,
+        Err(err) => {
+            eprintln!("error: could not start the interpreter: {}", err);
+            1
+        }
+    }
+}
+
+#[cfg(feature = "interpreter")]
+fn run_interpreter(filename: &str, raw_code: &str, debug_flag: bool) -> i32 {
+    let mut diagnostics = Diagnostics::new();
+    let (expression_tree, types) = check(filename, raw_code, debug_flag, &mut diagnostics);
+
+    // report any warnings
+    if !diagnostics.is_empty() {
+        eprint!("{}", render_diagnostics(&diagnostics, filename, raw_code));
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let result = interpret(&expression_tree, &types, &mut out);
+    // what the program printed goes before any error
+    out.flush().ok();
+
+    match result {
+        Ok(()) => 0,
+        Err(diagnostic) => {
+            let mut diagnostics = Diagnostics::new();
+            diagnostics.push(diagnostic);
+            eprint!("{}", render_diagnostics(&diagnostics, filename, raw_code));
+            1
+        }
+    }
+}
+
+#[cfg(not(feature = "interpreter"))]
+fn interpret_file(_matches: &ArgMatches) -> i32 {
+    eprintln!("error: this build of tispc was built without the interpreter");
+    1
+}
 
-    let raw_code = fs::read_to_string(filename).expect("Something went wrong reading the file");
-    let token_stream = get_token_stream(&raw_code);
+fn read_source(filename: &str) -> String {
+    match fs::read_to_string(filename) {
+        Ok(raw_code) => raw_code,
+        Err(err) => {
+            eprintln!("error: could not read {}: {}", filename, err);
+            process::exit(1);
+        }
+    }
+}
+
+/// format_files
+///
+/// Formats the files given to `fmt`, or standard input, and returns
+/// the exit code: 1 if any file has errors, or with `--check`, if any
+/// file isn't formatted.
+fn format_files(matches: &ArgMatches) -> i32 {
+    let width = match matches.value_of("width") {
+        None => DEFAULT_WIDTH,
+        Some(width) => match width.parse() {
+            Ok(width) if width > 0 => width,
+            _ => {
+                eprintln!("error: invalid width `{}`", width);
+                return 1;
+            }
+        },
+    };
+    let check = matches.is_present("check");
+
+    let filenames: Vec<&str> = match matches.values_of("files") {
+        Some(filenames) => filenames.collect(),
+        None => {
+            let mut raw_code = String::new();
+            if let Err(err) = io::stdin().read_to_string(&mut raw_code) {
+                eprintln!("error: could not read standard input: {}", err);
+                return 1;
+            }
+
+            return match format_source(&raw_code, width) {
+                Ok(formatted) if check => (formatted != raw_code) as i32,
+                Ok(formatted) => {
+                    print!("{}", formatted);
+                    0
+                }
+                Err(diagnostics) => {
+                    eprint!("{}", render_diagnostics(&diagnostics, "<stdin>", &raw_code));
+                    1
+                }
+            };
+        }
+    };
+
+    let mut exit_code = 0;
+    for filename in filenames {
+        let raw_code = read_source(filename);
+        let formatted = match format_source(&raw_code, width) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                eprint!("{}", render_diagnostics(&diagnostics, filename, &raw_code));
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        if formatted == raw_code {
+            continue;
+        }
+
+        if check {
+            println!("{} is not formatted", filename);
+            exit_code = 1;
+        } else if let Err(err) = fs::write(filename, formatted) {
+            eprintln!("error: could not write {}: {}", filename, err);
+            exit_code = 1;
+        }
+    }
+
+    exit_code
+}
+
+#[cfg(feature = "llvm")]
+/// Optimizations chosen with `-O` and `--passes`
+fn optimizations(matches: &ArgMatches) -> Optimizations {
+    let level = match matches.value_of("opt-level") {
+        Some(name) => OptLevel::from_name(name).unwrap(),
+        None => OptLevel::O0,
+    };
+    let passes = match matches.values_of("passes") {
+        Some(passes) => passes.map(String::from).collect(),
+        None => Vec::new(),
+    };
+
+    Optimizations { level, passes }
+}
+
+/// check
+///
+/// Runs the front end of the compiler on `raw_code`: lexes, parses and
+/// type checks it. If the program has errors, they are printed and
+/// the compiler exits. Warnings are left in `diagnostics`.
+fn check<'a>(
+    filename: &str,
+    raw_code: &'a str,
+    debug_flag: bool,
+    diagnostics: &mut Diagnostics,
+) -> (Vec<Expr<'a>>, TypeTable) {
+    let token_stream = get_token_stream(raw_code, diagnostics);
 
-    let expression_tree = generate_expression_tree(token_stream.clone());
+    let expression_tree = generate_expression_tree(token_stream.clone(), diagnostics);
 
     if debug_flag {
         println!("\nExpression tree: \n{:?}\n", expression_tree);
     }
 
-    let context = Context::create();
+    // don't type check a program that failed to parse
+    if diagnostics.has_errors() {
+        abort(diagnostics, filename, raw_code);
+    }
+
+    let types = check_types(&expression_tree, diagnostics);
+    if diagnostics.has_errors() {
+        abort(diagnostics, filename, raw_code);
+    }
+
+    (expression_tree, types)
+}
+
+/// compile
+///
+/// Runs every phase of the compiler on `raw_code` and returns the
+/// generated (and optimized) LLVM module for `machine`, with debug
+/// info if `debug_info` is set. If the program has errors, they are
+/// printed and the compiler exits.
+#[cfg(feature = "llvm")]
+#[allow(clippy::too_many_arguments)]
+fn compile<'ctx>(
+    context: &'ctx Context,
+    machine: &TargetMachine,
+    filename: &str,
+    raw_code: &str,
+    debug_flag: bool,
+    debug_info: bool,
+    emit_llvm: bool,
+    optimizations: &Optimizations,
+) -> Module<'ctx> {
+    let mut diagnostics = Diagnostics::new();
+    let (expression_tree, types) = check(filename, raw_code, debug_flag, &mut diagnostics);
+
     let module = context.create_module("example");
+    set_target(&module, machine);
     let builder = context.create_builder();
     let mut codegen = Codegen {
-        context: &context,
+        context,
         module: &module,
         builder: &builder,
         builtins: &mut HashMap::new(),
-        variables: &mut HashMap::new(),
+        functions: &mut HashMap::new(),
+        variables: &mut Scopes::new(),
+        globals: &HashMap::new(),
+        types: &types,
+        filename,
+        source: raw_code,
+        generics: &mut HashMap::new(),
+        substitution: &mut HashMap::new(),
+        debug_info: None,
+        gc_frame: None,
     };
 
     codegen.init(filename);
-    codegen.generate_llvm_ir(expression_tree.clone());
+    if debug_info {
+        let pointer_size = machine.get_target_data().get_pointer_byte_size(None);
+        codegen.add_debug_info(
+            filename,
+            raw_code,
+            optimizations.level != OptLevel::O0,
+            pointer_size as u64 * 8,
+        );
+    }
+    codegen.generate_llvm_ir(expression_tree.clone(), &mut diagnostics);
 
-    if emit_llvm {
-        println!("{}", codegen.module.print_to_string().to_str().unwrap());
+    if diagnostics.has_errors() {
+        abort(&diagnostics, filename, raw_code);
+    }
+
+    // report any warnings
+    if !diagnostics.is_empty() {
+        eprint!("{}", render_diagnostics(&diagnostics, filename, raw_code));
     }
 
-    codegen.module.verify().expect("Errors were encountered");
+    module.verify().expect("Errors were encountered");
+
+    if let Err(err) = optimize(&module, optimizations) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
 
-    // create output file in the same directory as the input file
-    let parent_dir = Path::new(filename).parent().unwrap();
-    let out_file = parent_dir.join("output.ll");
+    if emit_llvm {
+        println!("{}", module.print_to_string().to_str().unwrap());
+    }
+
+    module
+}
 
-    codegen
-        .module
-        .print_to_file(out_file.to_str().unwrap())
-        .expect("Error printing to file");
+/// Prints every diagnostic collected so far and exits with a failure code
+fn abort(diagnostics: &Diagnostics, filename: &str, raw_code: &str) -> ! {
+    eprint!("{}", render_diagnostics(diagnostics, filename, raw_code));
+    process::exit(1);
 }
@@ -0,0 +1,57 @@
+use std::io;
+
+use inkwell::context::Context;
+
+use super::input::read_input;
+use super::session::Session;
+
+const HELP: &str = "\
+Enter Tisp expressions to evaluate them. Variables and functions are
+kept for the rest of the session.
+
+Commands:
+  :type <expr>     show the type of an expression without running it
+  :ir [<expr>...]  show the LLVM IR of expressions, or of the last input
+  :tokens <text>   show the tokens of some text
+  :help            show this message
+  :quit            leave the REPL (as does end of input, e.g. Ctrl-D)";
+
+/// run_repl
+///
+/// Runs an interactive session on stdin until it is closed or the
+/// user enters `:quit`.
+pub fn run_repl() -> Result<(), String> {
+    let context = Context::create();
+    let mut session = Session::new(&context)?;
+
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+
+    println!("Tisp REPL. Enter :help for a list of commands.");
+
+    while let Some(input) = read_input(&mut reader).map_err(|err| err.to_string())? {
+        let input = input.trim();
+        if !input.starts_with(':') {
+            session.eval(input);
+            continue;
+        }
+
+        let (command, args) = match input.find(char::is_whitespace) {
+            Some(index) => (&input[..index], &input[index..]),
+            None => (input, ""),
+        };
+        match command {
+            ":type" | ":t" => session.show_type(args),
+            ":ir" => session.show_ir(args),
+            ":tokens" => session.show_tokens(args),
+            ":help" | ":h" => println!("{}", HELP),
+            ":quit" | ":q" => break,
+            _ => eprintln!(
+                "error: unknown command `{}`, enter :help for a list of commands",
+                command
+            ),
+        }
+    }
+
+    Ok(())
+}
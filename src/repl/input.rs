@@ -0,0 +1,92 @@
+use std::io::{self, BufRead, Write};
+
+const PROMPT: &str = "tisp> ";
+const CONTINUATION_PROMPT: &str = "  ... ";
+
+/// read_input
+///
+/// Reads the next input from `reader`. A line that leaves parentheses
+/// open is continued on the following lines until they are closed.
+/// Meta-commands, which start with `:`, are always a single line.
+/// Returns `None` once `reader` is exhausted.
+pub fn read_input(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut input = String::new();
+
+    loop {
+        if input.is_empty() {
+            print!("{}", PROMPT);
+        } else {
+            print!("{}", CONTINUATION_PROMPT);
+        }
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            println!();
+            // an unfinished form is still returned, to report what's missing
+            if input.is_empty() {
+                return Ok(None);
+            }
+            return Ok(Some(input));
+        }
+
+        // blank lines don't start an input
+        if input.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        input.push_str(&line);
+
+        if input.trim_start().starts_with(':') || paren_depth(&input) <= 0 {
+            return Ok(Some(input));
+        }
+    }
+}
+
+/// Number of parentheses in `text` left open, not counting the ones
+/// in string literals and comments. A block comment left open counts
+/// as an open parenthesis too.
+fn paren_depth(text: &str) -> i64 {
+    let mut depth = 0;
+    let mut comment_depth = 0;
+    let mut in_string = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if comment_depth > 0 {
+            match (ch, chars.peek()) {
+                ('|', Some('#')) => {
+                    chars.next();
+                    comment_depth -= 1;
+                }
+                ('#', Some('|')) => {
+                    chars.next();
+                    comment_depth += 1;
+                }
+                _ => (),
+            }
+            continue;
+        }
+
+        match ch {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                chars.next();
+            }
+            ';' if !in_string => {
+                // the rest of the line is a comment
+                while matches!(chars.peek(), Some(ch) if *ch != '\n') {
+                    chars.next();
+                }
+            }
+            '#' if !in_string && chars.peek() == Some(&'|') => {
+                chars.next();
+                comment_depth += 1;
+            }
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth -= 1,
+            _ => (),
+        }
+    }
+
+    depth + comment_depth
+}
@@ -0,0 +1,7 @@
+mod console;
+mod input;
+mod session;
+
+pub use console::*;
+pub use input::*;
+pub use session::*;
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+
+use inkwell::context::Context;
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::{Linkage, Module};
+use inkwell::targets::{InitializationConfig, Target};
+use inkwell::types::FunctionType;
+use inkwell::OptimizationLevel;
+
+use crate::codegen::{Codegen, GenericFn};
+use crate::diagnostics::{render_diagnostics, Diagnostic, Diagnostics};
+use crate::tispc_lexer::{get_token_stream_at, Ident, IdentKind, Span, Token, Value};
+use crate::tispc_parser::{generate_expression_tree, Expr};
+use crate::typeck::{Scopes, Type, TypeChecker, TypeTable};
+
+/// Session
+///
+/// State of a REPL. Every input is compiled into its own module and
+/// added to one JIT execution engine. Top-level variables live in
+/// globals, and the functions of earlier inputs are declared in each
+/// new module, so later inputs can keep using them. Every top-level
+/// `let` gets a global of its own, since it may shadow a variable of
+/// an earlier input with a different type.
+///
+/// Inputs are leaked so that the names in them can be kept for the
+/// whole session, and their spans index into `history` so that
+/// diagnostics can point at earlier inputs.
+pub struct Session<'ctx> {
+    context: &'ctx Context,
+    engine: Option<ExecutionEngine<'ctx>>,
+    modules: Vec<Module<'ctx>>,
+    checker: TypeChecker<'static>,
+    types: TypeTable,
+    globals: Vec<Global>,
+    // functions compiled by earlier inputs, including the instances
+    // of generic functions
+    functions: Vec<(&'static str, FunctionType<'ctx>)>,
+    generics: HashMap<&'static str, GenericFn<'static>>,
+    history: String,
+}
+
+/// Input that compiled, but hasn't been added to the session yet
+struct Compiled<'ctx> {
+    module: Module<'ctx>,
+    entry: String,
+    checker: TypeChecker<'static>,
+    types: TypeTable,
+    globals: Vec<Global>,
+    generics: Vec<(&'static str, GenericFn<'static>)>,
+    // functions defined at the top level of the input
+    defined: Vec<&'static str>,
+}
+
+/// Global holding a top-level variable
+struct Global {
+    name: &'static str,
+    symbol: String,
+    ty: Type,
+}
+
+impl<'ctx> Session<'ctx> {
+    pub fn new(context: &'ctx Context) -> Result<Session<'ctx>, String> {
+        Target::initialize_native(&InitializationConfig::default())?;
+
+        Ok(Session {
+            context,
+            engine: None,
+            modules: Vec::new(),
+            checker: TypeChecker::new(),
+            types: TypeTable::new(),
+            globals: Vec::new(),
+            functions: Vec::new(),
+            generics: HashMap::new(),
+            history: String::new(),
+        })
+    }
+
+    /// Compiles and runs `input`, then prints its value and the types
+    /// of the functions it defines
+    pub fn eval(&mut self, input: &str) {
+        let compiled = match self.parse(input).and_then(|tree| self.compile(tree)) {
+            Some(compiled) => compiled,
+            None => return,
+        };
+
+        let defined = compiled.defined.clone();
+        if let Err(err) = self.run(compiled) {
+            eprintln!("error: {}", err);
+            return;
+        }
+
+        for name in defined {
+            if let Some(ty) = self.checker.describe_function(name) {
+                println!("{}: {}", name, ty);
+            }
+        }
+    }
+
+    /// Prints the type of the expression in `input` without running it
+    pub fn show_type(&mut self, input: &str) {
+        let tree = match self.parse(input) {
+            Some(tree) => tree,
+            None => return,
+        };
+        let expr = match tree.as_slice() {
+            [expr] => expr,
+            _ => {
+                eprintln!("error: `:type` takes exactly one expression");
+                return;
+            }
+        };
+
+        // the checker is copied so that nothing declared in `expr` is kept
+        match self.checker.clone().describe_expr(expr) {
+            Ok(ty) => println!("{}", ty),
+            Err(diagnostic) => {
+                match function_name(expr).and_then(|name| self.checker.describe_function(name)) {
+                    Some(ty) => println!("{}", ty),
+                    None => self.report_one(diagnostic),
+                }
+            }
+        }
+    }
+
+    /// Prints the LLVM IR of `input` without running it, or the IR of
+    /// the last input if `input` is empty
+    pub fn show_ir(&mut self, input: &str) {
+        if input.trim().is_empty() {
+            match self.modules.last() {
+                Some(module) => print!("{}", module.print_to_string().to_str().unwrap()),
+                None => eprintln!("error: nothing has been compiled yet"),
+            }
+            return;
+        }
+
+        if let Some(compiled) = self.parse(input).and_then(|tree| self.compile(tree)) {
+            print!("{}", compiled.module.print_to_string().to_str().unwrap());
+        }
+    }
+
+    /// Prints the tokens of `input`
+    pub fn show_tokens(&mut self, input: &str) {
+        for token in self.lex(input) {
+            println!("{:?}", token);
+        }
+    }
+
+    fn lex(&mut self, input: &str) -> Vec<Token<'static>> {
+        let source: &'static str = Box::leak(input.to_string().into_boxed_str());
+        let offset = self.history.len();
+        self.history.push_str(source);
+        self.history.push('\n');
+
+        let mut diagnostics = Diagnostics::new();
+        let token_stream = get_token_stream_at(source, offset, &mut diagnostics);
+        self.report(&diagnostics);
+
+        token_stream
+    }
+
+    fn parse(&mut self, input: &str) -> Option<Vec<Expr<'static>>> {
+        let mut diagnostics = Diagnostics::new();
+        let token_stream = self.lex(input);
+        let expression_tree = generate_expression_tree(token_stream, &mut diagnostics);
+
+        if diagnostics.has_errors() {
+            self.report(&diagnostics);
+            return None;
+        }
+        Some(expression_tree)
+    }
+
+    /// Type checks and compiles an input against the session, leaving
+    /// the session itself unchanged
+    fn compile(&mut self, expression_tree: Vec<Expr<'static>>) -> Option<Compiled<'ctx>> {
+        let mut diagnostics = Diagnostics::new();
+
+        // a function can't be replaced once other code may call it
+        for expression in expression_tree.iter() {
+            if let Expr::Defn {
+                name, name_span, ..
+            } = expression
+            {
+                if self.is_defined(name) {
+                    diagnostics.push(
+                        Diagnostic::error("E0210", format!("function `{}` is defined twice", name))
+                            .with_primary(*name_span, "redefined here"),
+                    );
+                }
+            }
+        }
+        if diagnostics.has_errors() {
+            self.report(&diagnostics);
+            return None;
+        }
+
+        let mut checker = self.checker.clone();
+        let input_types = checker.check_program(&expression_tree, &mut diagnostics);
+        if diagnostics.has_errors() {
+            self.report(&diagnostics);
+            return None;
+        }
+        let mut types = self.types.clone();
+        types.extend(input_types);
+
+        let mut lets = Vec::new();
+        for expression in expression_tree.iter() {
+            top_level_lets(expression, &mut lets);
+        }
+        let mut globals: Vec<Global> = Vec::new();
+        for (name, span) in lets.iter() {
+            globals.push(Global {
+                name,
+                symbol: format!("{}.{}", name, self.globals.len() + globals.len()),
+                ty: types.get(*span).unwrap().clone(),
+            });
+        }
+
+        let mut generics = Vec::new();
+        let mut defined = Vec::new();
+        for expression in expression_tree.iter() {
+            if let Expr::Defn {
+                name,
+                params,
+                body,
+                span,
+                ..
+            } = expression
+            {
+                defined.push(*name);
+                if types.get(*span).unwrap().has_vars() {
+                    let generic = GenericFn {
+                        params: params.clone(),
+                        body: body.clone(),
+                        span: *span,
+                    };
+                    generics.push((*name, generic));
+                }
+            }
+        }
+
+        let entry = format!("__repl_{}", self.modules.len());
+        let module = self.context.create_module(&entry);
+        let builder = self.context.create_builder();
+        let mut codegen = Codegen {
+            context: self.context,
+            module: &module,
+            builder: &builder,
+            builtins: &mut HashMap::new(),
+            functions: &mut HashMap::new(),
+            variables: &mut Scopes::new(),
+            globals: &HashMap::new(),
+            types: &types,
+            filename: "<repl>",
+            source: &self.history,
+            generics: &mut HashMap::new(),
+            substitution: &mut HashMap::new(),
+            debug_info: None,
+            gc_frame: None,
+        };
+
+        codegen.add_printf();
+        codegen.add_string_functions();
+        codegen.add_gc_runtime();
+        codegen.disable_gc_collections();
+        for (name, fn_type) in self.functions.iter() {
+            let function = module.add_function(name, *fn_type, None);
+            codegen.functions.insert(name, function);
+        }
+        for (name, generic) in self.generics.iter() {
+            codegen.generics.insert(name, generic.clone());
+        }
+
+        // globals of earlier inputs are only declared here; the
+        // module of the input that introduced them defines them
+        for global in self.globals.iter() {
+            let ptr = module.add_global(codegen.llvm_type(&global.ty), None, &global.symbol);
+            codegen
+                .variables
+                .declare(global.name, ptr.as_pointer_value());
+        }
+        let mut let_globals = HashMap::new();
+        for (global, (_, span)) in globals.iter().zip(lets.iter()) {
+            let ptr = module.add_global(codegen.llvm_type(&global.ty), None, &global.symbol);
+            ptr.set_initializer(&codegen.const_zero(&global.ty));
+            let_globals.insert(*span, ptr.as_pointer_value());
+        }
+        codegen.globals = &let_globals;
+
+        codegen.generate_repl_entry(&entry, expression_tree, &mut diagnostics);
+        if diagnostics.has_errors() {
+            self.report(&diagnostics);
+            return None;
+        }
+
+        if let Err(err) = module.verify() {
+            eprintln!("error: generated invalid LLVM IR: {}", err);
+            return None;
+        }
+
+        Some(Compiled {
+            module,
+            entry,
+            checker,
+            types,
+            globals,
+            generics,
+            defined,
+        })
+    }
+
+    /// Adds a compiled input to the session and runs it
+    fn run(&mut self, compiled: Compiled<'ctx>) -> Result<(), String> {
+        let engine = match &self.engine {
+            Some(engine) => {
+                engine
+                    .add_module(&compiled.module)
+                    .map_err(|_| String::from("could not add the input to the JIT"))?;
+                engine
+            }
+            None => {
+                let engine = compiled
+                    .module
+                    .create_jit_execution_engine(OptimizationLevel::None)
+                    .map_err(|err| err.to_string())?;
+                self.engine.get_or_insert(engine)
+            }
+        };
+
+        // `get_function` can't check the signature it's given, and this
+        // is the one `Codegen::generate_repl_entry` gives every entry:
+        // no arguments, and an `i32` back
+        let entry =
+            unsafe { engine.get_function::<unsafe extern "C" fn() -> i32>(&compiled.entry) }
+                .map_err(|err| err.to_string())?;
+
+        // the functions of `fn`s are internal to their input, and only
+        // reached through closures
+        for function in compiled.module.get_functions() {
+            let name = function.get_name().to_str().unwrap();
+            if function.count_basic_blocks() > 0
+                && function.get_linkage() != Linkage::Internal
+                && name != compiled.entry
+            {
+                let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+                self.functions.push((name, function.get_type()));
+            }
+        }
+        self.checker = compiled.checker;
+        self.types = compiled.types;
+        self.globals.extend(compiled.globals);
+        self.generics.extend(compiled.generics);
+        self.modules.push(compiled.module);
+
+        // the engine, which owns the code of `entry`, lives as long as
+        // the session
+        unsafe {
+            entry.call();
+        }
+
+        Ok(())
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.generics.contains_key(name)
+            || self.functions.iter().any(|(function, _)| *function == name)
+    }
+
+    fn report(&self, diagnostics: &Diagnostics) {
+        if !diagnostics.is_empty() {
+            eprint!(
+                "{}",
+                render_diagnostics(diagnostics, "<repl>", &self.history)
+            );
+        }
+    }
+
+    fn report_one(&self, diagnostic: Diagnostic) {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(diagnostic);
+        self.report(&diagnostics);
+    }
+}
+
+/// Name of the function `expr` refers to, if it's a bare name
+fn function_name<'a>(expr: &Expr<'a>) -> Option<&'a str> {
+    match expr {
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Variable,
+                value: Some(Value::String(name)),
+            },
+            _,
+        ) => Some(name),
+        _ => None,
+    }
+}
+
+/// Collects the `let`s of `expr` that declare top-level variables,
+/// i.e. every `let` that isn't inside a body, a branch or a `do`
+/// block, which have scopes of their own
+fn top_level_lets<'a>(expr: &Expr<'a>, lets: &mut Vec<(&'a str, Span)>) {
+    match expr {
+        Expr::Let {
+            name, value, span, ..
+        } => {
+            if let Some(value) = value {
+                top_level_lets(value, lets);
+            }
+            lets.push((name, *span));
+        }
+        Expr::Set { value, .. } | Expr::Cast { value, .. } => top_level_lets(value, lets),
+        Expr::Call(callee, args, _) => {
+            top_level_lets(callee, lets);
+            for arg in args {
+                top_level_lets(arg, lets);
+            }
+        }
+        Expr::While { condition, .. }
+        | Expr::When { condition, .. }
+        | Expr::If { condition, .. } => top_level_lets(condition, lets),
+        Expr::Print { args, .. } => {
+            for arg in args {
+                top_level_lets(arg, lets);
+            }
+        }
+        Expr::Cond { clauses, .. } => {
+            for clause in clauses {
+                if let Some(condition) = &clause.condition {
+                    top_level_lets(condition, lets);
+                }
+            }
+        }
+        Expr::Constant(..)
+        | Expr::Builtin(..)
+        | Expr::Do { .. }
+        | Expr::Defn { .. }
+        | Expr::Lambda { .. } => (),
+    }
+}
@@ -1,31 +1,115 @@
-use super::tokens::{IdentKind, LexToken, LiteralKind, Token, TokenKind, Value};
+use std::borrow::Cow;
+
+use super::span::Span;
+use super::tokens::{CommentKind, IdentKind, LexToken, LiteralKind, Token, TokenKind, Value};
+use crate::diagnostics::{Diagnostic, Diagnostics};
 use logos::Logos;
 
-pub fn get_token_stream(raw_code: &String) -> Vec<Token> {
+/// get_token_stream
+///
+/// Converts raw source code into a stream of `Token`s. Comments are
+/// kept in the stream, so tools can find them next to the code they
+/// describe. Text that does not form a valid token is reported in
+/// `diagnostics` and left out of the stream.
+pub fn get_token_stream<'a>(raw_code: &'a str, diagnostics: &mut Diagnostics) -> Vec<Token<'a>> {
+    get_token_stream_at(raw_code, 0, diagnostics)
+}
+
+/// get_token_stream_at
+///
+/// Like `get_token_stream`, for source code that starts `offset`
+/// bytes into a larger text, e.g. one input of a REPL session. Spans
+/// of the tokens and diagnostics index into the larger text.
+pub fn get_token_stream_at<'a>(
+    raw_code: &'a str,
+    offset: usize,
+    diagnostics: &mut Diagnostics,
+) -> Vec<Token<'a>> {
     let mut token_stream: Vec<Token> = Vec::new();
     let mut lex = LexToken::lexer(raw_code);
 
+    // span of the run of invalid characters currently being read
+    let mut invalid: Option<Span> = None;
+
     loop {
         let lex_token = lex.next();
+        let span = Span::from(lex.span());
+        let mut span = Span::new(span.start + offset, span.end + offset);
+
+        match (&lex_token, invalid) {
+            (Some(LexToken::Error), Some(invalid_span)) if invalid_span.end == span.start => {
+                invalid = Some(invalid_span.to(span));
+                continue;
+            }
+            (_, Some(invalid_span)) => {
+                diagnostics.push(invalid_token(raw_code, offset, invalid_span));
+                invalid = None;
+            }
+            _ => (),
+        }
+
+        // the longest number the regexes match can be followed by
+        // letters, as in `0b2` or `1e`, which are neither a number nor
+        // a name, so the number takes them in and is reported
+        if matches!(
+            lex_token,
+            Some(LexToken::Integer(_)) | Some(LexToken::Float(_))
+        ) {
+            let rest = lex.remainder();
+            let suffix = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                .unwrap_or(rest.len());
+            if suffix > 0 {
+                lex.bump(suffix);
+                span = Span::new(span.start, span.end + suffix);
+                let text = &raw_code[span.start - offset..span.end - offset];
+                diagnostics.push(
+                    Diagnostic::error("E0006", "invalid number literal")
+                        .with_primary(span, format!("`{}` is not a number or a name", text)),
+                );
+            }
+        }
+
         let (kind, value) = match lex_token {
-            Some(LexToken::Number(val)) => (
+            Some(LexToken::Integer(val)) => (
+                TokenKind::Literal(LiteralKind::Number),
+                Some(Value::Integer(val)),
+            ),
+            Some(LexToken::Float(val)) => (
                 TokenKind::Literal(LiteralKind::Number),
-                Some(Value::Number(val)),
+                Some(Value::Float(val)),
             ),
             Some(LexToken::Boolean(val)) => (
                 TokenKind::Literal(LiteralKind::Boolean),
                 Some(Value::Boolean(val)),
             ),
             Some(LexToken::String(val)) => {
-                // TODO: remove starting and ending \" from str
-                /*
-                 * let str_literal = String::from(val);
-                 * let len = str_literal.len() - 1;
-                 * let slice = str_literal[..len];
-                 */
+                // strip the quotes at the start and end
+                let contents = &val[1..val.len() - 1];
+                let text = match decode_string(contents, span.start + 1) {
+                    Ok(text) => text,
+                    Err(diagnostic) => {
+                        diagnostics.push(diagnostic);
+                        Cow::Borrowed(contents)
+                    }
+                };
+
+                (
+                    TokenKind::Literal(LiteralKind::String),
+                    Some(Value::Str(text)),
+                )
+            }
+            Some(LexToken::UnterminatedString(val)) => {
+                diagnostics.push(
+                    Diagnostic::error("E0005", "unterminated string").with_primary(
+                        Span::new(span.start, span.start + 1),
+                        "this string is never closed",
+                    ),
+                );
+
                 (
                     TokenKind::Literal(LiteralKind::String),
-                    Some(Value::String(val)),
+                    Some(Value::Str(Cow::Borrowed(&val[1..]))),
                 )
             }
             Some(LexToken::Ident(val)) => (
@@ -33,23 +117,177 @@ pub fn get_token_stream(raw_code: &String) -> Vec<Token> {
                 Some(Value::String(val)),
             ),
             Some(LexToken::Let) => (TokenKind::Ident(IdentKind::Let), None),
+            Some(LexToken::Set) => (TokenKind::Ident(IdentKind::Set), None),
+            Some(LexToken::Do) => (TokenKind::Ident(IdentKind::Do), None),
             Some(LexToken::Print) => (TokenKind::Ident(IdentKind::Print), None),
             Some(LexToken::While) => (TokenKind::Ident(IdentKind::While), None),
+            Some(LexToken::Defn) => (TokenKind::Ident(IdentKind::Defn), None),
+            Some(LexToken::Fn) => (TokenKind::Ident(IdentKind::Fn), None),
+            Some(LexToken::If) => (TokenKind::Ident(IdentKind::If), None),
+            Some(LexToken::Cond) => (TokenKind::Ident(IdentKind::Cond), None),
+            Some(LexToken::Else) => (TokenKind::Ident(IdentKind::Else), None),
+            Some(LexToken::When) => (TokenKind::Ident(IdentKind::When), None),
+            Some(LexToken::Unless) => (TokenKind::Ident(IdentKind::Unless), None),
+            Some(LexToken::And) => (TokenKind::Ident(IdentKind::And), None),
+            Some(LexToken::Or) => (TokenKind::Ident(IdentKind::Or), None),
+            Some(LexToken::Not) => (TokenKind::Ident(IdentKind::Not), None),
+            Some(LexToken::StrConcat) => (TokenKind::Ident(IdentKind::StrConcat), None),
+            Some(LexToken::StrLen) => (TokenKind::Ident(IdentKind::StrLen), None),
+            Some(LexToken::Substr) => (TokenKind::Ident(IdentKind::Substr), None),
+            Some(LexToken::StrEqual) => (TokenKind::Ident(IdentKind::StrEqual), None),
             Some(LexToken::OpenParen) => (TokenKind::OpenParen, None),
             Some(LexToken::CloseParen) => (TokenKind::CloseParen, None),
             Some(LexToken::Minus) => (TokenKind::Minus, None),
             Some(LexToken::Plus) => (TokenKind::Plus, None),
             Some(LexToken::Divide) => (TokenKind::Divide, None),
+            Some(LexToken::Percent) => (TokenKind::Percent, None),
+            Some(LexToken::Ampersand) => (TokenKind::Ampersand, None),
+            Some(LexToken::Pipe) => (TokenKind::Pipe, None),
+            Some(LexToken::Caret) => (TokenKind::Caret, None),
+            Some(LexToken::Tilde) => (TokenKind::Tilde, None),
+            Some(LexToken::ShiftLeft) => (TokenKind::ShiftLeft, None),
+            Some(LexToken::ShiftRight) => (TokenKind::ShiftRight, None),
             Some(LexToken::Mult) => (TokenKind::Mult, None),
             Some(LexToken::Greater) => (TokenKind::Greater, None),
             Some(LexToken::Smaller) => (TokenKind::Smaller, None),
+            Some(LexToken::GreaterEqual) => (TokenKind::GreaterEqual, None),
+            Some(LexToken::SmallerEqual) => (TokenKind::SmallerEqual, None),
+            Some(LexToken::Equal) => (TokenKind::Equal, None),
+            Some(LexToken::NotEqual) => (TokenKind::NotEqual, None),
+            Some(LexToken::Colon) => (TokenKind::Colon, None),
+            Some(LexToken::Arrow) => (TokenKind::Arrow, None),
+
+            Some(LexToken::LineComment(val)) => (
+                TokenKind::Comment(CommentKind::Line),
+                Some(Value::String(&val[1..])),
+            ),
+            Some(LexToken::BlockComment(closed)) => {
+                let text = &raw_code[span.start - offset..span.end - offset];
+                let text = if closed {
+                    &text[2..text.len() - 2]
+                } else {
+                    diagnostics.push(
+                        Diagnostic::error("E0004", "unterminated block comment").with_primary(
+                            Span::new(span.start, span.start + 2),
+                            "this `#|` is never closed",
+                        ),
+                    );
+                    &text[2..]
+                };
+
+                (
+                    TokenKind::Comment(CommentKind::Block),
+                    Some(Value::String(text)),
+                )
+            }
+            Some(LexToken::Discard) => (TokenKind::Discard, None),
 
             Some(LexToken::Whitespace) => continue,
-            Some(LexToken::Error) => continue,
+            Some(LexToken::Error) => {
+                invalid = Some(span);
+                continue;
+            }
             None => break,
         };
 
-        token_stream.push(Token { kind, value });
+        token_stream.push(Token { kind, value, span });
     }
     token_stream
 }
+
+fn invalid_token(raw_code: &str, offset: usize, span: Span) -> Diagnostic {
+    let text = &raw_code[span.start - offset..span.end - offset];
+
+    // `{:?}` shows control characters, which would be invisible
+    Diagnostic::error("E0001", format!("invalid token {:?}", text))
+        .with_primary(span, "not recognised by the lexer")
+}
+
+/// Decodes the escape sequences in the contents of a string literal:
+/// `\n`, `\t`, `\r`, `\"`, `\\` and `\u{...}` with 1 to 6 hex digits.
+/// `start` is the offset of `contents` in the source, to point at an
+/// invalid escape.
+fn decode_string(contents: &str, start: usize) -> Result<Cow<'_, str>, Diagnostic> {
+    if !contents.contains('\\') {
+        return Ok(Cow::Borrowed(contents));
+    }
+
+    let mut decoded = String::with_capacity(contents.len());
+    let mut chars = contents.char_indices();
+
+    while let Some((offset, ch)) = chars.next() {
+        if ch != '\\' {
+            decoded.push(ch);
+            continue;
+        }
+
+        // the lexer only matches a backslash followed by a character
+        let (escape_offset, escape) = chars.next().unwrap();
+        let span = Span::new(start + offset, start + escape_offset + escape.len_utf8());
+
+        match escape {
+            'n' => decoded.push('\n'),
+            't' => decoded.push('\t'),
+            'r' => decoded.push('\r'),
+            '"' => decoded.push('"'),
+            '\\' => decoded.push('\\'),
+            'u' => decoded.push(decode_unicode_escape(&mut chars, start, span)?),
+            _ => {
+                return Err(Diagnostic::error(
+                    "E0002",
+                    format!("unknown escape sequence `\\{}`", escape),
+                )
+                .with_primary(span, "unknown escape sequence")
+                .with_note("valid escapes are \\n, \\t, \\r, \\\", \\\\ and \\u{...}"))
+            }
+        }
+    }
+
+    Ok(Cow::Owned(decoded))
+}
+
+/// Reads the `{...}` of a `\u{...}` escape from `chars`. `span` covers
+/// the `\u` and is extended over the braces for diagnostics.
+fn decode_unicode_escape(
+    chars: &mut impl Iterator<Item = (usize, char)>,
+    start: usize,
+    span: Span,
+) -> Result<char, Diagnostic> {
+    let mut span = span;
+    let mut digits = String::new();
+    let mut closed = false;
+
+    for (index, (offset, ch)) in chars.enumerate() {
+        span.end = start + offset + ch.len_utf8();
+        match ch {
+            '{' if index == 0 => (),
+            '}' if index > 0 => {
+                closed = true;
+                break;
+            }
+            _ if index > 0 && ch.is_ascii_hexdigit() => digits.push(ch),
+            _ => break,
+        }
+    }
+
+    let code_point = match u32::from_str_radix(&digits, 16) {
+        Ok(code_point) if closed && digits.len() <= 6 => code_point,
+        _ => {
+            return Err(Diagnostic::error("E0003", "invalid unicode escape")
+                .with_primary(span, "expected `\\u{...}` with 1 to 6 hex digits"))
+        }
+    };
+
+    match std::char::from_u32(code_point) {
+        // strings are null-terminated at runtime
+        Some('\0') => Err(Diagnostic::error("E0003", "invalid unicode escape")
+            .with_primary(span, "strings can't contain the null character")),
+        Some(ch) => Ok(ch),
+        None => Err(
+            Diagnostic::error("E0003", "invalid unicode escape").with_primary(
+                span,
+                format!("`{:x}` is not a unicode character", code_point),
+            ),
+        ),
+    }
+}
@@ -1,5 +1,7 @@
 mod lexer;
+mod span;
 mod tokens;
 
+pub use lexer::*;
+pub use span::*;
 pub use tokens::*;
-pub use lexer::*;
\ No newline at end of file
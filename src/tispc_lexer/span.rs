@@ -0,0 +1,137 @@
+use std::ops::Range;
+
+/// Byte range `start..end` of a token or expression in the source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Span {
+        Span::new(range.start, range.end)
+    }
+}
+
+/// Line and column (both starting at 1) of a byte offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// LineIndex
+///
+/// Maps byte offsets in a source file to line and column numbers.
+/// Columns are counted in characters, not bytes.
+#[derive(Debug, Clone)]
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> LineIndex<'a> {
+        let mut line_starts = vec![0];
+        for (offset, ch) in source.char_indices() {
+            if ch == '\n' {
+                line_starts.push(offset + 1);
+            }
+        }
+
+        LineIndex {
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.source.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+
+        let line_start = self.line_starts[line];
+        let col = self.source[line_start..offset].chars().count() + 1;
+
+        LineCol {
+            line: line + 1,
+            col,
+        }
+    }
+
+    /// Text of the given (1-based) line without its line terminator
+    pub fn line_text(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line - 1];
+        let end = match self.line_starts.get(line) {
+            Some(next_start) => *next_start,
+            None => self.source.len(),
+        };
+
+        self.source[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    /// Like `line_col`, but counting columns in UTF-16 code units, as
+    /// editors do over the language server protocol
+    pub fn utf16_line_col(&self, offset: usize) -> LineCol {
+        let LineCol { line, .. } = self.line_col(offset);
+        let offset = offset.min(self.source.len());
+        let line_start = self.line_starts[line - 1];
+        let col = self.source[line_start..offset].encode_utf16().count() + 1;
+
+        LineCol { line, col }
+    }
+
+    /// Length of the given (1-based) line in UTF-16 code units,
+    /// without its line terminator
+    pub fn utf16_line_length(&self, line: usize) -> usize {
+        self.line_text(line).encode_utf16().count()
+    }
+
+    /// Byte offset of a line and UTF-16 column. Columns past the end
+    /// of a line are moved back to the end of that line, and lines
+    /// past the end of the source to the end of the source.
+    pub fn utf16_offset(&self, position: LineCol) -> usize {
+        if position.line > self.line_starts.len() {
+            return self.source.len();
+        }
+
+        let start = self.line_starts[position.line - 1];
+        let line_text = self.line_text(position.line);
+        let mut units = 1;
+        for (offset, ch) in line_text.char_indices() {
+            if units >= position.col {
+                return start + offset;
+            }
+            units += ch.len_utf16();
+        }
+        start + line_text.len()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+}
@@ -1,5 +1,9 @@
+use std::borrow::Cow;
+
 use logos::Logos;
 
+use super::span::Span;
+
 // Tokens to be used while running lexer
 #[derive(Debug, PartialEq, Logos)]
 pub enum LexToken<'a> {
@@ -21,42 +25,207 @@ pub enum LexToken<'a> {
     #[token("/")]
     Divide,
 
+    #[token("%")]
+    Percent,
+
+    #[token("&")]
+    Ampersand,
+
+    #[token("|")]
+    Pipe,
+
+    #[token("^")]
+    Caret,
+
+    #[token("~")]
+    Tilde,
+
+    #[token("<<")]
+    ShiftLeft,
+
+    #[token(">>")]
+    ShiftRight,
+
     #[token(">")]
     Greater,
 
     #[token("<")]
     Smaller,
 
+    #[token(">=")]
+    GreaterEqual,
+
+    #[token("<=")]
+    SmallerEqual,
+
+    #[token("=")]
+    Equal,
+
+    #[token("!=")]
+    NotEqual,
+
+    #[token(":")]
+    Colon,
+
+    #[token("->")]
+    Arrow,
+
     #[token("let")]
     Let,
 
+    #[token("set!")]
+    Set,
+
+    #[token("do")]
+    Do,
+
     #[token("print")]
     Print,
 
     #[token("while")]
     While,
 
-    // Match string literals and then strip the " at start and end
+    #[token("defn")]
+    Defn,
+
+    #[token("fn")]
+    Fn,
+
+    #[token("if")]
+    If,
+
+    #[token("cond")]
+    Cond,
+
+    #[token("else")]
+    Else,
+
+    #[token("when")]
+    When,
+
+    #[token("unless")]
+    Unless,
+
+    #[token("and")]
+    And,
+
+    #[token("or")]
+    Or,
+
+    #[token("not")]
+    Not,
+
+    #[token("str-concat")]
+    StrConcat,
+
+    #[token("str-len")]
+    StrLen,
+
+    #[token("substr")]
+    Substr,
+
+    #[token("str=")]
+    StrEqual,
+
+    // Match string literals, quotes included; the lexer strips the
+    // quotes and decodes escape sequences
     #[regex("\"([^\"\\\\]|\\\\.)*\"")]
     String(&'a str),
 
-    // Non-literal strings (currently variable names)
-    #[regex("[a-zA-Z]+")]
+    // a string literal that is never closed, which runs to the end of
+    // the file
+    #[regex("\"([^\"\\\\]|\\\\.)*\\\\?")]
+    UnterminatedString(&'a str),
+
+    // Non-literal strings (variable, function and type names)
+    #[regex("[a-zA-Z_][a-zA-Z0-9_]*")]
     Ident(&'a str),
 
-    #[regex("-?([0-9])+", |lex| lex.slice().parse())]
-    Number(f64),
+    // Integers in decimal, hex (`0x`) or binary (`0b`), with
+    // optional `_` separators
+    #[regex("-?[0-9][0-9_]*", |lex| parse_integer(lex.slice(), 10))]
+    #[regex("-?0x[0-9a-fA-F_]+", |lex| parse_integer(lex.slice(), 16))]
+    #[regex("-?0b[01_]+", |lex| parse_integer(lex.slice(), 2))]
+    Integer(i128),
+
+    #[regex(r"-?[0-9][0-9_]*\.[0-9][0-9_]*([eE][+-]?[0-9_]+)?", parse_float)]
+    #[regex(r"-?[0-9][0-9_]*[eE][+-]?[0-9_]+", parse_float)]
+    Float(f64),
 
     #[regex("(true|false)", |lex| lex.slice().parse())]
     Boolean(bool),
 
-    #[regex(r"[ \t\n\f]+", logos::skip)]
+    // `;` up to the end of the line
+    #[regex(";[^\r\n]*")]
+    LineComment(&'a str),
+
+    // `#| ... |#`, which can be nested; false if it's never closed
+    #[token("#|", block_comment)]
+    BlockComment(bool),
+
+    // `#_`, which comments out the form after it
+    #[token("#_")]
+    Discard,
+
+    #[regex(r"[ \t\r\n\f]+", logos::skip)]
     Whitespace,
 
     #[error]
     Error,
 }
 
+/// Value of an integer literal, without its `0x`/`0b` prefix and
+/// separators. Fails (making the literal an invalid token) if there
+/// are no digits or it's too large to be any integer type.
+fn parse_integer(literal: &str, radix: u32) -> Option<i128> {
+    let (negative, literal) = match literal.strip_prefix('-') {
+        Some(literal) => (true, literal),
+        None => (false, literal),
+    };
+    let digits: String = match radix {
+        10 => literal.chars().filter(|ch| *ch != '_').collect(),
+        _ => literal[2..].chars().filter(|ch| *ch != '_').collect(),
+    };
+
+    let value = i128::from_str_radix(&digits, radix).ok()?;
+    let value = if negative { -value } else { value };
+    if value < i64::MIN as i128 || value > u64::MAX as i128 {
+        return None;
+    }
+    Some(value)
+}
+
+/// Skips over the rest of a block comment, including any block
+/// comments nested in it. Returns whether the comment is closed.
+fn block_comment<'a>(lex: &mut logos::Lexer<'a, LexToken<'a>>) -> bool {
+    let rest = lex.remainder();
+    let mut depth = 1;
+    let mut position = 0;
+
+    while depth > 0 {
+        let text = &rest[position..];
+        if text.starts_with("|#") {
+            depth -= 1;
+            position += 2;
+        } else if text.starts_with("#|") {
+            depth += 1;
+            position += 2;
+        } else if let Some(ch) = text.chars().next() {
+            position += ch.len_utf8();
+        } else {
+            break;
+        }
+    }
+
+    lex.bump(position);
+    depth == 0
+}
+
+fn parse_float<'a>(lex: &mut logos::Lexer<'a, LexToken<'a>>) -> Option<f64> {
+    let literal: String = lex.slice().chars().filter(|ch| *ch != '_').collect();
+    literal.parse().ok()
+}
+
 // Final representation of the kind of token
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
@@ -72,13 +241,59 @@ pub enum TokenKind {
 
     Divide,
 
+    Percent,
+
+    Ampersand,
+
+    Pipe,
+
+    Caret,
+
+    Tilde,
+
+    ShiftLeft,
+
+    ShiftRight,
+
     Greater,
 
     Smaller,
 
+    GreaterEqual,
+
+    SmallerEqual,
+
+    Equal,
+
+    NotEqual,
+
+    Colon,
+
+    Arrow,
+
     Literal(LiteralKind),
 
     Ident(IdentKind),
+
+    // comments are kept in the token stream as trivia, and skipped
+    // by the reader
+    Comment(CommentKind),
+
+    Discard,
+}
+
+impl TokenKind {
+    /// Whether the token is a comment, which has no meaning to the
+    /// program
+    pub fn is_comment(&self) -> bool {
+        matches!(self, TokenKind::Comment(_))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommentKind {
+    Line,
+    Block,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -88,10 +303,14 @@ pub enum LiteralKind {
     Boolean,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value<'a> {
-    Number(f64),
+    Integer(i128),
+    Float(f64),
+    // name of a variable, function or type, or the text of a comment
     String(&'a str),
+    // contents of a string literal, with escape sequences decoded
+    Str(Cow<'a, str>),
     Boolean(bool),
 }
 
@@ -99,15 +318,147 @@ pub enum Value<'a> {
 pub enum IdentKind {
     Variable,
     Let,
+    Set,
+    Do,
     While,
     Print,
+    Defn,
+    Fn,
+    If,
+    Cond,
+    Else,
+    When,
+    Unless,
     Greater,
     Smaller,
+    GreaterEqual,
+    SmallerEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    StrConcat,
+    StrLen,
+    Substr,
+    StrEqual,
     FuncName,
     Plus,
     Minus,
     Mult,
     Div,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    Shl,
+    Shr,
+}
+
+impl IdentKind {
+    /// Every builtin that can be written in source code
+    pub const BUILTINS: [IdentKind; 36] = [
+        IdentKind::Let,
+        IdentKind::Set,
+        IdentKind::Do,
+        IdentKind::While,
+        IdentKind::Print,
+        IdentKind::Defn,
+        IdentKind::Fn,
+        IdentKind::If,
+        IdentKind::Cond,
+        IdentKind::Else,
+        IdentKind::When,
+        IdentKind::Unless,
+        IdentKind::Greater,
+        IdentKind::Smaller,
+        IdentKind::GreaterEqual,
+        IdentKind::SmallerEqual,
+        IdentKind::Equal,
+        IdentKind::NotEqual,
+        IdentKind::And,
+        IdentKind::Or,
+        IdentKind::Not,
+        IdentKind::StrConcat,
+        IdentKind::StrLen,
+        IdentKind::Substr,
+        IdentKind::StrEqual,
+        IdentKind::Plus,
+        IdentKind::Minus,
+        IdentKind::Mult,
+        IdentKind::Div,
+        IdentKind::Rem,
+        IdentKind::BitAnd,
+        IdentKind::BitOr,
+        IdentKind::BitXor,
+        IdentKind::BitNot,
+        IdentKind::Shl,
+        IdentKind::Shr,
+    ];
+
+    /// Name of a builtin as written in source code
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match self {
+            IdentKind::Let => "let",
+            IdentKind::Set => "set!",
+            IdentKind::Do => "do",
+            IdentKind::While => "while",
+            IdentKind::Print => "print",
+            IdentKind::Defn => "defn",
+            IdentKind::Fn => "fn",
+            IdentKind::If => "if",
+            IdentKind::Cond => "cond",
+            IdentKind::Else => "else",
+            IdentKind::When => "when",
+            IdentKind::Unless => "unless",
+            IdentKind::Greater => ">",
+            IdentKind::Smaller => "<",
+            IdentKind::GreaterEqual => ">=",
+            IdentKind::SmallerEqual => "<=",
+            IdentKind::Equal => "=",
+            IdentKind::NotEqual => "!=",
+            IdentKind::And => "and",
+            IdentKind::Or => "or",
+            IdentKind::Not => "not",
+            IdentKind::StrConcat => "str-concat",
+            IdentKind::StrLen => "str-len",
+            IdentKind::Substr => "substr",
+            IdentKind::StrEqual => "str=",
+            IdentKind::Plus => "+",
+            IdentKind::Minus => "-",
+            IdentKind::Mult => "*",
+            IdentKind::Div => "/",
+            IdentKind::Rem => "%",
+            IdentKind::BitAnd => "&",
+            IdentKind::BitOr => "|",
+            IdentKind::BitXor => "^",
+            IdentKind::BitNot => "~",
+            IdentKind::Shl => "<<",
+            IdentKind::Shr => ">>",
+            IdentKind::Variable | IdentKind::FuncName => return None,
+        };
+        Some(name)
+    }
+
+    /// Whether the builtin is a special form, which controls how its
+    /// arguments are evaluated, rather than an operator or function
+    pub fn is_special_form(&self) -> bool {
+        matches!(
+            self,
+            IdentKind::Let
+                | IdentKind::Set
+                | IdentKind::Do
+                | IdentKind::While
+                | IdentKind::Defn
+                | IdentKind::Fn
+                | IdentKind::If
+                | IdentKind::Cond
+                | IdentKind::Else
+                | IdentKind::When
+                | IdentKind::Unless
+        )
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -120,15 +471,42 @@ impl<'a> Ident<'a> {
     pub fn is_builtin(&'a self) -> bool {
         match self.kind {
             IdentKind::Let
+            | IdentKind::Set
+            | IdentKind::Do
             | IdentKind::Print
             | IdentKind::Div
             | IdentKind::Plus
             | IdentKind::FuncName
             | IdentKind::While
+            | IdentKind::Defn
+            | IdentKind::Fn
+            | IdentKind::If
+            | IdentKind::Cond
+            | IdentKind::Else
+            | IdentKind::When
+            | IdentKind::Unless
             | IdentKind::Greater
             | IdentKind::Smaller
+            | IdentKind::GreaterEqual
+            | IdentKind::SmallerEqual
+            | IdentKind::Equal
+            | IdentKind::NotEqual
+            | IdentKind::And
+            | IdentKind::Or
+            | IdentKind::Not
+            | IdentKind::StrConcat
+            | IdentKind::StrLen
+            | IdentKind::Substr
+            | IdentKind::StrEqual
             | IdentKind::Mult
-            | IdentKind::Minus => true,
+            | IdentKind::Minus
+            | IdentKind::Rem
+            | IdentKind::BitAnd
+            | IdentKind::BitOr
+            | IdentKind::BitXor
+            | IdentKind::BitNot
+            | IdentKind::Shl
+            | IdentKind::Shr => true,
             _ => false,
         }
     }
@@ -138,4 +516,5 @@ impl<'a> Ident<'a> {
 pub struct Token<'a> {
     pub kind: TokenKind,
     pub value: Option<Value<'a>>,
+    pub span: Span,
 }
@@ -0,0 +1,121 @@
+use std::slice;
+
+use super::parser::{CondClause, Expr, Param};
+use crate::tispc_lexer::{Ident, IdentKind, Value};
+use crate::typeck::Scopes;
+
+/// captured_names
+///
+/// Names that the body of a `fn` with parameters `params` uses but
+/// doesn't declare itself, in the order they are first used. Those
+/// that are variables where the `fn` is created are captured by it;
+/// the others name functions.
+pub fn captured_names<'a>(params: &[Param<'a>], body: &[Expr<'a>]) -> Vec<&'a str> {
+    let mut finder = Captures {
+        bound: Scopes::new(),
+        names: Vec::new(),
+    };
+    finder.lambda(params, body);
+    finder.names
+}
+
+/// Walks a body with the scoping rules of the type checker, keeping
+/// track of the names declared in it
+struct Captures<'a> {
+    bound: Scopes<'a, ()>,
+    names: Vec<&'a str>,
+}
+
+impl<'a> Captures<'a> {
+    fn lambda(&mut self, params: &[Param<'a>], body: &[Expr<'a>]) {
+        self.bound.push();
+        for param in params {
+            self.bound.declare(param.name, ());
+        }
+        self.block(body);
+        self.bound.pop();
+    }
+
+    fn use_name(&mut self, name: &'a str) {
+        if self.bound.get(name).is_none() && !self.names.contains(&name) {
+            self.names.push(name);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr<'a>) {
+        match expr {
+            Expr::Constant(..) => (),
+            Expr::Builtin(
+                Ident {
+                    kind: IdentKind::Variable,
+                    value: Some(Value::String(name)),
+                },
+                _,
+            ) => self.use_name(name),
+            Expr::Builtin(..) => (),
+            Expr::Call(head, args, _) => {
+                self.expr(head);
+                self.exprs(args);
+            }
+            Expr::Let { name, value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.bound.declare(name, ());
+            }
+            Expr::Set { name, value, .. } => {
+                self.expr(value);
+                self.use_name(name);
+            }
+            Expr::Do { body, .. } => self.block(body),
+            Expr::While {
+                condition, body, ..
+            }
+            | Expr::When {
+                condition, body, ..
+            } => {
+                self.expr(condition);
+                self.block(body);
+            }
+            Expr::Print { args, .. } => self.exprs(args),
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                self.block(slice::from_ref(&**then_branch));
+                if let Some(else_branch) = else_branch {
+                    self.block(slice::from_ref(&**else_branch));
+                }
+            }
+            Expr::Cond { clauses, .. } => self.clauses(clauses),
+            // functions only see their parameters
+            Expr::Defn { .. } => (),
+            Expr::Lambda { params, body, .. } => self.lambda(params, body),
+            Expr::Cast { value, .. } => self.expr(value),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr<'a>]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn block(&mut self, body: &[Expr<'a>]) {
+        self.bound.push();
+        self.exprs(body);
+        self.bound.pop();
+    }
+
+    fn clauses(&mut self, clauses: &[CondClause<'a>]) {
+        for clause in clauses {
+            if let Some(condition) = &clause.condition {
+                self.expr(condition);
+            }
+            self.block(&clause.body);
+        }
+    }
+}
@@ -1,3 +1,7 @@
+mod captures;
 mod parser;
+mod reader;
 
+pub use captures::*;
 pub use parser::*;
+pub use reader::*;
@@ -1,130 +1,672 @@
-use crate::tispc_lexer::{Ident, IdentKind, LiteralKind, Token, TokenKind, Value};
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use super::reader::{read_forms, SExpr};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::tispc_lexer::{Ident, IdentKind, LiteralKind, Span, Token, TokenKind, Value};
+use crate::typeck::Type;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr<'a> {
-    Constant(Value<'a>),
-    Builtin(Ident<'a>),
-    Call(Box<Expr<'a>>, Vec<Expr<'a>>),
+    Constant(Value<'a>, Span),
+    Builtin(Ident<'a>, Span),
+    Call(Box<Expr<'a>>, Vec<Expr<'a>>, Span),
+    Let {
+        name: &'a str,
+        name_span: Span,
+        ty: Option<TypeAnnotation<'a>>,
+        // `(let name)` declares a variable without a value
+        value: Option<Box<Expr<'a>>>,
+        span: Span,
+    },
+    // `(set! name value)` assigns to an existing variable
+    Set {
+        name: &'a str,
+        name_span: Span,
+        value: Box<Expr<'a>>,
+        span: Span,
+    },
+    // `(do body...)` runs its body in a scope of its own
+    Do {
+        body: Vec<Expr<'a>>,
+        span: Span,
+    },
     While {
         condition: Box<Expr<'a>>,
         body: Vec<Expr<'a>>,
+        span: Span,
+    },
+    Print {
+        args: Vec<Expr<'a>>,
+        span: Span,
+    },
+    If {
+        condition: Box<Expr<'a>>,
+        then_branch: Box<Expr<'a>>,
+        // an `if` without an `else` yields no value
+        else_branch: Option<Box<Expr<'a>>>,
+        span: Span,
+    },
+    Cond {
+        clauses: Vec<CondClause<'a>>,
+        span: Span,
+    },
+    // `(when condition body...)`, or `(unless condition body...)`
+    // when negated
+    When {
+        condition: Box<Expr<'a>>,
+        body: Vec<Expr<'a>>,
+        negated: bool,
+        span: Span,
+    },
+    Defn {
+        name: &'a str,
+        name_span: Span,
+        params: Vec<Param<'a>>,
+        return_ty: Option<TypeAnnotation<'a>>,
+        body: Vec<Expr<'a>>,
+        span: Span,
+    },
+    // `(fn (params...) body...)`, an anonymous function that captures
+    // the variables it uses from the scope it is created in
+    Lambda {
+        params: Vec<Param<'a>>,
+        return_ty: Option<TypeAnnotation<'a>>,
+        body: Vec<Expr<'a>>,
+        span: Span,
+    },
+    // `(type value)` converts a number to another numeric type
+    Cast {
+        ty: TypeAnnotation<'a>,
+        value: Box<Expr<'a>>,
+        span: Span,
     },
 }
 
+/// Parameter in the parameter list of a `defn` or `fn`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param<'a> {
+    pub name: &'a str,
+    pub span: Span,
+    pub ty: Option<TypeAnnotation<'a>>,
+}
+
+/// Clause of a `cond`: `(condition body...)` or `(else body...)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CondClause<'a> {
+    // None for the `else` clause
+    pub condition: Option<Expr<'a>>,
+    pub body: Vec<Expr<'a>>,
+    pub span: Span,
+}
+
+/// Type name written after a `:` or `->`, e.g. the `i64`
+/// in `(let x: i64 3)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeAnnotation<'a> {
+    pub name: &'a str,
+    pub span: Span,
+}
+
+type Forms<'a> = Peekable<IntoIter<SExpr<'a>>>;
+
+impl<'a> Expr<'a> {
+    /// Location of the expression in the source file
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Constant(_, span) => *span,
+            Expr::Builtin(_, span) => *span,
+            Expr::Call(_, _, span) => *span,
+            Expr::Let { span, .. } => *span,
+            Expr::Set { span, .. } => *span,
+            Expr::Do { span, .. } => *span,
+            Expr::While { span, .. } => *span,
+            Expr::Print { span, .. } => *span,
+            Expr::If { span, .. } => *span,
+            Expr::Cond { span, .. } => *span,
+            Expr::When { span, .. } => *span,
+            Expr::Defn { span, .. } => *span,
+            Expr::Lambda { span, .. } => *span,
+            Expr::Cast { span, .. } => *span,
+        }
+    }
+}
+
 /// generate_expression_tree
 ///
 /// Takes in a stream of `Token`s and generates an Expression
-/// tree of type Vec<Expr>
-
-pub fn generate_expression_tree(token_stream: Vec<Token>) -> Vec<Expr> {
-    let mut stack: Vec<Expr> = Vec::new();
-    for token in token_stream {
-        let expr = match token.kind {
-            TokenKind::OpenParen => continue,
-            TokenKind::Plus => Some(Expr::Builtin(Ident {
-                kind: IdentKind::Plus,
-                value: None,
-            })),
-            TokenKind::Minus => Some(Expr::Builtin(Ident {
-                kind: IdentKind::Minus,
-                value: None,
-            })),
-            TokenKind::Mult => Some(Expr::Builtin(Ident {
-                kind: IdentKind::Mult,
-                value: None,
-            })),
-            TokenKind::Divide => Some(Expr::Builtin(Ident {
-                kind: IdentKind::Div,
-                value: None,
-            })),
-            TokenKind::Ident(ident_kind) => match ident_kind {
-                IdentKind::Let => Some(Expr::Builtin(Ident {
-                    kind: IdentKind::Let,
-                    value: None,
-                })),
-                IdentKind::Print => Some(Expr::Builtin(Ident {
-                    kind: IdentKind::Print,
-                    value: None,
-                })),
-                IdentKind::Variable => Some(Expr::Builtin(Ident {
-                    kind: IdentKind::Variable,
-                    value: token.value,
-                })),
-                IdentKind::While => Some(Expr::Builtin(Ident {
-                    kind: IdentKind::While,
-                    value: None,
-                })),
-                _ => panic!("Invalid identifier kind"),
+/// tree of type Vec<Expr>. The tokens are first read into a
+/// list-structured tree of `SExpr`s, from which the special forms
+/// are then lowered. Malformed forms are reported in `diagnostics`
+/// and left out of the tree.
+
+pub fn generate_expression_tree<'a>(
+    token_stream: Vec<Token<'a>>,
+    diagnostics: &mut Diagnostics,
+) -> Vec<Expr<'a>> {
+    let mut expression_tree: Vec<Expr> = Vec::new();
+
+    for form in read_forms(token_stream, diagnostics) {
+        match lower_form(form) {
+            Ok(expr) => expression_tree.push(expr),
+            Err(diagnostic) => diagnostics.push(diagnostic),
+        }
+    }
+
+    expression_tree
+}
+
+fn lower_form(form: SExpr) -> Result<Expr, Diagnostic> {
+    match form {
+        SExpr::Atom(token) => match lower_atom(token)? {
+            Expr::Builtin(ident, span) if ident.is_builtin() => {
+                Err(Diagnostic::error("E0105", "builtin used as a value")
+                    .with_primary(span, "builtins can only be used at the start of a form"))
+            }
+            expr => Ok(expr),
+        },
+        SExpr::List(elements, span) => lower_list(elements, span),
+    }
+}
+
+/// Converts a single token into a constant or an identifier
+fn lower_atom(token: Token) -> Result<Expr, Diagnostic> {
+    let span = token.span;
+    let builtin = move |kind: IdentKind| Ok(Expr::Builtin(Ident { kind, value: None }, span));
+
+    match token.kind {
+        TokenKind::Plus => builtin(IdentKind::Plus),
+        TokenKind::Minus => builtin(IdentKind::Minus),
+        TokenKind::Mult => builtin(IdentKind::Mult),
+        TokenKind::Divide => builtin(IdentKind::Div),
+        TokenKind::Percent => builtin(IdentKind::Rem),
+        TokenKind::Ampersand => builtin(IdentKind::BitAnd),
+        TokenKind::Pipe => builtin(IdentKind::BitOr),
+        TokenKind::Caret => builtin(IdentKind::BitXor),
+        TokenKind::Tilde => builtin(IdentKind::BitNot),
+        TokenKind::ShiftLeft => builtin(IdentKind::Shl),
+        TokenKind::ShiftRight => builtin(IdentKind::Shr),
+        TokenKind::Greater => builtin(IdentKind::Greater),
+        TokenKind::Smaller => builtin(IdentKind::Smaller),
+        TokenKind::GreaterEqual => builtin(IdentKind::GreaterEqual),
+        TokenKind::SmallerEqual => builtin(IdentKind::SmallerEqual),
+        TokenKind::Equal => builtin(IdentKind::Equal),
+        TokenKind::NotEqual => builtin(IdentKind::NotEqual),
+        TokenKind::Ident(IdentKind::Variable) => Ok(Expr::Builtin(
+            Ident {
+                kind: IdentKind::Variable,
+                value: token.value,
+            },
+            token.span,
+        )),
+        TokenKind::Ident(kind) => builtin(kind),
+        TokenKind::Literal(LiteralKind::Boolean) | TokenKind::Literal(LiteralKind::Number) => {
+            Ok(Expr::Constant(token.value.unwrap(), token.span))
+        }
+        TokenKind::Literal(LiteralKind::String) => match token.value {
+            Some(Value::Str(text)) => Ok(Expr::Constant(Value::Str(text), token.span)),
+            _ => Err(Diagnostic::error("E0100", "invalid string literal")
+                .with_primary(token.span, "cannot be used here")),
+        },
+        TokenKind::Colon | TokenKind::Arrow => {
+            Err(Diagnostic::error("E0110", "unexpected type annotation")
+                .with_primary(token.span, "type annotations can only follow a name"))
+        }
+        TokenKind::OpenParen | TokenKind::CloseParen => {
+            unreachable!("parentheses are consumed by the reader")
+        }
+        TokenKind::Comment(_) | TokenKind::Discard => {
+            unreachable!("comments are skipped by the reader")
+        }
+    }
+}
+
+/// Lowers `(head args...)`, recognising the special forms by their head
+fn lower_list(elements: Vec<SExpr>, span: Span) -> Result<Expr, Diagnostic> {
+    let mut elements = elements.into_iter();
+
+    let head = match elements.next() {
+        Some(head) => head,
+        None => {
+            return Err(Diagnostic::error("E0103", "empty expression")
+                .with_primary(span, "expected a function or builtin name"))
+        }
+    };
+    let args: Vec<SExpr> = elements.collect();
+
+    let head = match head {
+        SExpr::Atom(token) => lower_atom(token)?,
+        list => lower_form(list)?,
+    };
+
+    match head {
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Let,
+                ..
+            },
+            _,
+        ) => lower_let(args, span),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Set,
+                ..
             },
-            TokenKind::Literal(LiteralKind::Boolean) => Some(Expr::Constant(token.value.unwrap())),
-            TokenKind::Literal(LiteralKind::Number) => Some(Expr::Constant(token.value.unwrap())),
-            TokenKind::Literal(LiteralKind::String) => match token.value {
-                Some(Value::String(str)) => Some(Expr::Constant(Value::String(str))),
-                _ => panic!("Invalid value for string literal"),
+            _,
+        ) => lower_set(args, span),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Do,
+                ..
             },
-            TokenKind::Greater => Some(Expr::Builtin(Ident {
-                kind: IdentKind::Greater,
-                value: None,
-            })),
-            TokenKind::Smaller => Some(Expr::Builtin(Ident {
-                kind: IdentKind::Smaller,
-                value: None,
-            })),
-
-            TokenKind::CloseParen => {
-                let mut params: Vec<Expr> = Vec::new();
-                // pop elements from stack until a Identitfier is found
-                loop {
-                    let expr = stack.pop();
-
-                    // params.push(expr.clone().unwrap());
-                    let expr_clone = expr.clone();
-                    match expr {
-                        Some(Expr::Builtin(identifier)) => {
-                            params.push(expr_clone.unwrap());
-                            if identifier.is_builtin() {
-                                break;
-                            }
-                        }
-                        None => break,
-                        _ => params.push(expr.clone().unwrap()),
-                    }
+            _,
+        ) => Ok(Expr::Do {
+            body: lower_forms(args)?,
+            span,
+        }),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::While,
+                ..
+            },
+            _,
+        ) => {
+            let mut args = args.into_iter();
+            let condition = match args.next() {
+                Some(condition) => lower_form(condition)?,
+                None => {
+                    return Err(Diagnostic::error("E0104", "`while` without a condition")
+                        .with_primary(span, "expected a condition after `while`"))
                 }
+            };
+
+            Ok(Expr::While {
+                condition: Box::new(condition),
+                body: lower_forms(args)?,
+                span,
+            })
+        }
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Print,
+                ..
+            },
+            _,
+        ) => Ok(Expr::Print {
+            args: lower_forms(args)?,
+            span,
+        }),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Defn,
+                ..
+            },
+            _,
+        ) => lower_defn(args, span),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Fn,
+                ..
+            },
+            _,
+        ) => lower_lambda(args, span),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::If,
+                ..
+            },
+            _,
+        ) => lower_if(args, span),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Cond,
+                ..
+            },
+            _,
+        ) => lower_cond(args, span),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::When,
+                ..
+            },
+            _,
+        ) => lower_when(args, span, false),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Unless,
+                ..
+            },
+            _,
+        ) => lower_when(args, span, true),
+        Expr::Builtin(
+            Ident {
+                kind: IdentKind::Variable,
+                value: Some(Value::String(name)),
+            },
+            head_span,
+        ) if is_numeric_type(name) => lower_cast(name, head_span, args, span),
+        Expr::Constant(_, constant_span) => {
+            Err(Diagnostic::error("E0106", "cannot call a literal")
+                .with_primary(constant_span, "expected a function or builtin name"))
+        }
+        head => Ok(Expr::Call(Box::new(head), lower_forms(args)?, span)),
+    }
+}
+
+/// Lowers `(let name)` and `(let name value)`, where the name
+/// may be followed by a type annotation: `(let name: type value)`
+fn lower_let(args: Vec<SExpr>, span: Span) -> Result<Expr, Diagnostic> {
+    let invalid_let = |label_span: Span, label: &str| {
+        Diagnostic::error("E0107", "invalid syntax for `let`")
+            .with_primary(label_span, label)
+            .with_note("variables are declared as `(let name value)`")
+    };
+
+    let mut args = args.into_iter().peekable();
+    let (name, name_span) = match args.next() {
+        Some(name) => lower_name(name, "variable")?,
+        None => return Err(invalid_let(span, "expected a variable name")),
+    };
+    let ty = lower_annotation(&mut args, TokenKind::Colon)?;
+
+    let value = match args.next() {
+        Some(value) => Some(Box::new(lower_form(value)?)),
+        None => None,
+    };
+
+    if let Some(extra) = args.next() {
+        return Err(invalid_let(extra.span(), "unexpected argument"));
+    }
+
+    Ok(Expr::Let {
+        name,
+        name_span,
+        ty,
+        value,
+        span,
+    })
+}
+
+/// Lowers `(set! name value)`
+fn lower_set(args: Vec<SExpr>, span: Span) -> Result<Expr, Diagnostic> {
+    let invalid_set = |label_span: Span, label: &str| {
+        Diagnostic::error("E0117", "invalid syntax for `set!`")
+            .with_primary(label_span, label)
+            .with_note("variables are assigned as `(set! name value)`")
+    };
+
+    let mut args = args.into_iter();
+    let (name, name_span) = match args.next() {
+        Some(name) => lower_name(name, "variable")?,
+        None => return Err(invalid_set(span, "expected a variable name")),
+    };
+
+    let value = match args.next() {
+        Some(value) => lower_form(value)?,
+        None => return Err(invalid_set(span, "expected a value after the name")),
+    };
+
+    if let Some(extra) = args.next() {
+        return Err(invalid_set(extra.span(), "unexpected argument"));
+    }
+
+    Ok(Expr::Set {
+        name,
+        name_span,
+        value: Box::new(value),
+        span,
+    })
+}
+
+/// Lowers `(defn name (params...) body...)`, where each parameter
+/// and the parameter list itself may be annotated with a type:
+/// `(defn name (a: i64 b: i64) -> i64 body...)`
+fn lower_defn(args: Vec<SExpr>, span: Span) -> Result<Expr, Diagnostic> {
+    let invalid_defn = |label_span: Span, label: &str| {
+        Diagnostic::error("E0109", "invalid syntax for `defn`")
+            .with_primary(label_span, label)
+            .with_note("functions are defined as `(defn name (params...) body...)`")
+    };
+
+    let mut args = args.into_iter().peekable();
+    let (name, name_span) = match args.next() {
+        Some(name) => lower_name(name, "function")?,
+        None => return Err(invalid_defn(span, "expected a function name")),
+    };
+
+    let params = match args.next() {
+        Some(SExpr::List(params, _)) => lower_params(params)?,
+        Some(other) => return Err(invalid_defn(other.span(), "expected a parameter list")),
+        None => return Err(invalid_defn(span, "expected a parameter list")),
+    };
+    let return_ty = lower_annotation(&mut args, TokenKind::Arrow)?;
+
+    Ok(Expr::Defn {
+        name,
+        name_span,
+        params,
+        return_ty,
+        body: lower_forms(args)?,
+        span,
+    })
+}
+
+/// Lowers `(fn (params...) body...)`, which is annotated like a
+/// `defn`: `(fn (a: i64 b: i64) -> i64 body...)`
+fn lower_lambda(args: Vec<SExpr>, span: Span) -> Result<Expr, Diagnostic> {
+    let mut args = args.into_iter().peekable();
+    let params = match args.next() {
+        Some(SExpr::List(params, _)) => lower_params(params)?,
+        other => {
+            let label_span = other.map_or(span, |other| other.span());
+            return Err(Diagnostic::error("E0118", "invalid syntax for `fn`")
+                .with_primary(label_span, "expected a parameter list")
+                .with_note("anonymous functions are written as `(fn (params...) body...)`"));
+        }
+    };
+    let return_ty = lower_annotation(&mut args, TokenKind::Arrow)?;
+
+    Ok(Expr::Lambda {
+        params,
+        return_ty,
+        body: lower_forms(args)?,
+        span,
+    })
+}
+
+/// Lowers `(type value)`, a conversion to the numeric type `name`
+fn lower_cast<'a>(
+    name: &'a str,
+    name_span: Span,
+    args: Vec<SExpr<'a>>,
+    span: Span,
+) -> Result<Expr<'a>, Diagnostic> {
+    let mut args = args.into_iter();
+
+    let value = match (args.next(), args.next()) {
+        (Some(value), None) => lower_form(value)?,
+        (None, _) => {
+            return Err(Diagnostic::error("E0115", "invalid conversion")
+                .with_primary(span, "expected a value to convert")
+                .with_note(format!("numbers are converted as `({} value)`", name)))
+        }
+        (Some(_), Some(extra)) => {
+            return Err(Diagnostic::error("E0115", "invalid conversion")
+                .with_primary(extra.span(), "unexpected argument")
+                .with_note(format!("numbers are converted as `({} value)`", name)))
+        }
+    };
+
+    Ok(Expr::Cast {
+        ty: TypeAnnotation {
+            name,
+            span: name_span,
+        },
+        value: Box::new(value),
+        span,
+    })
+}
 
-                let lead_ident = params.pop().unwrap();
-
-                let parent_expr = match lead_ident {
-                    Expr::Builtin(Ident {
-                        kind: IdentKind::While,
-                        value: _,
-                    }) => {
-                        let condition = params.pop().unwrap();
-                        // reverse params Vec to preserve expression order
-                        params.reverse();
-                        Some(Expr::While {
-                            condition: Box::new(condition),
-                            body: params,
-                        })
-                    }
-                    _ => {
-                        // reverse params Vec to preserve expression order
-                        params.reverse();
-                        Some(Expr::Call(Box::new(lead_ident), params))
-                    }
-                };
-
-                // create Expr from params and func name
-                // Some(Expr::Call(Box::new(func_name), params))
-                parent_expr
-            } // _ => panic!("Invaid expression"),
+fn is_numeric_type(name: &str) -> bool {
+    matches!(Type::from_name(name), Some(ty) if ty.is_numeric())
+}
+
+/// Lowers `(if condition then)` and `(if condition then else)`
+fn lower_if(args: Vec<SExpr>, span: Span) -> Result<Expr, Diagnostic> {
+    if args.len() < 2 || args.len() > 3 {
+        let label = match args.len() {
+            0 => "expected a condition",
+            1 => "expected a branch after the condition",
+            _ => "too many branches",
+        };
+        return Err(Diagnostic::error("E0112", "invalid syntax for `if`")
+            .with_primary(span, label)
+            .with_note("conditionals are written as `(if condition then else)`"));
+    }
+
+    let mut args = lower_forms(args)?.into_iter();
+    let condition = args.next().unwrap();
+    let then_branch = args.next().unwrap();
+
+    Ok(Expr::If {
+        condition: Box::new(condition),
+        then_branch: Box::new(then_branch),
+        else_branch: args.next().map(Box::new),
+        span,
+    })
+}
+
+/// Lowers `(cond (condition body...)... (else body...))`
+fn lower_cond(args: Vec<SExpr>, span: Span) -> Result<Expr, Diagnostic> {
+    let invalid_cond = |label_span: Span, label: &str| {
+        Diagnostic::error("E0113", "invalid syntax for `cond`")
+            .with_primary(label_span, label)
+            .with_note("clauses are written as `(condition body...)` or `(else body...)`")
+    };
+
+    if args.is_empty() {
+        return Err(invalid_cond(span, "expected at least one clause"));
+    }
+
+    let clause_count = args.len();
+    let mut clauses: Vec<CondClause> = Vec::new();
+
+    for (i, clause) in args.into_iter().enumerate() {
+        let (elements, clause_span) = match clause {
+            SExpr::List(elements, clause_span) if !elements.is_empty() => (elements, clause_span),
+            other => return Err(invalid_cond(other.span(), "expected a clause")),
         };
 
-        match expr {
-            None => (),
-            Some(_) => stack.push(expr.unwrap()),
+        let mut elements = elements.into_iter();
+        let condition = match elements.next().unwrap() {
+            SExpr::Atom(Token {
+                kind: TokenKind::Ident(IdentKind::Else),
+                ..
+            }) if i + 1 == clause_count => None,
+            SExpr::Atom(Token {
+                kind: TokenKind::Ident(IdentKind::Else),
+                span,
+                ..
+            }) => return Err(invalid_cond(span, "`else` must be the last clause")),
+            condition => Some(lower_form(condition)?),
+        };
+
+        clauses.push(CondClause {
+            condition,
+            body: lower_forms(elements)?,
+            span: clause_span,
+        });
+    }
+
+    Ok(Expr::Cond { clauses, span })
+}
+
+/// Lowers `(when condition body...)`, or `(unless condition body...)`
+/// if `negated`
+fn lower_when(args: Vec<SExpr>, span: Span, negated: bool) -> Result<Expr, Diagnostic> {
+    let mut args = args.into_iter();
+    let condition = match args.next() {
+        Some(condition) => lower_form(condition)?,
+        None => {
+            let keyword = if negated { "unless" } else { "when" };
+            return Err(
+                Diagnostic::error("E0114", format!("`{}` without a condition", keyword))
+                    .with_primary(span, format!("expected a condition after `{}`", keyword)),
+            );
+        }
+    };
+
+    Ok(Expr::When {
+        condition: Box::new(condition),
+        body: lower_forms(args)?,
+        negated,
+        span,
+    })
+}
+
+fn lower_params(params: Vec<SExpr>) -> Result<Vec<Param>, Diagnostic> {
+    let mut params = params.into_iter().peekable();
+    let mut lowered: Vec<Param> = Vec::new();
+
+    while let Some(param) = params.next() {
+        let (name, span) = lower_name(param, "parameter")?;
+        let ty = lower_annotation(&mut params, TokenKind::Colon)?;
+        lowered.push(Param { name, span, ty });
+    }
+
+    Ok(lowered)
+}
+
+/// Lowers the type annotation introduced by `marker` (`:` or `->`)
+/// if it is the next form, leaving `forms` untouched otherwise
+fn lower_annotation<'a>(
+    forms: &mut Forms<'a>,
+    marker: TokenKind,
+) -> Result<Option<TypeAnnotation<'a>>, Diagnostic> {
+    let marker_span = match forms.peek() {
+        Some(SExpr::Atom(token)) if token.kind == marker => token.span,
+        _ => return Ok(None),
+    };
+    forms.next();
+
+    match forms.next() {
+        Some(SExpr::Atom(Token {
+            kind: TokenKind::Ident(IdentKind::Variable),
+            value: Some(Value::String(name)),
+            span,
+        })) => Ok(Some(TypeAnnotation { name, span })),
+        Some(other) => Err(Diagnostic::error("E0111", "expected a type name")
+            .with_primary(other.span(), "not a type name")),
+        None => Err(Diagnostic::error("E0111", "expected a type name")
+            .with_primary(marker_span, "missing a type after this")),
+    }
+}
+
+/// Expects `form` to be a plain identifier naming a `what`
+fn lower_name<'a>(form: SExpr<'a>, what: &str) -> Result<(&'a str, Span), Diagnostic> {
+    match form {
+        // calls with a type name are conversions, so nothing by that
+        // name could ever be called
+        SExpr::Atom(Token {
+            kind: TokenKind::Ident(IdentKind::Variable),
+            value: Some(Value::String(name)),
+            span,
+        }) if is_numeric_type(name) => {
+            Err(Diagnostic::error("E0108", format!("invalid {} name", what))
+                .with_primary(span, format!("`{}` is the name of a type", name)))
         }
-        // stack.push(expr.unwrap());
+        SExpr::Atom(Token {
+            kind: TokenKind::Ident(IdentKind::Variable),
+            value: Some(Value::String(name)),
+            span,
+        }) => Ok((name, span)),
+        other => Err(Diagnostic::error("E0108", format!("invalid {} name", what))
+            .with_primary(other.span(), "expected an identifier")),
     }
+}
 
-    stack
+fn lower_forms<'a>(
+    forms: impl IntoIterator<Item = SExpr<'a>>,
+) -> Result<Vec<Expr<'a>>, Diagnostic> {
+    forms.into_iter().map(lower_form).collect()
 }
@@ -0,0 +1,146 @@
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::tispc_lexer::{Span, Token, TokenKind};
+
+/// S-expression read from the token stream, before any
+/// special forms (`let`, `while`, ...) have been recognised
+#[derive(Debug, Clone, PartialEq)]
+pub enum SExpr<'a> {
+    Atom(Token<'a>),
+    List(Vec<SExpr<'a>>, Span),
+}
+
+impl<'a> SExpr<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            SExpr::Atom(token) => token.span,
+            SExpr::List(_, span) => *span,
+        }
+    }
+}
+
+/// read_forms
+///
+/// Recursive-descent reader that groups a stream of `Token`s into
+/// top-level S-expressions following `OpenParen`/`CloseParen`.
+/// Comments are skipped, along with the forms commented out by `#_`.
+/// Unbalanced parentheses are reported in `diagnostics`, and a form
+/// that is never closed is left out of the result.
+pub fn read_forms<'a>(
+    token_stream: Vec<Token<'a>>,
+    diagnostics: &mut Diagnostics,
+) -> Vec<SExpr<'a>> {
+    let mut reader = Reader {
+        tokens: token_stream
+            .into_iter()
+            .filter(|token| !token.kind.is_comment())
+            .collect(),
+        position: 0,
+    };
+
+    let mut forms: Vec<SExpr> = Vec::new();
+    while reader.peek().is_some() {
+        if let Err(diagnostic) = reader.skip_discarded() {
+            diagnostics.push(diagnostic);
+            continue;
+        }
+
+        let token = match reader.peek() {
+            Some(token) => token,
+            None => break,
+        };
+        match token.kind {
+            TokenKind::CloseParen => {
+                diagnostics.push(
+                    Diagnostic::error("E0101", "unexpected closing parenthesis")
+                        .with_primary(token.span, "no matching `(`"),
+                );
+                reader.position += 1;
+            }
+            _ => match reader.read_form() {
+                Ok(form) => forms.push(form),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            },
+        }
+    }
+
+    forms
+}
+
+struct Reader<'a> {
+    tokens: Vec<Token<'a>>,
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Reads one atom or one complete (possibly nested) list
+    fn read_form(&mut self) -> Result<SExpr<'a>, Diagnostic> {
+        let token = self.next().expect("read_form called at end of input");
+
+        match token.kind {
+            TokenKind::OpenParen => self.read_list(token.span),
+            _ => Ok(SExpr::Atom(token)),
+        }
+    }
+
+    /// Skips any `#_` at the current position together with the form
+    /// it comments out. `#_ #_ a b` comments out both `a` and `b`.
+    fn skip_discarded(&mut self) -> Result<(), Diagnostic> {
+        while let Some(Token {
+            kind: TokenKind::Discard,
+            span,
+            ..
+        }) = self.peek()
+        {
+            let discard_span = *span;
+            self.position += 1;
+            self.skip_discarded()?;
+
+            match self.peek() {
+                Some(token) if token.kind != TokenKind::CloseParen => {
+                    self.read_form()?;
+                }
+                _ => {
+                    return Err(Diagnostic::error("E0116", "nothing to comment out")
+                        .with_primary(discard_span, "`#_` must be followed by a form"))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the elements of a list up to and including its `CloseParen`
+    fn read_list(&mut self, open_span: Span) -> Result<SExpr<'a>, Diagnostic> {
+        let mut elements: Vec<SExpr> = Vec::new();
+
+        loop {
+            self.skip_discarded()?;
+            match self.peek() {
+                Some(Token {
+                    kind: TokenKind::CloseParen,
+                    span,
+                    ..
+                }) => {
+                    let span = open_span.to(*span);
+                    self.position += 1;
+                    return Ok(SExpr::List(elements, span));
+                }
+                Some(_) => elements.push(self.read_form()?),
+                None => {
+                    return Err(Diagnostic::error("E0102", "unclosed parenthesis")
+                        .with_primary(open_span, "this `(` is never closed"))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,946 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use super::order::defn_order;
+use super::scopes::Scopes;
+use super::types::{Substitution, Type, TypeTable, TypeVar};
+use super::unify::{Constraint, Unifier};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::tispc_lexer::{Ident, IdentKind, Span, Value};
+use crate::tispc_parser::{CondClause, Expr, Param, TypeAnnotation};
+
+/// check_types
+///
+/// Infers the type of every expression between parsing and codegen,
+/// Hindley–Milner style. Annotations are optional: unannotated
+/// variables, parameters and return types get the most general type
+/// that fits how they are used. Functions that work on more than one
+/// type are generic, and their type keeps its type variables; every
+/// other type variable left at the end defaults to `f64` if it must be
+/// a float, to `i64` if it must be a number, an integer, printable or
+/// comparable with `=`, and to `unit` if nothing constrains it.
+/// Mismatches are reported in `diagnostics`.
+pub fn check_types<'a>(expression_tree: &[Expr<'a>], diagnostics: &mut Diagnostics) -> TypeTable {
+    TypeChecker::new().check_program(expression_tree, diagnostics)
+}
+
+/// Type of a function, generic over `vars`
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<TypeVar>,
+    ty: Type,
+}
+
+#[derive(Debug, Clone)]
+struct Signature {
+    scheme: Scheme,
+    // spans of the parameters, used to explain argument mismatches
+    param_spans: Vec<Span>,
+}
+
+/// TypeChecker
+///
+/// Inference state of a program. `check_types` uses a fresh checker
+/// for every file, while the REPL keeps one for the whole session so
+/// that each input sees the functions and variables of the ones
+/// before it.
+#[derive(Debug, Clone, Default)]
+pub struct TypeChecker<'a> {
+    functions: HashMap<&'a str, Signature>,
+    variables: Scopes<'a, (Type, Option<Span>)>,
+    unifier: Unifier,
+    // variables quantified over by generic functions
+    type_params: HashSet<TypeVar>,
+    // depth of the parameter scope of the innermost `fn` being
+    // checked; the variables of scopes below it are captured
+    lambda_scope: Option<usize>,
+    table: TypeTable,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new() -> TypeChecker<'a> {
+        TypeChecker {
+            functions: HashMap::new(),
+            variables: Scopes::new(),
+            unifier: Unifier::new(),
+            type_params: HashSet::new(),
+            lambda_scope: None,
+            table: TypeTable::new(),
+        }
+    }
+
+    /// Checks the top-level expressions of a program (or REPL input)
+    /// and returns the types of their subexpressions
+    pub fn check_program(
+        &mut self,
+        expression_tree: &[Expr<'a>],
+        diagnostics: &mut Diagnostics,
+    ) -> TypeTable {
+        // declare top-level functions up front so they can be
+        // called before their definition
+        for expression in expression_tree {
+            if let Expr::Defn { .. } = expression {
+                if let Err(diagnostic) = self.declare_function(expression) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+
+        // functions are checked, and generalized, before the code that
+        // calls them, or a call would pin down the type variables of a
+        // generic function. The rest of the program can come after
+        // them, since functions only see their parameters.
+        let mut order = defn_order(expression_tree);
+        order.extend(
+            expression_tree
+                .iter()
+                .enumerate()
+                .filter(|(_, expression)| !matches!(expression, Expr::Defn { .. }))
+                .map(|(index, _)| index),
+        );
+
+        // errors are still reported in the order of the program
+        let mut errors: Vec<(usize, Diagnostic)> = Vec::new();
+        for index in order {
+            if let Err(diagnostic) = self.infer(&expression_tree[index]) {
+                errors.push((index, diagnostic));
+            }
+        }
+        errors.sort_by_key(|(index, _)| *index);
+        diagnostics.extend(errors.into_iter().map(|(_, diagnostic)| diagnostic));
+
+        self.unifier.apply_defaults(&self.type_params);
+        let unifier = &self.unifier;
+        self.table.map(|ty| unifier.resolve(ty));
+
+        mem::take(&mut self.table)
+    }
+
+    /// Infers the type of `expr` and renders it for the user
+    pub fn describe_expr(&mut self, expr: &Expr<'a>) -> Result<String, Diagnostic> {
+        let ty = self.infer(expr)?;
+        Ok(self.unifier.describe(&ty))
+    }
+
+    /// Type of the function called `name` as shown to the user
+    pub fn describe_function(&self, name: &str) -> Option<String> {
+        self.functions
+            .get(name)
+            .map(|signature| self.describe(&signature.scheme.ty))
+    }
+
+    /// Renders a type this checker inferred for the user, e.g. one
+    /// from the table `check_program` returned
+    pub fn describe(&self, ty: &Type) -> String {
+        self.unifier.describe(ty)
+    }
+
+    fn infer(&mut self, expr: &Expr<'a>) -> Result<Type, Diagnostic> {
+        let ty = match expr {
+            // integer literals can also be used as floats
+            Expr::Constant(Value::Integer(_), _) => self.unifier.fresh(Constraint::Numeric),
+            Expr::Constant(Value::Float(_), _) => self.unifier.fresh(Constraint::Float),
+            Expr::Constant(Value::Boolean(_), _) => Type::Bool,
+            Expr::Constant(Value::Str(_), _) => Type::Str,
+            Expr::Constant(Value::String(_), _) => {
+                unreachable!("names are parsed into builtins, not constants")
+            }
+
+            Expr::Builtin(
+                Ident {
+                    kind: IdentKind::Variable,
+                    value: Some(Value::String(name)),
+                },
+                span,
+            ) => match self.variables.get(name) {
+                Some((ty, _)) => ty.clone(),
+                None => {
+                    return Err(Diagnostic::error(
+                        "E0301",
+                        format!("cannot find variable `{}`", name),
+                    )
+                    .with_primary(*span, "not found in this scope"))
+                }
+            },
+            Expr::Builtin(_, span) => {
+                return Err(Diagnostic::error("E0309", "builtin used as a value")
+                    .with_primary(*span, "builtins can only be called"))
+            }
+
+            Expr::Let {
+                name,
+                name_span,
+                ty,
+                value,
+                ..
+            } => self.infer_let(name, *name_span, ty.as_ref(), value.as_deref())?,
+
+            Expr::Set {
+                name,
+                name_span,
+                value,
+                ..
+            } => self.infer_set(name, *name_span, value)?,
+
+            Expr::Do { body, .. } => self.infer_block(body)?,
+
+            Expr::While {
+                condition, body, ..
+            } => {
+                self.check(condition, &Type::Bool, None)?;
+                self.infer_block(body)?;
+                Type::Unit
+            }
+
+            Expr::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.check(condition, &Type::Bool, None)?;
+                let then_ty = self.in_scope(|checker| checker.infer(then_branch))?;
+                match else_branch {
+                    Some(else_branch) => {
+                        self.in_scope(|checker| {
+                            checker.check(else_branch, &then_ty, Some(then_branch.span()))
+                        })?;
+                        then_ty
+                    }
+                    None => Type::Unit,
+                }
+            }
+
+            Expr::Cond { clauses, .. } => self.infer_cond(clauses)?,
+
+            Expr::When {
+                condition, body, ..
+            } => {
+                self.check(condition, &Type::Bool, None)?;
+                self.infer_block(body)?;
+                Type::Unit
+            }
+
+            Expr::Print { args, .. } => {
+                for arg in args {
+                    let ty = self.infer(arg)?;
+                    if self.unifier.require(&ty, Constraint::Printable).is_err() {
+                        let ty = self.unifier.describe(&ty);
+                        return Err(Diagnostic::error(
+                            "E0305",
+                            format!("cannot print values of type `{}`", ty),
+                        )
+                        .with_primary(arg.span(), format!("this is of type `{}`", ty)));
+                    }
+                }
+                Type::Unit
+            }
+
+            Expr::Defn { .. } => {
+                // the table entry of a `defn` holds the function's type
+                self.check_defn(expr)?;
+                return Ok(Type::Unit);
+            }
+
+            Expr::Lambda {
+                params,
+                return_ty,
+                body,
+                span,
+            } => self.infer_lambda(params, return_ty.as_ref(), body, *span)?,
+
+            Expr::Call(head, args, span) => self.infer_call(head, args, *span)?,
+
+            Expr::Cast { ty, value, .. } => {
+                let from = self.infer(value)?;
+                if self.unifier.require(&from, Constraint::Numeric).is_err() {
+                    let from = self.unifier.describe(&from);
+                    return Err(Diagnostic::error(
+                        "E0311",
+                        format!("cannot convert `{}` to `{}`", from, ty.name),
+                    )
+                    .with_primary(value.span(), format!("this is of type `{}`", from))
+                    .with_note("only numbers can be converted"));
+                }
+                resolve_annotation(ty)?
+            }
+        };
+
+        self.table.insert(expr.span(), ty.clone());
+        Ok(ty)
+    }
+
+    /// Checks that `expr` has type `expected`. `origin` is the span
+    /// of the code that made the caller expect that type, if any.
+    fn check(
+        &mut self,
+        expr: &Expr<'a>,
+        expected: &Type,
+        origin: Option<Span>,
+    ) -> Result<(), Diagnostic> {
+        let found = self.infer(expr)?;
+
+        if self.unifier.unify(expected, &found).is_ok() {
+            return Ok(());
+        }
+
+        let expected = self.unifier.describe(expected);
+        let mut diagnostic = Diagnostic::error("E0300", "mismatched types").with_primary(
+            expr.span(),
+            format!(
+                "expected `{}`, found `{}`",
+                expected,
+                self.unifier.describe(&found)
+            ),
+        );
+        if let Some(origin) = origin {
+            diagnostic = diagnostic
+                .with_secondary(origin, format!("expected `{}` because of this", expected));
+        }
+
+        Err(diagnostic)
+    }
+
+    fn infer_let(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        annotation: Option<&TypeAnnotation>,
+        value: Option<&Expr<'a>>,
+    ) -> Result<Type, Diagnostic> {
+        let (ty, origin) = match annotation {
+            Some(annotation) => (resolve_annotation(annotation)?, Some(annotation.span)),
+            // `(let name)` starts the variable off as the number 0
+            None if value.is_none() => (self.unifier.fresh(Constraint::Numeric), None),
+            None => (self.unifier.fresh(Constraint::Any), None),
+        };
+
+        // the value is checked before the variable is declared, so
+        // `(let n (+ n 1))` reads the `n` it shadows
+        if let Some(value) = value {
+            self.check(value, &ty, origin)?;
+        }
+
+        // assignments are checked against the declaration
+        let origin = origin.or(Some(name_span));
+        self.variables.declare(name, (ty.clone(), origin));
+        Ok(ty)
+    }
+
+    /// `set!` stores into the innermost variable called `name`, which
+    /// keeps its type
+    fn infer_set(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        value: &Expr<'a>,
+    ) -> Result<Type, Diagnostic> {
+        let (ty, origin) = match self.variables.get(name) {
+            Some(variable) => variable.clone(),
+            None => {
+                return Err(Diagnostic::error(
+                    "E0312",
+                    format!("cannot assign to undefined variable `{}`", name),
+                )
+                .with_primary(name_span, "not found in this scope")
+                .with_note(format!("declare it first with `(let {} value)`", name)))
+            }
+        };
+
+        if let Some(lambda_scope) = self.lambda_scope {
+            if self.variables.depth_of(name) < Some(lambda_scope) {
+                return Err(Diagnostic::error(
+                    "E0313",
+                    format!("cannot assign to captured variable `{}`", name),
+                )
+                .with_primary(name_span, "captured by value")
+                .with_note("a `fn` gets a copy of the variables it uses when it is created"));
+            }
+        }
+
+        self.check(value, &ty, origin)?;
+        Ok(ty)
+    }
+
+    /// Infers the expressions of a body in a scope of their own. The
+    /// body has the type of its last expression, or `unit` if empty.
+    fn infer_block(&mut self, body: &[Expr<'a>]) -> Result<Type, Diagnostic> {
+        self.in_scope(|checker| {
+            let mut ty = Type::Unit;
+            for expr in body {
+                ty = checker.infer(expr)?;
+            }
+            Ok(ty)
+        })
+    }
+
+    /// Runs `f` in a new scope, which is left again even if `f` fails
+    fn in_scope<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, Diagnostic>,
+    ) -> Result<T, Diagnostic> {
+        self.variables.push();
+        let result = f(self);
+        self.variables.pop();
+        result
+    }
+
+    /// Gives a `defn` a type from its annotations, with a fresh type
+    /// variable for every missing annotation
+    fn declare_function(&mut self, defn: &Expr<'a>) -> Result<(), Diagnostic> {
+        let (name, params, return_ty) = match defn {
+            Expr::Defn {
+                name,
+                params,
+                return_ty,
+                ..
+            } => (*name, params, return_ty),
+            _ => unreachable!("declare_function called on a non-defn expression"),
+        };
+
+        let mut param_types: Vec<Type> = Vec::new();
+        for param in params {
+            param_types.push(self.resolve_param(param)?);
+        }
+
+        let ret = match return_ty {
+            Some(annotation) => resolve_annotation(annotation)?,
+            None => self.unifier.fresh(Constraint::Any),
+        };
+
+        self.functions.insert(
+            name,
+            Signature {
+                scheme: Scheme {
+                    vars: Vec::new(),
+                    ty: Type::Fn(param_types, Box::new(ret)),
+                },
+                param_spans: params.iter().map(|param| param.span).collect(),
+            },
+        );
+
+        Ok(())
+    }
+
+    fn check_defn(&mut self, defn: &Expr<'a>) -> Result<(), Diagnostic> {
+        let (name, params, return_ty, body, span) = match defn {
+            Expr::Defn {
+                name,
+                params,
+                return_ty,
+                body,
+                span,
+                ..
+            } => (*name, params, return_ty, body, *span),
+            _ => unreachable!("check_defn called on a non-defn expression"),
+        };
+
+        // top-level functions were declared before checking started,
+        // unless they have been checked (and generalized) already
+        let declared = match self.functions.get(name) {
+            Some(signature) => signature.scheme.vars.is_empty(),
+            None => false,
+        };
+        if !declared {
+            self.declare_function(defn)?;
+        }
+
+        let fn_type = self.functions[name].scheme.ty.clone();
+        let (param_types, ret) = match &fn_type {
+            Type::Fn(params, ret) => (params.clone(), (**ret).clone()),
+            _ => unreachable!("functions are declared with a function type"),
+        };
+
+        // every function has its own scopes, starting out with only
+        // its parameters
+        let caller_variables = mem::take(&mut self.variables);
+        let caller_lambda_scope = self.lambda_scope.take();
+        for (param, ty) in params.iter().zip(param_types.iter()) {
+            self.variables
+                .declare(param.name, (ty.clone(), Some(param.span)));
+        }
+
+        let origin = return_ty.as_ref().map(|annotation| annotation.span);
+        let result = self.check_body(body, &ret, origin, span);
+        self.variables = caller_variables;
+        self.lambda_scope = caller_lambda_scope;
+        result?;
+
+        let scheme = self.generalize(name, &fn_type);
+        self.type_params.extend(scheme.vars.iter());
+        if let Some(signature) = self.functions.get_mut(name) {
+            signature.scheme = scheme;
+        }
+        self.table.insert(span, fn_type);
+
+        Ok(())
+    }
+
+    /// Infers the type of a `fn`, whose body sees the variables around
+    /// it as well as its parameters. Unlike functions defined with
+    /// `defn` it is not generic: how it is used decides the types its
+    /// annotations leave open.
+    fn infer_lambda(
+        &mut self,
+        params: &[Param<'a>],
+        return_ty: Option<&TypeAnnotation>,
+        body: &[Expr<'a>],
+        span: Span,
+    ) -> Result<Type, Diagnostic> {
+        let mut param_types: Vec<Type> = Vec::new();
+        for param in params {
+            param_types.push(self.resolve_param(param)?);
+        }
+
+        let ret = match return_ty {
+            Some(annotation) => resolve_annotation(annotation)?,
+            None => self.unifier.fresh(Constraint::Any),
+        };
+        let origin = return_ty.map(|annotation| annotation.span);
+
+        let enclosing_lambda_scope = self.lambda_scope.replace(self.variables.depth());
+        let result = self.in_scope(|checker| {
+            for (param, ty) in params.iter().zip(param_types.iter()) {
+                checker
+                    .variables
+                    .declare(param.name, (ty.clone(), Some(param.span)));
+            }
+            checker.check_body(body, &ret, origin, span)
+        });
+        self.lambda_scope = enclosing_lambda_scope;
+        result?;
+
+        Ok(Type::Fn(param_types, Box::new(ret)))
+    }
+
+    /// Checks that the last expression of a function body or `cond`
+    /// clause has type `expected`. An empty body has type `unit`.
+    fn check_body(
+        &mut self,
+        body: &[Expr<'a>],
+        expected: &Type,
+        origin: Option<Span>,
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        let (last, init) = match body.split_last() {
+            Some(split) => split,
+            None => {
+                if self.unifier.unify(expected, &Type::Unit).is_ok() {
+                    return Ok(());
+                }
+
+                let expected = self.unifier.describe(expected);
+                let mut diagnostic = Diagnostic::error("E0300", "mismatched types").with_primary(
+                    span,
+                    format!("expected `{}`, but the body is empty", expected),
+                );
+                if let Some(origin) = origin {
+                    diagnostic = diagnostic
+                        .with_secondary(origin, format!("expected `{}` because of this", expected));
+                }
+                return Err(diagnostic);
+            }
+        };
+
+        for expr in init {
+            self.infer(expr)?;
+        }
+
+        self.check(last, expected, origin)
+    }
+
+    /// A `cond` with an `else` clause yields the value of the clause
+    /// that ran, so all clauses must have the same type. Without an
+    /// `else` there may be no value, and the `cond` is `unit`.
+    fn infer_cond(&mut self, clauses: &[CondClause<'a>]) -> Result<Type, Diagnostic> {
+        let has_else = match clauses.last() {
+            Some(clause) => clause.condition.is_none(),
+            None => false,
+        };
+        let ty = if has_else {
+            self.unifier.fresh(Constraint::Any)
+        } else {
+            Type::Unit
+        };
+
+        // the first clause decides the type of the others
+        let mut origin: Option<Span> = None;
+        for clause in clauses {
+            if let Some(condition) = &clause.condition {
+                self.check(condition, &Type::Bool, None)?;
+            }
+
+            if has_else {
+                self.in_scope(|checker| {
+                    checker.check_body(&clause.body, &ty, origin, clause.span)
+                })?;
+                let clause_origin = clause.body.last().map_or(clause.span, Expr::span);
+                origin = origin.or(Some(clause_origin));
+            } else {
+                self.infer_block(&clause.body)?;
+            }
+        }
+
+        Ok(ty)
+    }
+
+    /// Quantifies the type of function `name` over the type variables
+    /// that no other function depends on
+    fn generalize(&self, name: &str, fn_type: &Type) -> Scheme {
+        let mut env_vars: HashSet<TypeVar> = HashSet::new();
+        for (other, signature) in self.functions.iter() {
+            if *other == name {
+                continue;
+            }
+            for var in self.unifier.free_vars(&signature.scheme.ty) {
+                if !signature.scheme.vars.contains(&var) {
+                    env_vars.insert(var);
+                }
+            }
+        }
+
+        Scheme {
+            vars: self
+                .unifier
+                .free_vars(fn_type)
+                .into_iter()
+                .filter(|var| !env_vars.contains(var))
+                .collect(),
+            ty: fn_type.clone(),
+        }
+    }
+
+    /// Copies the type of a generic function with fresh variables
+    /// in place of its type parameters
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mut substitution = Substitution::new();
+        for var in scheme.vars.iter() {
+            let constraint = self.unifier.constraint(*var);
+            substitution.insert(*var, self.unifier.fresh(constraint));
+        }
+
+        self.unifier.resolve(&scheme.ty).substitute(&substitution)
+    }
+
+    /// Type of a call. A variable holding a closure shadows a function
+    /// of the same name, and the compiler, the interpreter and the
+    /// language server all resolve calls the same way.
+    fn infer_call(
+        &mut self,
+        head: &Expr<'a>,
+        args: &[Expr<'a>],
+        span: Span,
+    ) -> Result<Type, Diagnostic> {
+        let (kind, name, head_span) = match head {
+            Expr::Builtin(Ident { kind, value }, head_span) => (kind, value, *head_span),
+            // anything else has to evaluate to a function value
+            callee => return self.infer_closure_call(callee, args, span),
+        };
+
+        match (kind, name) {
+            (IdentKind::Variable, Some(Value::String(name)))
+                if self.variables.get(name).is_some() =>
+            {
+                self.infer_closure_call(head, args, span)
+            }
+            (IdentKind::Variable, Some(Value::String(name))) => {
+                self.infer_user_call(name, head_span, args, span)
+            }
+            (IdentKind::Plus, _)
+            | (IdentKind::Minus, _)
+            | (IdentKind::Mult, _)
+            | (IdentKind::Div, _) => {
+                if args.is_empty() {
+                    return Err(Diagnostic::error("E0308", "missing operands")
+                        .with_primary(head_span, "expects at least one operand"));
+                }
+                self.infer_operands(args, head_span, Constraint::Numeric)
+            }
+            (IdentKind::Rem, _)
+            | (IdentKind::BitAnd, _)
+            | (IdentKind::BitOr, _)
+            | (IdentKind::BitXor, _) => {
+                if args.is_empty() {
+                    return Err(Diagnostic::error("E0308", "missing operands")
+                        .with_primary(head_span, "expects at least one operand"));
+                }
+                self.infer_operands(args, head_span, Constraint::Integer)
+            }
+            (IdentKind::Shl, _) | (IdentKind::Shr, _) => {
+                if args.len() != 2 {
+                    return Err(Diagnostic::error(
+                        "E0308",
+                        format!("shifts take 2 operands but {} were given", args.len()),
+                    )
+                    .with_primary(span, "expected 2 operands"));
+                }
+                self.infer_operands(args, head_span, Constraint::Integer)
+            }
+            (IdentKind::BitNot, _) => {
+                if args.len() != 1 {
+                    return Err(Diagnostic::error(
+                        "E0308",
+                        format!("`~` takes 1 operand but {} were given", args.len()),
+                    )
+                    .with_primary(span, "expected 1 operand"));
+                }
+                self.infer_operands(args, head_span, Constraint::Integer)
+            }
+            (IdentKind::Greater, _)
+            | (IdentKind::Smaller, _)
+            | (IdentKind::GreaterEqual, _)
+            | (IdentKind::SmallerEqual, _)
+            | (IdentKind::Equal, _)
+            | (IdentKind::NotEqual, _) => {
+                if args.len() != 2 {
+                    return Err(Diagnostic::error(
+                        "E0308",
+                        format!("comparisons take 2 operands but {} were given", args.len()),
+                    )
+                    .with_primary(span, "expected 2 operands"));
+                }
+                let constraint = match kind {
+                    IdentKind::Equal | IdentKind::NotEqual => Constraint::Equatable,
+                    _ => Constraint::Numeric,
+                };
+                self.infer_operands(args, head_span, constraint)?;
+                Ok(Type::Bool)
+            }
+            (IdentKind::And, _) | (IdentKind::Or, _) => {
+                if args.is_empty() {
+                    return Err(Diagnostic::error("E0308", "missing operands")
+                        .with_primary(head_span, "expects at least one operand"));
+                }
+                for arg in args {
+                    self.check(arg, &Type::Bool, None)?;
+                }
+                Ok(Type::Bool)
+            }
+            (IdentKind::Not, _) => {
+                self.check_operands("not", args, &[Type::Bool], span)?;
+                Ok(Type::Bool)
+            }
+            (IdentKind::StrConcat, _) => {
+                if args.is_empty() {
+                    return Err(Diagnostic::error("E0308", "missing operands")
+                        .with_primary(head_span, "expects at least one operand"));
+                }
+                for arg in args {
+                    self.check(arg, &Type::Str, None)?;
+                }
+                Ok(Type::Str)
+            }
+            (IdentKind::StrLen, _) => {
+                self.check_operands("str-len", args, &[Type::Str], span)?;
+                Ok(Type::I64)
+            }
+            (IdentKind::Substr, _) => {
+                let operands = [Type::Str, Type::I64, Type::I64];
+                self.check_operands("substr", args, &operands, span)?;
+                Ok(Type::Str)
+            }
+            (IdentKind::StrEqual, _) => {
+                self.check_operands("str=", args, &[Type::Str, Type::Str], span)?;
+                Ok(Type::Bool)
+            }
+            _ => Err(Diagnostic::error("E0309", "cannot call this expression")
+                .with_primary(head_span, "cannot be called")),
+        }
+    }
+
+    /// Checks the operands of a builtin that takes a fixed number of
+    /// operands of known types
+    fn check_operands(
+        &mut self,
+        name: &str,
+        args: &[Expr<'a>],
+        expected: &[Type],
+        span: Span,
+    ) -> Result<(), Diagnostic> {
+        if args.len() != expected.len() {
+            let operands = match expected.len() {
+                1 => String::from("1 operand"),
+                count => format!("{} operands", count),
+            };
+            return Err(Diagnostic::error(
+                "E0308",
+                format!(
+                    "`{}` takes {} but {} were given",
+                    name,
+                    operands,
+                    args.len()
+                ),
+            )
+            .with_primary(span, format!("expected {}", operands)));
+        }
+
+        for (arg, ty) in args.iter().zip(expected.iter()) {
+            self.check(arg, ty, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// All operands of an arithmetic or comparison builtin must share
+    /// one type allowed by `constraint`, which is checked against the
+    /// first operand that isn't a bare number
+    fn infer_operands(
+        &mut self,
+        args: &[Expr<'a>],
+        operator_span: Span,
+        constraint: Constraint,
+    ) -> Result<Type, Diagnostic> {
+        let first = args
+            .iter()
+            .position(|arg| {
+                !matches!(
+                    arg,
+                    Expr::Constant(Value::Integer(_), _) | Expr::Constant(Value::Float(_), _)
+                )
+            })
+            .unwrap_or(0);
+        let origin = args[first].span();
+
+        let ty = self.infer(&args[first])?;
+        if self.unifier.require(&ty, constraint).is_err() {
+            let ty = self.unifier.describe(&ty);
+            let expects = match constraint {
+                Constraint::Equatable => "expects numbers or booleans",
+                Constraint::Integer => "expects integers",
+                _ => "expects numbers",
+            };
+            return Err(Diagnostic::error(
+                "E0307",
+                format!("cannot apply this operator to values of type `{}`", ty),
+            )
+            .with_primary(origin, format!("this is of type `{}`", ty))
+            .with_secondary(operator_span, expects));
+        }
+
+        for (i, arg) in args.iter().enumerate() {
+            if i != first {
+                self.check(arg, &ty, Some(origin))?;
+            }
+        }
+
+        Ok(ty)
+    }
+
+    fn infer_user_call(
+        &mut self,
+        name: &'a str,
+        name_span: Span,
+        args: &[Expr<'a>],
+        span: Span,
+    ) -> Result<Type, Diagnostic> {
+        let (scheme, param_spans) = match self.functions.get(name) {
+            Some(signature) => (signature.scheme.clone(), signature.param_spans.clone()),
+            None => {
+                return Err(Diagnostic::error(
+                    "E0302",
+                    format!("function `{}` is not defined", name),
+                )
+                .with_primary(name_span, "not found in this scope"))
+            }
+        };
+
+        let instance = self.instantiate(&scheme);
+        let (params, ret) = match &instance {
+            Type::Fn(params, ret) => (params.clone(), (**ret).clone()),
+            _ => unreachable!("functions are declared with a function type"),
+        };
+
+        if args.len() != params.len() {
+            return Err(Diagnostic::error(
+                "E0304",
+                format!(
+                    "function `{}` takes {} arguments but {} were given",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+            )
+            .with_primary(span, format!("expected {} arguments", params.len())));
+        }
+
+        for ((arg, param), param_span) in args.iter().zip(params.iter()).zip(param_spans) {
+            self.check(arg, param, Some(param_span))?;
+        }
+
+        self.table.insert_instance(span, instance);
+        Ok(ret)
+    }
+
+    /// Calls the function value `callee` evaluates to, e.g. a variable
+    /// holding a `fn`. A callee whose type isn't known yet, like a
+    /// parameter, gets the function type the call implies.
+    fn infer_closure_call(
+        &mut self,
+        callee: &Expr<'a>,
+        args: &[Expr<'a>],
+        span: Span,
+    ) -> Result<Type, Diagnostic> {
+        let callee_type = self.infer(callee)?;
+
+        let (params, ret) = match self.unifier.resolve(&callee_type) {
+            Type::Fn(params, ret) => (params, *ret),
+            ty => {
+                let params: Vec<Type> = args
+                    .iter()
+                    .map(|_| self.unifier.fresh(Constraint::Any))
+                    .collect();
+                let ret = self.unifier.fresh(Constraint::Any);
+                let fn_type = Type::Fn(params.clone(), Box::new(ret.clone()));
+
+                if self.unifier.unify(&ty, &fn_type).is_err() {
+                    let ty = self.unifier.describe(&ty);
+                    return Err(Diagnostic::error(
+                        "E0309",
+                        format!("cannot call a value of type `{}`", ty),
+                    )
+                    .with_primary(callee.span(), format!("this is of type `{}`", ty)));
+                }
+                (params, ret)
+            }
+        };
+
+        if args.len() != params.len() {
+            return Err(Diagnostic::error(
+                "E0304",
+                format!(
+                    "this function takes {} arguments but {} were given",
+                    params.len(),
+                    args.len()
+                ),
+            )
+            .with_primary(span, format!("expected {} arguments", params.len())));
+        }
+
+        for (arg, param) in args.iter().zip(params.iter()) {
+            self.check(arg, param, Some(callee.span()))?;
+        }
+
+        Ok(ret)
+    }
+
+    fn resolve_param(&mut self, param: &Param) -> Result<Type, Diagnostic> {
+        match &param.ty {
+            Some(annotation) => resolve_annotation(annotation),
+            None => Ok(self.unifier.fresh(Constraint::Any)),
+        }
+    }
+}
+
+fn resolve_annotation(annotation: &TypeAnnotation) -> Result<Type, Diagnostic> {
+    match Type::from_name(annotation.name) {
+        Some(ty) => Ok(ty),
+        None => Err(
+            Diagnostic::error("E0310", format!("unknown type `{}`", annotation.name))
+                .with_primary(annotation.span, "not a type")
+                .with_note(
+                    "the available types are `i8`, `i16`, `i32`, `i64`, `u8`, `u16`, `u32`, \
+                     `u64`, `f32`, `f64`, `bool`, `str` and `unit`",
+                ),
+        ),
+    }
+}
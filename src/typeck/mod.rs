@@ -0,0 +1,11 @@
+mod checker;
+mod order;
+mod scopes;
+mod types;
+mod unify;
+
+pub use checker::*;
+pub use order::*;
+pub use scopes::*;
+pub use types::*;
+pub use unify::*;
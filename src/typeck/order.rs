@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use crate::tispc_parser::{captured_names, Expr};
+
+/// defn_order
+///
+/// Indexes of the `defn`s among `expression_tree`, ordered so that a
+/// function comes after the functions it calls. Functions that call
+/// each other, directly or through others, can't be ordered and come
+/// out next to each other, in the order they are defined.
+pub fn defn_order<'a>(expression_tree: &[Expr<'a>]) -> Vec<usize> {
+    let mut defns: Vec<usize> = Vec::new();
+    let mut nodes: HashMap<&'a str, usize> = HashMap::new();
+    for (index, expression) in expression_tree.iter().enumerate() {
+        if let Expr::Defn { name, .. } = expression {
+            // a second definition is an error reported elsewhere
+            nodes.entry(name).or_insert(defns.len());
+            defns.push(index);
+        }
+    }
+
+    // functions only see their parameters, so any other name their
+    // body uses is a function
+    let calls: Vec<Vec<usize>> = defns
+        .iter()
+        .map(|index| match &expression_tree[*index] {
+            Expr::Defn { params, body, .. } => captured_names(params, body)
+                .iter()
+                .filter_map(|name| nodes.get(name).copied())
+                .collect(),
+            _ => unreachable!("only defns are ordered"),
+        })
+        .collect();
+
+    let mut sorter = Sorter {
+        calls: &calls,
+        visits: vec![None; defns.len()],
+        low_links: vec![0; defns.len()],
+        stack: Vec::new(),
+        on_stack: vec![false; defns.len()],
+        order: Vec::new(),
+    };
+    for node in 0..defns.len() {
+        if sorter.visits[node].is_none() {
+            sorter.visit(node);
+        }
+    }
+
+    sorter.order.into_iter().map(|node| defns[node]).collect()
+}
+
+/// Tarjan's algorithm, which finds the groups of functions that call
+/// each other, callees first
+struct Sorter<'c> {
+    calls: &'c [Vec<usize>],
+    // when each function was first visited
+    visits: Vec<Option<usize>>,
+    // earliest visit reachable from each function
+    low_links: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    order: Vec<usize>,
+}
+
+impl<'c> Sorter<'c> {
+    fn visit(&mut self, node: usize) {
+        let visit = self.stack.len() + self.order.len();
+        self.visits[node] = Some(visit);
+        self.low_links[node] = visit;
+        self.stack.push(node);
+        self.on_stack[node] = true;
+
+        for callee in self.calls[node].iter() {
+            match self.visits[*callee] {
+                None => {
+                    self.visit(*callee);
+                    self.low_links[node] = self.low_links[node].min(self.low_links[*callee]);
+                }
+                Some(callee_visit) if self.on_stack[*callee] => {
+                    self.low_links[node] = self.low_links[node].min(callee_visit);
+                }
+                Some(_) => (),
+            }
+        }
+
+        // `node` is the first function visited of its group, which is
+        // everything above it on the stack
+        if Some(self.low_links[node]) == self.visits[node] {
+            let start = self.stack.iter().rposition(|n| *n == node).unwrap();
+            let mut group = self.stack.split_off(start);
+            for member in group.iter() {
+                self.on_stack[*member] = false;
+            }
+            group.sort_unstable();
+            self.order.extend(group);
+        }
+    }
+}
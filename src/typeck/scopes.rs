@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+/// Scopes
+///
+/// Stack of nested scopes mapping variable names to `T`, with the
+/// top-level scope at the bottom. A name is looked up from the
+/// innermost scope outwards, so a variable shadows the variables of
+/// the same name in enclosing scopes, and earlier ones in its own.
+#[derive(Debug, Clone)]
+pub struct Scopes<'a, T> {
+    scopes: Vec<HashMap<&'a str, T>>,
+}
+
+impl<'a, T> Scopes<'a, T> {
+    pub fn new() -> Scopes<'a, T> {
+        Scopes {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    /// Enters a new innermost scope
+    pub fn push(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Leaves the innermost scope, dropping its variables
+    pub fn pop(&mut self) {
+        assert!(self.scopes.len() > 1, "cannot leave the top-level scope");
+        self.scopes.pop();
+    }
+
+    /// Declares a variable in the innermost scope
+    pub fn declare(&mut self, name: &'a str, value: T) {
+        self.scopes.last_mut().unwrap().insert(name, value);
+    }
+
+    /// Number of scopes, counting the top-level one
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    /// Position of the innermost scope declaring `name`, counting
+    /// from the top-level scope at 0
+    pub fn depth_of(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rposition(|scope| scope.contains_key(name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+}
+
+impl<'a, T> Default for Scopes<'a, T> {
+    fn default() -> Scopes<'a, T> {
+        Scopes::new()
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::tispc_lexer::Span;
+
+/// Identifies a type variable created during inference
+pub type TypeVar = u32;
+
+/// Types to replace type variables with, e.g. the types a generic
+/// function is instantiated with
+pub type Substitution = HashMap<TypeVar, Type>;
+
+/// Type of a Tisp value as seen by the type checker
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Bool,
+    Str,
+    // value of forms run only for their effect (`while`, `print`, `defn`)
+    Unit,
+    Fn(Vec<Type>, Box<Type>),
+    // type that is not known yet during inference, or a type
+    // parameter of a generic function once inference is done
+    Var(TypeVar),
+}
+
+impl Type {
+    /// Resolves the name used in a type annotation
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "i8" => Some(Type::I8),
+            "i16" => Some(Type::I16),
+            "i32" => Some(Type::I32),
+            "i64" => Some(Type::I64),
+            "u8" => Some(Type::U8),
+            "u16" => Some(Type::U16),
+            "u32" => Some(Type::U32),
+            "u64" => Some(Type::U64),
+            "f32" => Some(Type::F32),
+            "f64" => Some(Type::F64),
+            "bool" => Some(Type::Bool),
+            "str" => Some(Type::Str),
+            "unit" => Some(Type::Unit),
+            _ => None,
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.is_signed() || matches!(self, Type::U8 | Type::U16 | Type::U32 | Type::U64)
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::I8 | Type::I16 | Type::I32 | Type::I64)
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::F32 | Type::F64)
+    }
+
+    /// Whether values of the type can be compared with `=` and `!=`
+    pub fn is_equatable(&self) -> bool {
+        self.is_numeric() || *self == Type::Bool
+    }
+
+    pub fn is_printable(&self) -> bool {
+        self.is_equatable() || *self == Type::Str
+    }
+
+    /// Size of a numeric type in bits
+    pub fn bit_width(&self) -> Option<u32> {
+        match self {
+            Type::I8 | Type::U8 => Some(8),
+            Type::I16 | Type::U16 => Some(16),
+            Type::I32 | Type::U32 | Type::F32 => Some(32),
+            Type::I64 | Type::U64 | Type::F64 => Some(64),
+            _ => None,
+        }
+    }
+
+    /// Smallest and largest value of an integer type
+    pub fn int_bounds(&self) -> Option<(i128, i128)> {
+        if !self.is_integer() {
+            return None;
+        }
+
+        let bits = self.bit_width()?;
+        if self.is_signed() {
+            Some((-(1 << (bits - 1)), (1 << (bits - 1)) - 1))
+        } else {
+            Some((0, (1 << bits) - 1))
+        }
+    }
+
+    /// Whether the type still mentions type variables, i.e. belongs
+    /// to a generic function
+    pub fn has_vars(&self) -> bool {
+        match self {
+            Type::Var(_) => true,
+            Type::Fn(params, ret) => params.iter().any(Type::has_vars) || ret.has_vars(),
+            _ => false,
+        }
+    }
+
+    /// Type variables in order of their first appearance
+    pub fn vars(&self) -> Vec<TypeVar> {
+        let mut vars = Vec::new();
+        self.collect_vars(&mut vars);
+        vars
+    }
+
+    fn collect_vars(&self, vars: &mut Vec<TypeVar>) {
+        match self {
+            Type::Var(var) if !vars.contains(var) => vars.push(*var),
+            Type::Fn(params, ret) => {
+                for param in params {
+                    param.collect_vars(vars);
+                }
+                ret.collect_vars(vars);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn substitute(&self, substitution: &Substitution) -> Type {
+        match self {
+            Type::Var(var) => match substitution.get(var) {
+                Some(ty) => ty.clone(),
+                None => self.clone(),
+            },
+            Type::Fn(params, ret) => Type::Fn(
+                params
+                    .iter()
+                    .map(|param| param.substitute(substitution))
+                    .collect(),
+                Box::new(ret.substitute(substitution)),
+            ),
+            _ => self.clone(),
+        }
+    }
+
+    /// Records in `substitution` the types that the variables of
+    /// `self` take in `instance`, a copy of `self` with (some of)
+    /// its variables replaced
+    pub fn bind_vars(&self, instance: &Type, substitution: &mut Substitution) {
+        match (self, instance) {
+            (Type::Var(var), _) => {
+                substitution.insert(*var, instance.clone());
+            }
+            (Type::Fn(params, ret), Type::Fn(instance_params, instance_ret)) => {
+                for (param, instance_param) in params.iter().zip(instance_params) {
+                    param.bind_vars(instance_param, substitution);
+                }
+                ret.bind_vars(instance_ret, substitution);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::U8 => write!(f, "u8"),
+            Type::U16 => write!(f, "u16"),
+            Type::U32 => write!(f, "u32"),
+            Type::U64 => write!(f, "u64"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+            Type::Unit => write!(f, "unit"),
+            Type::Fn(params, ret) => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -> {}", ret)
+            }
+            Type::Var(var) => write!(f, "t{}", var),
+        }
+    }
+}
+
+/// TypeTable
+///
+/// Result of type checking: the type of every checked expression,
+/// keyed by the expression's span. For `let` this is the type of
+/// the variable, and for `defn` the type of the function, which
+/// contains type variables if the function is generic.
+///
+/// Every call of a user-defined function also records the type the
+/// function was instantiated with at that call.
+#[derive(Debug, Default, Clone)]
+pub struct TypeTable {
+    types: HashMap<Span, Type>,
+    instances: HashMap<Span, Type>,
+}
+
+impl TypeTable {
+    pub fn new() -> TypeTable {
+        TypeTable {
+            types: HashMap::new(),
+            instances: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, span: Span, ty: Type) {
+        self.types.insert(span, ty);
+    }
+
+    pub fn get(&self, span: Span) -> Option<&Type> {
+        self.types.get(&span)
+    }
+
+    pub fn insert_instance(&mut self, call_span: Span, ty: Type) {
+        self.instances.insert(call_span, ty);
+    }
+
+    /// Type of the function called by the call at `call_span`
+    pub fn instance(&self, call_span: Span) -> Option<&Type> {
+        self.instances.get(&call_span)
+    }
+
+    /// Adds the types recorded in `other`
+    pub fn extend(&mut self, other: TypeTable) {
+        self.types.extend(other.types);
+        self.instances.extend(other.instances);
+    }
+
+    /// Replaces every recorded type with `f(type)`
+    pub fn map(&mut self, f: impl Fn(&Type) -> Type) {
+        for ty in self.types.values_mut().chain(self.instances.values_mut()) {
+            *ty = f(ty);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+
+use super::types::{Type, TypeVar};
+
+/// Restriction on the types a type variable can stand for. Each
+/// constraint allows a subset of the types allowed by the ones
+/// before it, e.g. numbers can be compared and printed, except that
+/// `Integer` and `Float` split the numbers between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Constraint {
+    Any,
+    Printable,
+    Equatable,
+    Numeric,
+    Integer,
+    Float,
+}
+
+impl Constraint {
+    pub fn allows(self, ty: &Type) -> bool {
+        match self {
+            Constraint::Any => true,
+            Constraint::Printable => ty.is_printable(),
+            Constraint::Equatable => ty.is_equatable(),
+            Constraint::Numeric => ty.is_numeric(),
+            Constraint::Integer => ty.is_integer(),
+            Constraint::Float => ty.is_float(),
+        }
+    }
+
+    /// Constraint allowing only the types both `self` and `other`
+    /// allow, if there are any
+    pub fn meet(self, other: Constraint) -> Option<Constraint> {
+        match (self, other) {
+            (Constraint::Integer, Constraint::Float) | (Constraint::Float, Constraint::Integer) => {
+                None
+            }
+            (Constraint::Integer, _) | (_, Constraint::Integer) => Some(Constraint::Integer),
+            (Constraint::Float, _) | (_, Constraint::Float) => Some(Constraint::Float),
+            (Constraint::Numeric, _) | (_, Constraint::Numeric) => Some(Constraint::Numeric),
+            (Constraint::Equatable, _) | (_, Constraint::Equatable) => Some(Constraint::Equatable),
+            (Constraint::Printable, _) | (_, Constraint::Printable) => Some(Constraint::Printable),
+            (Constraint::Any, Constraint::Any) => Some(Constraint::Any),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Binding {
+    Unbound(Constraint),
+    Bound(Type),
+}
+
+/// Error of unification: two types don't fit together, or a type
+/// doesn't meet a constraint. The checker knows where they came from,
+/// so it's the one to explain the mismatch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mismatch;
+
+/// Unifier
+///
+/// Holds the type variables created during inference and the types
+/// they have been bound to by unification.
+#[derive(Debug, Default, Clone)]
+pub struct Unifier {
+    bindings: Vec<Binding>,
+}
+
+impl Unifier {
+    pub fn new() -> Unifier {
+        Unifier {
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Creates a new type variable
+    pub fn fresh(&mut self, constraint: Constraint) -> Type {
+        self.bindings.push(Binding::Unbound(constraint));
+        Type::Var(self.bindings.len() as TypeVar - 1)
+    }
+
+    /// Constraint of a variable that is not bound yet
+    pub fn constraint(&self, var: TypeVar) -> Constraint {
+        match &self.bindings[var as usize] {
+            Binding::Unbound(constraint) => *constraint,
+            Binding::Bound(_) => Constraint::Any,
+        }
+    }
+
+    /// Follows the bindings of `ty` until its outermost type is known
+    /// or is an unbound variable
+    fn shallow(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(var) = ty {
+            match &self.bindings[var as usize] {
+                Binding::Bound(bound) => ty = bound.clone(),
+                Binding::Unbound(_) => break,
+            }
+        }
+        ty
+    }
+
+    /// Replaces every bound variable in `ty` with its type
+    pub fn resolve(&self, ty: &Type) -> Type {
+        match self.shallow(ty) {
+            Type::Fn(params, ret) => Type::Fn(
+                params.iter().map(|param| self.resolve(param)).collect(),
+                Box::new(self.resolve(&ret)),
+            ),
+            ty => ty,
+        }
+    }
+
+    /// Makes `a` and `b` the same type, binding variables as needed.
+    /// Fails if the types have different shapes.
+    pub fn unify(&mut self, a: &Type, b: &Type) -> Result<(), Mismatch> {
+        match (self.shallow(a), self.shallow(b)) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => self.bind(var, ty),
+            (Type::Fn(a_params, a_ret), Type::Fn(b_params, b_ret)) => {
+                if a_params.len() != b_params.len() {
+                    return Err(Mismatch);
+                }
+                for (a_param, b_param) in a_params.iter().zip(b_params.iter()) {
+                    self.unify(a_param, b_param)?;
+                }
+                self.unify(&a_ret, &b_ret)
+            }
+            (a, b) if a == b => Ok(()),
+            _ => Err(Mismatch),
+        }
+    }
+
+    /// Restricts `ty` to the types allowed by `constraint`
+    pub fn require(&mut self, ty: &Type, constraint: Constraint) -> Result<(), Mismatch> {
+        match self.shallow(ty) {
+            Type::Var(var) => {
+                let merged = self.constraint(var).meet(constraint).ok_or(Mismatch)?;
+                self.bindings[var as usize] = Binding::Unbound(merged);
+                Ok(())
+            }
+            ty if constraint.allows(&ty) => Ok(()),
+            _ => Err(Mismatch),
+        }
+    }
+
+    fn bind(&mut self, var: TypeVar, ty: Type) -> Result<(), Mismatch> {
+        // a variable can't stand for a type containing itself
+        if self.resolve(&ty).vars().contains(&var) {
+            return Err(Mismatch);
+        }
+
+        self.require(&ty, self.constraint(var))?;
+        self.bindings[var as usize] = Binding::Bound(ty);
+        Ok(())
+    }
+
+    /// Unbound variables of `ty`
+    pub fn free_vars(&self, ty: &Type) -> Vec<TypeVar> {
+        self.resolve(ty).vars()
+    }
+
+    /// Binds every variable left unbound after inference, except the
+    /// type parameters of generic functions: floats default to `f64`,
+    /// other numbers to `i64` and anything else to `unit`
+    pub fn apply_defaults(&mut self, type_params: &HashSet<TypeVar>) {
+        for var in 0..self.bindings.len() {
+            if type_params.contains(&(var as TypeVar)) {
+                continue;
+            }
+            if let Binding::Unbound(constraint) = self.bindings[var] {
+                self.bindings[var] = Binding::Bound(match constraint {
+                    Constraint::Any => Type::Unit,
+                    Constraint::Float => Type::F64,
+                    Constraint::Printable
+                    | Constraint::Equatable
+                    | Constraint::Numeric
+                    | Constraint::Integer => Type::I64,
+                });
+            }
+        }
+    }
+
+    /// Renders `ty` for a diagnostic, naming its variables `'a`, `'b`,
+    /// ... and showing numbers of unknown type as `{number}`,
+    /// `{integer}` or `{float}`
+    pub fn describe(&self, ty: &Type) -> String {
+        let mut names = Vec::new();
+        self.describe_with(&self.resolve(ty), &mut names)
+    }
+
+    fn describe_with(&self, ty: &Type, names: &mut Vec<TypeVar>) -> String {
+        match ty {
+            Type::Var(var) if self.constraint(*var) == Constraint::Numeric => {
+                "{number}".to_string()
+            }
+            Type::Var(var) if self.constraint(*var) == Constraint::Integer => {
+                "{integer}".to_string()
+            }
+            Type::Var(var) if self.constraint(*var) == Constraint::Float => "{float}".to_string(),
+            Type::Var(var) => {
+                let index = match names.iter().position(|name| name == var) {
+                    Some(index) => index,
+                    None => {
+                        names.push(*var);
+                        names.len() - 1
+                    }
+                };
+                match index {
+                    0..=25 => format!("'{}", (b'a' + index as u8) as char),
+                    _ => format!("'t{}", index),
+                }
+            }
+            Type::Fn(params, ret) => {
+                let params: Vec<String> = params
+                    .iter()
+                    .map(|param| self.describe_with(param, names))
+                    .collect();
+                format!(
+                    "fn({}) -> {}",
+                    params.join(", "),
+                    self.describe_with(ret, names)
+                )
+            }
+            ty => ty.to_string(),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! common
+//!
+//! Helpers for the tests that run the `tispc` binary on programs of
+//! their own. Every test binary uses a different part of them.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+/// Program
+///
+/// A source file written to a temporary directory of its own, next to
+/// the outputs `tispc` makes from it. The directory is removed when
+/// the program is dropped.
+pub struct Program {
+    dir: PathBuf,
+    pub path: PathBuf,
+}
+
+impl Program {
+    pub fn new(name: &str, source: &str) -> Program {
+        // test binaries run in processes of their own, and tests in
+        // threads of the same one
+        let dir = env::temp_dir().join(format!("tispc-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.tp", name));
+        fs::write(&path, source).unwrap();
+
+        Program { dir, path }
+    }
+
+    /// Path of a file next to the program, e.g. for `-o`
+    pub fn sibling(&self, file_name: &str) -> PathBuf {
+        self.dir.join(file_name)
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The `tispc` binary being tested
+pub fn tispc() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_tispc"))
+}
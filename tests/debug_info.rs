@@ -0,0 +1,63 @@
+//! debug_info
+//!
+//! Tests of the DWARF debug info `-g` adds, through the LLVM IR that
+//! `--emit=llvm-ir` writes.
+
+#![cfg(feature = "llvm")]
+
+mod common;
+
+use std::fs;
+
+use common::{tispc, Program};
+
+const SOURCE: &str = r#"
+(defn square (x: i64) -> i64
+  (* x x))
+
+(let total (square 4))
+(print total)
+"#;
+
+#[test]
+fn describes_functions_and_variables() {
+    let ir = emit_ir("described", &["-g"]);
+
+    assert!(ir.contains("!llvm.dbg.cu"), "no compile unit:\n{}", ir);
+    assert!(ir.contains("distinct !DICompileUnit("));
+    assert!(ir.contains("!DISubprogram(name: \"main\""));
+    assert!(ir.contains("!DISubprogram(name: \"square\""));
+    assert!(ir.contains("!DILocalVariable(name: \"x\", arg: "));
+    assert!(ir.contains("!DILocalVariable(name: \"total\""));
+    assert!(ir.contains("call void @llvm.dbg.declare("));
+}
+
+#[test]
+fn no_debug_info_by_default() {
+    let ir = emit_ir("plain", &[]);
+
+    assert!(!ir.contains("DICompileUnit"));
+    assert!(!ir.contains("llvm.dbg.declare"));
+}
+
+fn emit_ir(name: &str, args: &[&str]) -> String {
+    let program = Program::new(name, SOURCE);
+    let output = program.sibling(&format!("{}.ll", name));
+
+    let result = tispc()
+        .arg("-i")
+        .arg(&program.path)
+        .arg("--emit=llvm-ir")
+        .args(args)
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .expect("could not run tispc");
+    assert!(
+        result.status.success(),
+        "tispc failed:\n{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+
+    fs::read_to_string(&output).expect("tispc did not write the IR")
+}
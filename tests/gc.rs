@@ -0,0 +1,120 @@
+//! gc
+//!
+//! Tests of the garbage collector compiled into programs, through the
+//! statistics they print when `TISP_GC_STATS` is set. The golden test
+//! `programs/garbage.tp` checks that collections don't free anything
+//! still in use; these check that they happen at all.
+
+#![cfg(feature = "llvm")]
+
+mod common;
+
+use std::process::{Command, Output};
+
+use common::{tispc, Program};
+
+const GC_STATS_VAR: &str = "TISP_GC_STATS";
+
+// keeps strings and a closure alive through a few megabytes of garbage
+const GARBAGE: &str = r#"
+(defn make_greeter (greeting: str)
+  (fn (name: str) (str-concat greeting ", " name)))
+
+(let greet (make_greeter (str-concat "Hello" "")))
+(let i 0)
+(while (< i 20000)
+  (greet (str-concat "garbage " "number " "one"))
+  (set! i (+ i 1)))
+(print (greet "world"))
+"#;
+
+#[test]
+fn collects_garbage() {
+    let output = run("collects", GARBAGE, &["--gc-stats"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, world ");
+
+    let (collections, freed) = stats(&output);
+    assert!(collections > 0, "no collections ran");
+    assert!(freed > 0, "collections freed nothing");
+}
+
+#[test]
+fn small_programs_never_collect() {
+    let output = run("small", r#"(print (str-concat "a" "b"))"#, &["--gc-stats"]);
+    assert!(output.status.success());
+    assert_eq!(stats(&output), (0, 0));
+}
+
+#[test]
+fn stats_are_off_by_default() {
+    let output = run("quiet", GARBAGE, &[]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn executables_check_for_stats_when_they_run() {
+    let program = Program::new("executable", GARBAGE);
+    let executable = program.sibling("executable");
+    let status = tispc()
+        .arg("-i")
+        .arg(&program.path)
+        .arg("-o")
+        .arg(&executable)
+        .status()
+        .expect("could not run tispc");
+    assert!(status.success());
+
+    let run = |stats_on: bool| {
+        let mut command = Command::new(&executable);
+        command.env_remove(GC_STATS_VAR);
+        if stats_on {
+            command.env(GC_STATS_VAR, "1");
+        }
+        command.output().expect("could not run the program")
+    };
+
+    let quiet = run(false);
+    assert!(quiet.status.success());
+    assert!(quiet.stderr.is_empty());
+
+    let (collections, _) = stats(&run(true));
+    assert!(collections > 0, "no collections ran");
+}
+
+#[test]
+fn interpreter_has_no_stats() {
+    let output = run("interpreted", GARBAGE, &["--interpret", "--gc-stats"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--gc-stats"));
+}
+
+fn run(name: &str, source: &str, args: &[&str]) -> Output {
+    let program = Program::new(name, source);
+    tispc()
+        .env_remove(GC_STATS_VAR)
+        .arg("run")
+        .args(args)
+        .arg(&program.path)
+        .output()
+        .expect("could not run tispc")
+}
+
+/// Collections and bytes freed, from the line the program prints
+fn stats(output: &Output) -> (u64, u64) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line = stderr
+        .lines()
+        .find(|line| line.starts_with("gc: "))
+        .unwrap_or_else(|| panic!("no statistics in stderr:\n{}", stderr));
+
+    let numbers: Vec<u64> = line
+        .split_whitespace()
+        .filter_map(|word| word.parse().ok())
+        .collect();
+    match numbers.as_slice() {
+        [collections, freed] => (*collections, *freed),
+        _ => panic!("malformed statistics: {}", line),
+    }
+}
@@ -0,0 +1,430 @@
+//! golden
+//!
+//! Golden-file tests for the compiler, run with `cargo test`.
+//!
+//! Every program in `tests/programs` is run with `tispc run` (with the
+//! JIT, and with the interpreter), and its exit code, output and
+//! diagnostics are compared with the `.expected` file next to it.
+//! Every file in `tests/syntax` has the tokens the lexer makes of it
+//! compared with a `.tokens` file, the expression tree the parser
+//! builds with a `.ast` file, and what `tispc fmt` makes of it with a
+//! `.formatted` file. Formatting it again must change nothing and keep
+//! every comment, and `tispc fmt --check` must only accept it once it
+//! is formatted.
+//!
+//! `cargo test --test golden -- --bless` writes what the compiler does
+//! now into the files instead, to create them for new tests or update
+//! them after an intended change. Any other argument only runs the
+//! tests whose name contains it.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use tispc::diagnostics::{render_diagnostics, Diagnostics};
+use tispc::formatter::{format_source, DEFAULT_WIDTH};
+use tispc::tispc_lexer::{get_token_stream, LineIndex, Token, TokenKind};
+use tispc::tispc_parser::generate_expression_tree;
+
+mod common;
+
+use common::{tispc, Program};
+
+fn main() {
+    let mut bless = false;
+    let mut filters = Vec::new();
+    for arg in env::args().skip(1) {
+        if arg == "--bless" {
+            bless = true;
+        } else if !arg.starts_with('-') {
+            filters.push(arg);
+        }
+        // other flags are meant for the default test harness
+    }
+
+    let mut suite = Suite {
+        bless,
+        filters,
+        passed: 0,
+        failed: Vec::new(),
+        blessed: 0,
+    };
+
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let programs = tests.join("programs");
+    for program in files_with_extension(&programs, "tp") {
+        for (mode, args) in modes() {
+            suite.run_program(&program, mode, args);
+        }
+    }
+
+    for file in files_with_extension(&tests.join("syntax"), "tp") {
+        let source = read(&file);
+        suite.compare(&file, "tokens", &tokens_snapshot(&file, &source));
+        suite.compare(&file, "ast", &ast_snapshot(&file, &source));
+        suite.check_formatting(&file, &source);
+    }
+
+    println!();
+    for name in &suite.failed {
+        println!("failed: {}", name);
+    }
+    println!(
+        "test result: {}. {} passed; {} failed; {} blessed",
+        if suite.failed.is_empty() {
+            "ok"
+        } else {
+            "FAILED"
+        },
+        suite.passed,
+        suite.failed.len(),
+        suite.blessed
+    );
+
+    if !suite.failed.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// The ways programs are run, with the arguments to `tispc` for each.
+/// Both have to give the same results.
+fn modes() -> Vec<(&'static str, &'static [&'static str])> {
+    let mut modes: Vec<(&str, &[&str])> = Vec::new();
+    if cfg!(feature = "llvm") {
+        modes.push(("jit", &["run"]));
+    }
+    if cfg!(feature = "interpreter") {
+        modes.push(("interpreter", &["run", "--interpret"]));
+    }
+    modes
+}
+
+struct Suite {
+    bless: bool,
+    filters: Vec<String>,
+    passed: usize,
+    failed: Vec<String>,
+    blessed: usize,
+}
+
+impl Suite {
+    fn selected(&self, name: &str) -> bool {
+        self.filters.is_empty() || self.filters.iter().any(|filter| name.contains(filter))
+    }
+
+    /// Runs `program` with `tispc` and compares what happened with its
+    /// `.expected` file
+    fn run_program(&mut self, program: &Path, mode: &str, args: &[&str]) {
+        let name = format!("{} ({})", test_name(program), mode);
+        if !self.selected(&name) {
+            return;
+        }
+
+        // diagnostics name the file as it is given to tispc, so they
+        // don't depend on where the repository is
+        let output = tispc()
+            .args(args)
+            .arg(program.file_name().unwrap())
+            .current_dir(program.parent().unwrap())
+            .output();
+        let output = match output {
+            Ok(output) => output,
+            Err(err) => {
+                println!("test {} ... FAILED", name);
+                println!("could not run tispc: {}", err);
+                self.failed.push(name);
+                return;
+            }
+        };
+
+        let mut actual = String::new();
+        match output.status.code() {
+            Some(code) => writeln!(actual, "exit code: {}", code).unwrap(),
+            None => writeln!(actual, "killed by a signal").unwrap(),
+        }
+        section(
+            &mut actual,
+            "stdout",
+            &String::from_utf8_lossy(&output.stdout),
+        );
+        section(
+            &mut actual,
+            "diagnostics",
+            &String::from_utf8_lossy(&output.stderr),
+        );
+
+        self.check(&name, &program.with_extension("expected"), &actual);
+    }
+
+    /// Compares a snapshot of `file` with the file next to it that has
+    /// the extension `kind`
+    fn compare(&mut self, file: &Path, kind: &str, actual: &str) {
+        let name = format!("{} ({})", test_name(file), kind);
+        if self.selected(&name) {
+            self.check(&name, &file.with_extension(kind), actual);
+        }
+    }
+
+    /// Compares the formatted `file` with its `.formatted` file, and
+    /// checks what has to hold for any file `tispc fmt` formats
+    fn check_formatting(&mut self, file: &Path, source: &str) {
+        let formatted = match format_source(source, DEFAULT_WIDTH) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                let mut snapshot = String::new();
+                write_diagnostics(&mut snapshot, file, source, &diagnostics);
+                self.compare(file, "formatted", &snapshot);
+                return;
+            }
+        };
+        self.compare(file, "formatted", &formatted);
+
+        let name = format!("{} (fmt)", test_name(file));
+        if self.selected(&name) {
+            let problems = formatting_problems(file, source, &formatted);
+            self.verify(&name, &problems);
+        }
+    }
+
+    /// Passes the test `name` if nothing is in `problems`. These tests
+    /// have no file to bless.
+    fn verify(&mut self, name: &str, problems: &[String]) {
+        if problems.is_empty() {
+            println!("test {} ... ok", name);
+            self.passed += 1;
+            return;
+        }
+
+        println!("test {} ... FAILED", name);
+        for problem in problems {
+            println!("{}", problem);
+        }
+        self.failed.push(name.to_string());
+    }
+
+    fn check(&mut self, name: &str, expected_path: &Path, actual: &str) {
+        let expected = fs::read_to_string(expected_path).ok();
+        if expected.as_deref() == Some(actual) {
+            println!("test {} ... ok", name);
+            self.passed += 1;
+            return;
+        }
+
+        if self.bless {
+            fs::write(expected_path, actual).unwrap_or_else(|err| {
+                panic!("could not write {}: {}", expected_path.display(), err)
+            });
+            println!("test {} ... blessed", name);
+            self.blessed += 1;
+            return;
+        }
+
+        println!("test {} ... FAILED", name);
+        match expected {
+            Some(expected) => {
+                println!(
+                    "{} differs (- expected, + actual):",
+                    expected_path.display()
+                );
+                print!("{}", diff(&expected, actual));
+            }
+            None => println!(
+                "{} is missing; run with --bless to create it",
+                expected_path.display()
+            ),
+        }
+        self.failed.push(name.to_string());
+    }
+}
+
+/// The tokens of `source`, one per line with its position, followed by
+/// any errors of the lexer
+fn tokens_snapshot(file: &Path, source: &str) -> String {
+    let mut diagnostics = Diagnostics::new();
+    let token_stream = get_token_stream(source, &mut diagnostics);
+
+    let index = LineIndex::new(source);
+    let mut snapshot = String::new();
+    for token in &token_stream {
+        write_token(&mut snapshot, &index, token);
+    }
+    write_diagnostics(&mut snapshot, file, source, &diagnostics);
+
+    snapshot
+}
+
+fn write_token(snapshot: &mut String, index: &LineIndex, token: &Token) {
+    let start = index.line_col(token.span.start);
+    write!(snapshot, "{}:{} {:?}", start.line, start.col, token.kind).unwrap();
+    if let Some(value) = &token.value {
+        write!(snapshot, " {:?}", value).unwrap();
+    }
+    snapshot.push('\n');
+}
+
+/// The expression tree of `source`, one top-level expression after the
+/// other, followed by any errors of the lexer and parser
+fn ast_snapshot(file: &Path, source: &str) -> String {
+    let mut diagnostics = Diagnostics::new();
+    let token_stream = get_token_stream(source, &mut diagnostics);
+    let expression_tree = generate_expression_tree(token_stream, &mut diagnostics);
+
+    let mut snapshot = String::new();
+    for expression in &expression_tree {
+        writeln!(snapshot, "{:#?}", expression).unwrap();
+    }
+    write_diagnostics(&mut snapshot, file, source, &diagnostics);
+
+    snapshot
+}
+
+/// What is wrong with `formatted`, the formatted `source` of `file`
+fn formatting_problems(file: &Path, source: &str, formatted: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    match format_source(formatted, DEFAULT_WIDTH) {
+        Ok(again) if again == formatted => {}
+        Ok(again) => problems.push(format!(
+            "formatting again changes it (- once, + twice):\n{}",
+            diff(formatted, &again)
+        )),
+        Err(_) => problems.push("the formatted code has errors".to_string()),
+    }
+
+    let before = comments(source);
+    let after = comments(formatted);
+    if before != after {
+        problems.push(format!(
+            "the comments changed (- before, + after):\n{}",
+            diff(&before, &after)
+        ));
+    }
+
+    let stem = file.file_stem().unwrap().to_string_lossy();
+    let checks = [
+        ("source", source, source == formatted),
+        ("formatted", formatted, true),
+    ];
+    for (kind, text, accepted) in checks.iter() {
+        let program = Program::new(&format!("fmt-{}-{}", kind, stem), text);
+        let status = tispc()
+            .args(["fmt", "--check"])
+            .arg(&program.path)
+            .output()
+            .expect("could not run tispc")
+            .status;
+        if status.success() != *accepted {
+            problems.push(format!(
+                "`tispc fmt --check` exits with {} on the {} code",
+                status, kind
+            ));
+        }
+    }
+
+    problems
+}
+
+/// The comments of `source` and the `#_` that comment out forms, one
+/// per line
+fn comments(source: &str) -> String {
+    let token_stream = get_token_stream(source, &mut Diagnostics::new());
+    let mut comments = String::new();
+    for token in &token_stream {
+        if token.kind.is_comment() || token.kind == TokenKind::Discard {
+            writeln!(comments, "{:?} {:?}", token.kind, token.value).unwrap();
+        }
+    }
+    comments
+}
+
+fn write_diagnostics(snapshot: &mut String, file: &Path, source: &str, diagnostics: &Diagnostics) {
+    if !diagnostics.is_empty() {
+        let filename = file.file_name().unwrap().to_string_lossy();
+        section(
+            snapshot,
+            "diagnostics",
+            &render_diagnostics(diagnostics, &filename, source),
+        );
+    }
+}
+
+/// Appends `text` under a `--- name` header, ending it with a line
+/// break if it has none. Empty sections other than stdout are left
+/// out.
+fn section(out: &mut String, name: &str, text: &str) {
+    if text.is_empty() && name != "stdout" {
+        return;
+    }
+
+    writeln!(out, "--- {}", name).unwrap();
+    out.push_str(text);
+    if !text.is_empty() && !text.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Line by line difference between `expected` and `actual`, marking
+/// lines only in `expected` with `-` and lines only in `actual` with
+/// `+`
+fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // common[i][j] is the length of the longest common subsequence of
+    // old[i..] and new[j..]
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            writeln!(out, "  {}", old[i]).unwrap();
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            writeln!(out, "- {}", old[i]).unwrap();
+            i += 1;
+        } else {
+            writeln!(out, "+ {}", new[j]).unwrap();
+            j += 1;
+        }
+    }
+
+    out
+}
+
+/// Files in `dir` with the given extension, sorted by name
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let entries =
+        fs::read_dir(dir).unwrap_or_else(|err| panic!("could not read {}: {}", dir.display(), err));
+
+    let mut files: Vec<PathBuf> = entries
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| matches!(path.extension(), Some(ext) if ext == extension))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Name of a test file relative to `tests`, e.g. `programs/fib.tp`
+fn test_name(file: &Path) -> String {
+    let dir = file.parent().unwrap().file_name().unwrap();
+    let name = file.file_name().unwrap();
+    format!("{}/{}", dir.to_string_lossy(), name.to_string_lossy())
+}
+
+fn read(file: &Path) -> String {
+    fs::read_to_string(file)
+        .unwrap_or_else(|err| panic!("could not read {}: {}", file.display(), err))
+}
@@ -0,0 +1,81 @@
+//! lsp
+//!
+//! Tests of what the language server works out about a document,
+//! without an editor on the other end.
+
+use tispc::lsp::{analyze, DefinitionKind};
+use tispc::tispc_lexer::{LineCol, LineIndex};
+
+const SOURCE: &str = "(defn id (x) x)
+(defn add (a b) (+ a b))
+(let total (add 1 2))
+(print (id total) (id \"s\"))
+";
+
+/// Byte offset of the `nth` (from 0) occurrence of `needle`
+fn find(needle: &str, nth: usize) -> usize {
+    SOURCE.match_indices(needle).nth(nth).unwrap().0
+}
+
+#[test]
+fn definition_of_a_use() {
+    let analysis = analyze(SOURCE);
+    assert!(analysis.diagnostics.is_empty());
+
+    let definition = analysis.definition_at(find("total", 1)).unwrap();
+    assert_eq!(definition.name, "total");
+    assert_eq!(definition.kind, DefinitionKind::Variable);
+    assert_eq!(definition.span.start, find("total", 0));
+
+    let definition = analysis.definition_at(find("add", 1) + 1).unwrap();
+    assert_eq!(definition.kind, DefinitionKind::Function);
+    assert_eq!(definition.span.start, find("add", 0));
+}
+
+#[test]
+fn references_include_the_definition() {
+    let analysis = analyze(SOURCE);
+    let definition = analysis.definition_at(find("id", 0)).unwrap();
+
+    let starts: Vec<usize> = analysis
+        .references(definition)
+        .iter()
+        .map(|span| span.start)
+        .collect();
+    assert_eq!(
+        starts,
+        vec![find("id", 0), find("(id", 0) + 1, find("(id", 1) + 1]
+    );
+}
+
+#[test]
+fn hover_names_type_variables() {
+    let analysis = analyze(SOURCE);
+
+    let (span, text) = analysis.hover_at(find("id", 0)).unwrap();
+    assert_eq!(span.start, find("id", 0));
+    assert_eq!(text, "id: fn('a) -> 'a");
+
+    let (_, text) = analysis.hover_at(find("add", 0)).unwrap();
+    assert_eq!(text, "add: fn({number}, {number}) -> {number}");
+
+    let (_, text) = analysis.hover_at(find("total", 0)).unwrap();
+    assert_eq!(text, "total: i64");
+}
+
+#[test]
+fn utf16_positions_skip_crlf() {
+    let index = LineIndex::new("(let s \"é😀\")\r\n(print s)\r\n");
+
+    assert_eq!(index.utf16_line_length(1), 13);
+    assert_eq!(index.utf16_line_length(2), 9);
+    // past the end of the first line is its end, not its `\r`
+    assert_eq!(index.utf16_offset(LineCol { line: 1, col: 40 }), 16);
+    assert_eq!(index.utf16_offset(LineCol { line: 2, col: 2 }), 19);
+    assert_eq!(index.utf16_offset(LineCol { line: 9, col: 1 }), 29);
+
+    // the emoji takes two UTF-16 units, but four bytes
+    assert_eq!(index.utf16_line_col(10), LineCol { line: 1, col: 10 });
+    assert_eq!(index.utf16_line_col(14), LineCol { line: 1, col: 12 });
+    assert_eq!(index.utf16_line_col(18), LineCol { line: 2, col: 1 });
+}
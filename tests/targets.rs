@@ -0,0 +1,72 @@
+//! targets
+//!
+//! Tests of compiling for other machines with `--target`, and of
+//! listing the targets LLVM supports with `--print targets`.
+
+#![cfg(feature = "llvm")]
+
+mod common;
+
+use std::env;
+use std::fs;
+
+use common::{tispc, Program};
+
+// `e_machine` of 64-bit ARM in ELF headers
+const EM_AARCH64: u16 = 183;
+
+#[test]
+fn object_for_another_machine() {
+    let program = Program::new("aarch64", "(print (+ 1 2))");
+    let object = program.sibling("aarch64.o");
+
+    let result = tispc()
+        .arg("-i")
+        .arg(&program.path)
+        .arg("--emit=obj")
+        .arg("--target")
+        .arg("aarch64-unknown-linux-gnu")
+        .arg("-o")
+        .arg(&object)
+        .output()
+        .expect("could not run tispc");
+    assert!(
+        result.status.success(),
+        "tispc failed:\n{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+
+    let bytes = fs::read(&object).expect("tispc did not write an object file");
+    assert_eq!(&bytes[..4], b"\x7fELF");
+    // 64-bit, little-endian
+    assert_eq!(&bytes[4..6], &[2, 1]);
+    assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), EM_AARCH64);
+}
+
+#[test]
+fn print_targets_lists_this_machine() {
+    let result = tispc()
+        .arg("--print")
+        .arg("targets")
+        .output()
+        .expect("could not run tispc");
+    assert!(result.status.success());
+
+    // LLVM names some architectures differently from Rust
+    let host = match env::consts::ARCH {
+        "x86_64" => "x86-64",
+        arch => arch,
+    };
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    let names: Vec<&str> = stdout
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+    assert!(
+        names.contains(&host),
+        "{} is not among the targets:\n{}",
+        host,
+        stdout
+    );
+}
@@ -0,0 +1,322 @@
+//! wasm
+//!
+//! Tests of the WebAssembly modules `tispc` makes with `--target
+//! wasm32-unknown-unknown`. The modules are parsed here to check that
+//! they only import the host functions of the `tisp` module, with the
+//! documented signatures, and that they export `main` and their
+//! memory.
+//!
+//! Most tests look at the object file of `--emit=obj`, which is a
+//! module too. Exports are only added when linking, which needs
+//! `wasm-ld` (or the linker in `WASM_LD`), so the test of them is
+//! ignored unless asked for with `cargo test -- --ignored`.
+
+#![cfg(feature = "llvm")]
+
+mod common;
+
+use std::fs;
+
+use common::{tispc, Program};
+
+// value types of the binary format
+const I32: u8 = 0x7f;
+const I64: u8 = 0x7e;
+const F64: u8 = 0x7c;
+
+// kinds of imports and exports
+const FUNC: u8 = 0;
+const TABLE: u8 = 1;
+const MEMORY: u8 = 2;
+const GLOBAL: u8 = 3;
+
+#[test]
+fn module_header() {
+    let bytes = compile("header", "(print 1)", "obj");
+
+    assert_eq!(&bytes[..4], b"\0asm");
+    assert_eq!(&bytes[4..8], &[1, 0, 0, 0]);
+
+    // sections other than custom ones come in the order of their ids
+    let ids: Vec<u8> = sections(&bytes)
+        .iter()
+        .map(|(id, _)| *id)
+        .filter(|id| *id != 0)
+        .collect();
+    let mut sorted = ids.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    assert_eq!(ids, sorted);
+}
+
+#[test]
+fn print_imports_host_functions() {
+    let source = r#"
+(let small: u8 200)
+(print -1 small 1.5 (f32 0.25) true "text")
+"#;
+    let module = Module::parse(&compile("print", source, "obj"));
+
+    // objects also import their memory and table, for the linker
+    let mut imports: Vec<(&str, &str, FuncType)> = module
+        .imports
+        .iter()
+        .filter(|import| import.kind == FUNC)
+        .map(|import| {
+            let ty = module.types[import.index as usize].clone();
+            (import.module.as_str(), import.name.as_str(), ty)
+        })
+        .collect();
+    imports.sort();
+
+    let void = Vec::new();
+    assert_eq!(
+        imports,
+        vec![
+            ("tisp", "print_f64", (vec![F64], void.clone())),
+            ("tisp", "print_i64", (vec![I64], void.clone())),
+            // strings are pointers into the memory
+            ("tisp", "print_str", (vec![I32], void.clone())),
+            ("tisp", "print_u64", (vec![I64], void)),
+        ]
+    );
+}
+
+#[test]
+#[ignore = "links with wasm-ld, which may not be installed"]
+fn exports_main_and_memory() {
+    let module = Module::parse(&compile("exports", "(print \"hi\")", "exe"));
+
+    let main = module
+        .exports
+        .iter()
+        .find(|export| export.name == "main")
+        .expect("main is not exported");
+    assert_eq!(main.kind, FUNC);
+    assert_eq!(module.func_type(main.index), (vec![I32], vec![I32]));
+
+    let memory = module.exports.iter().find(|export| export.name == "memory");
+    assert_eq!(memory.map(|export| export.kind), Some(MEMORY));
+}
+
+#[test]
+fn strings_need_no_c_library() {
+    let source = r#"
+(let greeting (str-concat "Hello, " "world"))
+(print greeting (str-len greeting) (substr greeting 7 5) (str= greeting "Hello"))
+"#;
+    let module = Module::parse(&compile("strings", source, "obj"));
+
+    for import in module.imports.iter().filter(|import| import.kind == FUNC) {
+        assert_eq!(
+            import.module, "tisp",
+            "{}.{} is not a host function",
+            import.module, import.name
+        );
+    }
+}
+
+/// Compiles `source` to WebAssembly as the `--emit` kind `emit`, an
+/// object file or a linked module, and returns its bytes
+fn compile(name: &str, source: &str, emit: &str) -> Vec<u8> {
+    let program = Program::new(name, source);
+    let output = program.sibling(&format!("{}.wasm", name));
+
+    let result = tispc()
+        .arg("-i")
+        .arg(&program.path)
+        .arg("--target")
+        .arg("wasm32-unknown-unknown")
+        .arg(format!("--emit={}", emit))
+        .arg("-o")
+        .arg(&output)
+        .output()
+        .expect("could not run tispc");
+    assert!(
+        result.status.success(),
+        "tispc failed:\n{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+
+    fs::read(&output).expect("tispc did not write a module")
+}
+
+/// Parameter and result types of a function
+type FuncType = (Vec<u8>, Vec<u8>);
+
+struct Import {
+    module: String,
+    name: String,
+    kind: u8,
+    // type of an imported function
+    index: u32,
+}
+
+struct Export {
+    name: String,
+    kind: u8,
+    index: u32,
+}
+
+/// The parts of a module the tests look at
+struct Module {
+    types: Vec<FuncType>,
+    imports: Vec<Import>,
+    // type of every function defined in the module
+    functions: Vec<u32>,
+    exports: Vec<Export>,
+}
+
+impl Module {
+    fn parse(bytes: &[u8]) -> Module {
+        let mut module = Module {
+            types: Vec::new(),
+            imports: Vec::new(),
+            functions: Vec::new(),
+            exports: Vec::new(),
+        };
+
+        for (id, contents) in sections(bytes) {
+            let mut reader = Reader::new(contents);
+            match id {
+                1 => {
+                    for _ in 0..reader.uleb() {
+                        assert_eq!(reader.byte(), 0x60, "not a function type");
+                        let params = reader.bytes_vec();
+                        let results = reader.bytes_vec();
+                        module.types.push((params, results));
+                    }
+                }
+                2 => {
+                    for _ in 0..reader.uleb() {
+                        let import_module = reader.name();
+                        let name = reader.name();
+                        let kind = reader.byte();
+                        let mut index = 0;
+                        match kind {
+                            FUNC => index = reader.uleb(),
+                            TABLE => {
+                                reader.byte();
+                                reader.limits();
+                            }
+                            MEMORY => reader.limits(),
+                            GLOBAL => {
+                                reader.byte();
+                                reader.byte();
+                            }
+                            _ => panic!("unknown import kind {}", kind),
+                        }
+                        module.imports.push(Import {
+                            module: import_module,
+                            name,
+                            kind,
+                            index,
+                        });
+                    }
+                }
+                3 => {
+                    for _ in 0..reader.uleb() {
+                        module.functions.push(reader.uleb());
+                    }
+                }
+                7 => {
+                    for _ in 0..reader.uleb() {
+                        let name = reader.name();
+                        let kind = reader.byte();
+                        let index = reader.uleb();
+                        module.exports.push(Export { name, kind, index });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        module
+    }
+
+    /// Type of the function at `index`, which counts imported functions
+    /// before the ones defined in the module
+    fn func_type(&self, index: u32) -> FuncType {
+        let imported: Vec<&Import> = self
+            .imports
+            .iter()
+            .filter(|import| import.kind == FUNC)
+            .collect();
+        let index = index as usize;
+        let type_index = match imported.get(index) {
+            Some(import) => import.index,
+            None => self.functions[index - imported.len()],
+        };
+
+        self.types[type_index as usize].clone()
+    }
+}
+
+/// Ids and contents of the sections of a module
+fn sections(bytes: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut reader = Reader::new(&bytes[8..]);
+    let mut sections = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.byte();
+        let size = reader.uleb() as usize;
+        sections.push((id, reader.take(size)));
+    }
+
+    sections
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, count: usize) -> &'a [u8] {
+        assert!(count <= self.bytes.len(), "module ends too early");
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        taken
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    /// Unsigned LEB128 number
+    fn uleb(&mut self) -> u32 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte();
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    fn bytes_vec(&mut self) -> Vec<u8> {
+        let count = self.uleb() as usize;
+        self.take(count).to_vec()
+    }
+
+    fn name(&mut self) -> String {
+        String::from_utf8(self.bytes_vec()).expect("names are UTF-8")
+    }
+
+    fn limits(&mut self) {
+        let has_max = self.byte() & 1 == 1;
+        self.uleb();
+        if has_max {
+            self.uleb();
+        }
+    }
+}